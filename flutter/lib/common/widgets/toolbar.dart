@@ -697,6 +697,11 @@ Future<List<TRadioMenu<String>>> toolbarImageQuality(
         value: kRemoteImageQualityLow,
         groupValue: groupValue,
         onChanged: onChanged),
+    TRadioMenu<String>(
+        child: Text(translate('Screen content')),
+        value: kRemoteImageQualityScreenContent,
+        groupValue: groupValue,
+        onChanged: onChanged),
     TRadioMenu<String>(
       child: Text(translate('Custom')),
       value: kRemoteImageQualityCustom,
@@ -909,6 +914,17 @@ Future<List<TToggleMenu>> toolbarDisplayToggle(
           bind.sessionToggleOption(sessionId: sessionId, value: option);
         },
         child: Text(translate('Mute'))));
+    // audio of screen recordings
+    final recordOption = 'disable-record-audio';
+    final recordValue = bind.sessionGetToggleOptionSync(
+        sessionId: sessionId, arg: recordOption);
+    v.add(TToggleMenu(
+        value: recordValue,
+        onChanged: (value) {
+          if (value == null) return;
+          bind.sessionToggleOption(sessionId: sessionId, value: recordOption);
+        },
+        child: Text(translate('Mute recordings'))));
   }
   // file copy and paste
   // If the version is less than 1.2.4, file copy and paste is supported on Windows only.
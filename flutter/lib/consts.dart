@@ -405,6 +405,9 @@ const kRemoteImageQualityBalanced = 'balanced';
 /// [kRemoteImageQualityLow] Low image quality, better performance.
 const kRemoteImageQualityLow = 'low';
 
+/// [kRemoteImageQualityScreenContent] Sharp text, I444 and near-lossless if the link allows.
+const kRemoteImageQualityScreenContent = 'screen';
+
 /// [kRemoteImageQualityCustom] Custom image quality.
 const kRemoteImageQualityCustom = 'custom';
 
@@ -1945,6 +1945,11 @@ class _DisplayState extends State<_Display> {
           groupValue: groupValue,
           label: 'Optimize reaction time',
           onChanged: isOptFixed ? null : onChanged),
+      _Radio(context,
+          value: kRemoteImageQualityScreenContent,
+          groupValue: groupValue,
+          label: 'Screen content',
+          onChanged: isOptFixed ? null : onChanged),
       _Radio(context,
           value: kRemoteImageQualityCustom,
           groupValue: groupValue,
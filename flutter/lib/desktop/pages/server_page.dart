@@ -355,7 +355,6 @@ Widget buildConnectionCard(Client client) {
         _CmHeader(client: client),
         client.type_() == ClientType.file ||
                 client.type_() == ClientType.portForward ||
-                client.type_() == ClientType.terminal ||
                 client.disconnected
             ? Offstage()
             : _PrivilegeBoard(client: client),
@@ -682,7 +681,26 @@ class _PrivilegeBoardState extends State<_PrivilegeBoard> {
               padding: EdgeInsets.symmetric(horizontal: spacing),
               mainAxisSpacing: spacing,
               crossAxisSpacing: spacing,
-              children: client.type_() == ClientType.camera
+              children: client.type_() == ClientType.terminal
+                  ? [
+                      // Observers of a shared terminal are read-only until granted input.
+                      buildPermissionIcon(
+                        client.terminalInput,
+                        Icons.keyboard,
+                        (enabled) {
+                          bind.cmSwitchPermission(
+                              connId: client.id,
+                              name: "terminal_input",
+                              enabled: enabled);
+                          setState(() {
+                            client.terminalInput = enabled;
+                          });
+                        },
+                        translate('Enable terminal input'),
+                        canModify: canModifyPermission,
+                      ),
+                    ]
+                  : client.type_() == ClientType.camera
                   ? [
                       buildPermissionIcon(
                         client.audio,
@@ -1225,6 +1225,7 @@ class __DisplayPageState extends State<_DisplayPage> {
                 _RadioEntry('Good image quality', kRemoteImageQualityBest),
                 _RadioEntry('Balanced', kRemoteImageQualityBalanced),
                 _RadioEntry('Optimize reaction time', kRemoteImageQualityLow),
+                _RadioEntry('Screen content', kRemoteImageQualityScreenContent),
                 _RadioEntry('Custom', kRemoteImageQualityCustom),
               ],
               getter: () {
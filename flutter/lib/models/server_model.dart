@@ -551,11 +551,13 @@ class ServerModel with ChangeNotifier {
         } else {
           if (_clients[index].authorized) {
             _clients[index].privacyMode = client.privacyMode;
+            _clients[index].terminalInput = client.terminalInput;
             notifyListeners();
             return;
           }
           _clients[index].authorized = true;
           _clients[index].privacyMode = client.privacyMode;
+          _clients[index].terminalInput = client.terminalInput;
         }
       } else {
         final index = _clients.indexWhere((c) => c.id == client.id);
@@ -828,6 +830,7 @@ class Client {
   bool recording = false;
   bool blockInput = false;
   bool privacyMode = false;
+  bool terminalInput = true;
   bool disconnected = false;
   bool fromSwitch = false;
   bool inVoiceCall = false;
@@ -857,6 +860,7 @@ class Client {
     recording = json['recording'];
     blockInput = json['block_input'];
     privacyMode = json['privacy_mode'] ?? privacyMode;
+    terminalInput = json['terminal_input'] ?? terminalInput;
     disconnected = json['disconnected'];
     fromSwitch = json['from_switch'];
     inVoiceCall = json['in_voice_call'];
@@ -882,6 +886,7 @@ class Client {
     data['recording'] = recording;
     data['block_input'] = blockInput;
     data['privacy_mode'] = privacyMode;
+    data['terminal_input'] = terminalInput;
     data['disconnected'] = disconnected;
     data['from_switch'] = fromSwitch;
     data['in_voice_call'] = inVoiceCall;
@@ -6,7 +6,7 @@
 
 include!(concat!(env!("OUT_DIR"), "/aom_ffi.rs"));
 
-use crate::codec::{base_bitrate, codec_thread_num};
+use crate::codec::{base_bitrate, codec_thread_num, near_lossless_q_max};
 use crate::{codec::EncoderApi, EncodeFrame, STRIDE_ALIGN};
 use crate::{common::GoogleImage, generate_call_macro, generate_call_ptr_macro, Error, Result};
 use crate::{EncodeInput, EncodeYuvFormat, Pixfmt};
@@ -47,6 +47,8 @@ pub struct AomEncoderConfig {
     pub height: u32,
     pub quality: f32,
     pub keyframe_interval: Option<usize>,
+    /// Tune for screen content, see `Quality::ScreenContent`
+    pub screen_content: bool,
 }
 
 pub struct AomEncoder {
@@ -54,6 +56,7 @@ pub struct AomEncoder {
     width: usize,
     height: usize,
     i444: bool,
+    screen_content: bool,
     yuvfmt: EncodeYuvFormat,
 }
 
@@ -114,7 +117,7 @@ mod webrtc {
         } else {
             c.kf_mode = aom_kf_mode::AOM_KF_DISABLED;
         }
-        let (q_min, q_max) = AomEncoder::calc_q_values(cfg.quality);
+        let (q_min, q_max) = AomEncoder::calc_q_values(cfg.quality, cfg.screen_content);
         c.rc_min_quantizer = q_min;
         c.rc_max_quantizer = q_max;
         c.rc_target_bitrate = AomEncoder::bitrate(cfg.width as _, cfg.height as _, cfg.quality);
@@ -136,7 +139,11 @@ mod webrtc {
         Ok(c)
     }
 
-    pub fn set_controls(ctx: *mut aom_codec_ctx_t, cfg: &aom_codec_enc_cfg) -> ResultType<()> {
+    pub fn set_controls(
+        ctx: *mut aom_codec_ctx_t,
+        cfg: &aom_codec_enc_cfg,
+        screen_content: bool,
+    ) -> ResultType<()> {
         use aom_tune_content::*;
         use aome_enc_control_id::*;
         macro_rules! call_ctl {
@@ -188,7 +195,8 @@ mod webrtc {
         call_ctl!(ctx, AV1E_SET_ENABLE_INTERINTRA_COMP, 0);
         call_ctl!(ctx, AV1E_SET_ENABLE_INTERINTRA_WEDGE, 0);
         call_ctl!(ctx, AV1E_SET_ENABLE_INTRA_EDGE_FILTER, 0);
-        call_ctl!(ctx, AV1E_SET_ENABLE_INTRABC, 0);
+        // Intra block copy helps repeated glyphs, the screen content profile affords its cost.
+        call_ctl!(ctx, AV1E_SET_ENABLE_INTRABC, screen_content as i32);
         call_ctl!(ctx, AV1E_SET_ENABLE_MASKED_COMP, 0);
         call_ctl!(ctx, AV1E_SET_ENABLE_PAETH_INTRA, 0);
         call_ctl!(ctx, AV1E_SET_ENABLE_QM, 0);
@@ -216,6 +224,16 @@ mod webrtc {
             assert_eq!(three_threads, 2);
             assert_eq!(max_threads, 6);
         }
+
+        #[test]
+        fn screen_content_goes_near_lossless_above_best() {
+            let best = AomEncoder::calc_q_values(2.0, false);
+            assert_eq!(AomEncoder::calc_q_values(2.0, true), best);
+            assert_eq!(AomEncoder::calc_q_values(3.0, false), best);
+            assert_eq!(AomEncoder::calc_q_values(3.0, true), (best.0, 10));
+            let q_max = AomEncoder::calc_q_values(2.5, true).1;
+            assert!(q_max > 10 && q_max < best.1);
+        }
     }
 }
 
@@ -239,12 +257,13 @@ impl EncoderApi for AomEncoder {
                     flags,
                     AOM_ENCODER_ABI_VERSION as _
                 ));
-                webrtc::set_controls(&mut ctx, &c)?;
+                webrtc::set_controls(&mut ctx, &c, config.screen_content)?;
                 Ok(Self {
                     ctx,
                     width: config.width as _,
                     height: config.height as _,
                     i444,
+                    screen_content: config.screen_content,
                     yuvfmt: Self::get_yuvfmt(config.width, config.height, i444),
                 })
             }
@@ -278,7 +297,7 @@ impl EncoderApi for AomEncoder {
 
     fn set_quality(&mut self, ratio: f32) -> ResultType<()> {
         let mut c = unsafe { *self.ctx.config.enc.to_owned() };
-        let (q_min, q_max) = Self::calc_q_values(ratio);
+        let (q_min, q_max) = Self::calc_q_values(ratio, self.screen_content);
         c.rc_min_quantizer = q_min;
         c.rc_max_quantizer = q_max;
         c.rc_target_bitrate = Self::bitrate(self.width as _, self.height as _, ratio);
@@ -370,7 +389,7 @@ impl AomEncoder {
     }
 
     #[inline]
-    fn calc_q_values(ratio: f32) -> (u32, u32) {
+    fn calc_q_values(ratio: f32, screen_content: bool) -> (u32, u32) {
         let b = (ratio * 100.0) as u32;
         let b = std::cmp::min(b, 200);
         let q_min1 = 24;
@@ -385,6 +404,9 @@ impl AomEncoder {
 
         q_min = q_min.clamp(q_min2, q_min1);
         q_max = q_max.clamp(q_max2, q_max1);
+        if screen_content {
+            q_max = near_lossless_q_max(ratio, q_max, 10);
+        }
 
         (q_min, q_max)
     }
@@ -9,6 +9,8 @@ use std::{
 use crate::hwcodec::*;
 #[cfg(feature = "mediacodec")]
 use crate::mediacodec::{MediaCodecDecoder, H264_DECODER_SUPPORT, H265_DECODER_SUPPORT};
+#[cfg(feature = "openh264")]
+use crate::openh264codec::{OpenH264Decoder, OpenH264Encoder, OpenH264EncoderConfig};
 #[cfg(feature = "vram")]
 use crate::vram::*;
 use crate::{
@@ -55,6 +57,8 @@ pub enum EncoderCfg {
     HWRAM(HwRamEncoderConfig),
     #[cfg(feature = "vram")]
     VRAM(VRamEncoderConfig),
+    #[cfg(feature = "openh264")]
+    OpenH264(OpenH264EncoderConfig),
 }
 
 pub trait EncoderApi {
@@ -116,6 +120,8 @@ pub struct Decoder {
     h264_media_codec: MediaCodecDecoder,
     #[cfg(feature = "mediacodec")]
     h265_media_codec: MediaCodecDecoder,
+    #[cfg(feature = "openh264")]
+    h264_openh264: Option<OpenH264Decoder>,
     format: CodecFormat,
     valid: bool,
     #[cfg(feature = "hwcodec")]
@@ -165,6 +171,10 @@ impl Encoder {
                     Err(e)
                 }
             },
+            #[cfg(feature = "openh264")]
+            EncoderCfg::OpenH264(_) => Ok(Encoder {
+                codec: Box::new(OpenH264Encoder::new(config, i444)?),
+            }),
         }
     }
 
@@ -230,8 +240,10 @@ impl Encoder {
                     HwRamEncoder::try_get(CodecFormat::H265).map_or(None, |c| Some(c.name));
             }
         }
-        let h264_useable =
-            _all_support_h264_decoding && (h264vram_encoding || h264hw_encoding.is_some());
+        // The software encoder is used when H264 is preferred, not picked automatically.
+        let h264sw_encoding = cfg!(feature = "openh264");
+        let h264_useable = _all_support_h264_decoding
+            && (h264vram_encoding || h264hw_encoding.is_some() || h264sw_encoding);
         let h265_useable =
             _all_support_h265_decoding && (h265vram_encoding || h265hw_encoding.is_some());
         let mut format = ENCODE_CODEC_FORMAT.lock().unwrap();
@@ -275,7 +287,7 @@ impl Encoder {
         } else {
             CodecFormat::VP9
         };
-        if h264_useable {
+        if _all_support_h264_decoding && (h264vram_encoding || h264hw_encoding.is_some()) {
             auto_codec = CodecFormat::H264;
         }
         if h265_useable {
@@ -295,7 +307,7 @@ impl Encoder {
             PreferCodec::VP9 => CodecFormat::VP9,
             PreferCodec::AV1 => CodecFormat::AV1,
             PreferCodec::H264 => {
-                if h264vram_encoding || h264hw_encoding.is_some() {
+                if h264vram_encoding || h264hw_encoding.is_some() || h264sw_encoding {
                     CodecFormat::H264
                 } else {
                     auto_codec
@@ -351,6 +363,10 @@ impl Encoder {
             encoding.h264 |= VRamEncoder::available(CodecFormat::H264).len() > 0;
             encoding.h265 |= VRamEncoder::available(CodecFormat::H265).len() > 0;
         }
+        #[cfg(feature = "openh264")]
+        {
+            encoding.h264 = true;
+        }
         encoding
     }
 
@@ -392,6 +408,8 @@ impl Encoder {
                     return;
                 }
             },
+            #[cfg(feature = "openh264")]
+            EncoderCfg::OpenH264(_) => CodecFormat::H264,
         };
         let current = ENCODE_CODEC_FORMAT.lock().unwrap().clone();
         if current != format {
@@ -402,9 +420,20 @@ impl Encoder {
 
     pub fn use_i444(config: &EncoderCfg) -> bool {
         let decodings = PEER_DECODINGS.lock().unwrap().clone();
-        let prefer_i444 = decodings
-            .iter()
-            .all(|d| d.1.prefer_chroma == Chroma::I444.into());
+        let screen_content = matches!(
+            config,
+            EncoderCfg::VPX(VpxEncoderConfig {
+                screen_content: true,
+                ..
+            }) | EncoderCfg::AOM(AomEncoderConfig {
+                screen_content: true,
+                ..
+            })
+        );
+        let prefer_i444 = screen_content
+            || decodings
+                .iter()
+                .all(|d| d.1.prefer_chroma == Chroma::I444.into());
         let i444_useable = match config {
             EncoderCfg::VPX(vpx) => match vpx.codec {
                 VpxVideoCodecId::VP8 => false,
@@ -415,6 +444,8 @@ impl Encoder {
             EncoderCfg::HWRAM(_) => false,
             #[cfg(feature = "vram")]
             EncoderCfg::VRAM(_) => false,
+            #[cfg(feature = "openh264")]
+            EncoderCfg::OpenH264(_) => false,
         };
         prefer_i444 && i444_useable && !decodings.is_empty()
     }
@@ -485,6 +516,10 @@ impl Decoder {
                     0
                 };
         }
+        #[cfg(feature = "openh264")]
+        {
+            decoding.ability_h264 = 1;
+        }
         for unsupported in mark_unsupported {
             match unsupported {
                 CodecFormat::VP8 => decoding.ability_vp8 = 0,
@@ -507,6 +542,8 @@ impl Decoder {
         let (mut h264_vram, mut h265_vram) = (None, None);
         #[cfg(feature = "mediacodec")]
         let (mut h264_media_codec, mut h265_media_codec) = (None, None);
+        #[cfg(feature = "openh264")]
+        let mut h264_openh264 = None;
         let mut valid = false;
 
         match format {
@@ -560,6 +597,14 @@ impl Decoder {
                     }
                     valid = h264_media_codec.is_some();
                 }
+                #[cfg(feature = "openh264")]
+                if !valid {
+                    match OpenH264Decoder::new() {
+                        Ok(v) => h264_openh264 = Some(v),
+                        Err(e) => log::error!("create H264 openh264 decoder failed: {}", e),
+                    }
+                    valid = h264_openh264.is_some();
+                }
             }
             CodecFormat::H265 => {
                 #[cfg(feature = "vram")]
@@ -612,6 +657,8 @@ impl Decoder {
             h264_media_codec,
             #[cfg(feature = "mediacodec")]
             h265_media_codec,
+            #[cfg(feature = "openh264")]
+            h264_openh264,
             format,
             valid,
             #[cfg(feature = "hwcodec")]
@@ -658,7 +705,7 @@ impl Decoder {
                     bail!("av1 decoder not available");
                 }
             }
-            #[cfg(any(feature = "hwcodec", feature = "vram"))]
+            #[cfg(any(feature = "hwcodec", feature = "vram", feature = "openh264"))]
             video_frame::Union::H264s(h264s) => {
                 *chroma = Some(Chroma::I420);
                 #[cfg(feature = "vram")]
@@ -670,6 +717,10 @@ impl Decoder {
                 if let Some(decoder) = &mut self.h264_ram {
                     return Decoder::handle_hwram_video_frame(decoder, h264s, rgb, &mut self.i420);
                 }
+                #[cfg(feature = "openh264")]
+                if let Some(decoder) = &mut self.h264_openh264 {
+                    return Decoder::handle_openh264_video_frame(decoder, h264s, rgb);
+                }
                 Err(anyhow!("don't support h264!"))
             }
             #[cfg(any(feature = "hwcodec", feature = "vram"))]
@@ -802,6 +853,22 @@ impl Decoder {
         return Ok(ret);
     }
 
+    // rgb [in/out] fmt and stride must be set in ImageRgb
+    #[cfg(feature = "openh264")]
+    fn handle_openh264_video_frame(
+        decoder: &mut OpenH264Decoder,
+        frames: &EncodedVideoFrames,
+        rgb: &mut ImageRgb,
+    ) -> ResultType<bool> {
+        let mut ret = false;
+        for h264 in frames.frames.iter() {
+            if decoder.decode(&h264.data, rgb)? {
+                ret = true;
+            }
+        }
+        Ok(ret)
+    }
+
     // rgb [in/out] fmt and stride must be set in ImageRgb
     #[cfg(feature = "mediacodec")]
     fn handle_mediacodec_video_frame(
@@ -893,6 +960,13 @@ pub fn allow_d3d_render() -> bool {
 pub const BR_BEST: f32 = 1.5;
 pub const BR_BALANCED: f32 = 0.67;
 pub const BR_SPEED: f32 = 0.5;
+/// The ratio requested by the screen content profile, the quantizers of VP9 and AV1 go towards
+/// near-lossless between `BR_NEAR_LOSSLESS_START` and this.
+pub const BR_SCREEN_CONTENT: f32 = 3.0;
+pub const BR_NEAR_LOSSLESS_START: f32 = 2.0;
+/// Set in the low byte of `custom_image_quality` to request the screen content profile, older
+/// peers only read the quality above it.
+pub const SCREEN_CONTENT_FLAG: i32 = 1;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Quality {
@@ -900,6 +974,9 @@ pub enum Quality {
     Balanced,
     Low,
     Custom(f32),
+    /// Text and UI: I444 when supported, screen content tuning and near-lossless quantizers
+    /// when the link allows.
+    ScreenContent(f32),
 }
 
 impl Default for Quality {
@@ -916,16 +993,28 @@ impl Quality {
         }
     }
 
+    pub fn is_screen_content(&self) -> bool {
+        matches!(self, Quality::ScreenContent(_))
+    }
+
     pub fn ratio(&self) -> f32 {
         match self {
             Quality::Best => BR_BEST,
             Quality::Balanced => BR_BALANCED,
             Quality::Low => BR_SPEED,
-            Quality::Custom(v) => *v,
+            Quality::Custom(v) | Quality::ScreenContent(v) => *v,
         }
     }
 }
 
+/// The maximum quantizer of the screen content profile, from `q_max` at `BR_NEAR_LOSSLESS_START`
+/// down to `q_near_lossless` at `BR_SCREEN_CONTENT`.
+pub(crate) fn near_lossless_q_max(ratio: f32, q_max: u32, q_near_lossless: u32) -> u32 {
+    let t = ((ratio - BR_NEAR_LOSSLESS_START) / (BR_SCREEN_CONTENT - BR_NEAR_LOSSLESS_START))
+        .clamp(0.0, 1.0);
+    ((1.0 - t) * q_max as f32 + t * q_near_lossless as f32).round() as u32
+}
+
 pub fn base_bitrate(width: u32, height: u32) -> u32 {
     const RESOLUTION_PRESETS: &[(u32, u32, u32)] = &[
         (640, 480, 400),     // VGA, 307k pixels
@@ -1107,6 +1196,7 @@ pub fn test_av1() {
                     height,
                     quality,
                     keyframe_interval,
+                    screen_content: false,
                 }),
                 i444,
             ) else {
@@ -47,6 +47,8 @@ pub mod convert;
 pub mod hwcodec;
 #[cfg(feature = "mediacodec")]
 pub mod mediacodec;
+#[cfg(feature = "openh264")]
+pub mod openh264codec;
 pub mod vpxcodec;
 #[cfg(feature = "vram")]
 pub mod vram;
@@ -176,6 +178,20 @@ pub trait TraitPixelBuffer {
     fn stride(&self) -> Vec<usize>;
 
     fn pixfmt(&self) -> Pixfmt;
+
+    /// The regions changed since the previous frame, `None` if unknown.
+    fn dirty_rects(&self) -> Option<&[DirtyRect]> {
+        None
+    }
+}
+
+/// A changed region of a frame, in pixels from its top left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub x: usize,
+    pub y: usize,
+    pub w: usize,
+    pub h: usize,
 }
 
 #[cfg(not(any(target_os = "ios")))]
@@ -193,6 +209,15 @@ impl Frame<'_> {
         }
     }
 
+    /// The regions changed since the previous frame, `None` if unknown. Only used for the
+    /// damage statistics, the encoders always get the whole frame.
+    pub fn dirty_rects(&self) -> Option<&[DirtyRect]> {
+        match self {
+            Frame::PixelBuffer(pixelbuffer) => pixelbuffer.dirty_rects(),
+            Frame::Texture(_) => None,
+        }
+    }
+
     pub fn to<'a>(
         &'a self,
         yuvfmt: EncodeYuvFormat,
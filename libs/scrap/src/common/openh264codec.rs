@@ -0,0 +1,251 @@
+//! Software H264 with openh264, for the machines without a usable hardware codec.
+//!
+//! The encoder is only picked when H264 is preferred, the hardware encoders come first. The
+//! recordings of its stream need the `hwcodec` muxer, like the hardware H264, so the `openh264`
+//! feature enables `hwcodec`.
+
+use crate::{
+    codec::{base_bitrate, codec_thread_num, EncoderApi, EncoderCfg},
+    common::GoogleImage,
+    EncodeInput, EncodeYuvFormat, ImageRgb, Pixfmt,
+};
+use hbb_common::{
+    anyhow::{anyhow, Context},
+    bytes::Bytes,
+    log,
+    message_proto::{Chroma, EncodedVideoFrame, EncodedVideoFrames, VideoFrame},
+    ResultType,
+};
+use openh264::{
+    decoder::{DecodedYUV, Decoder},
+    encoder::{
+        BitRate, Encoder, EncoderConfig, FrameRate, FrameType, IntraFramePeriod, RateControlMode,
+        UsageType,
+    },
+    formats::{YUVSlices, YUVSource},
+    OpenH264API, Timestamp,
+};
+use openh264_sys2::{SBitrateInfo, ENCODER_OPTION_BITRATE, SPATIAL_LAYER_ALL};
+
+const DEFAULT_FPS: f32 = 30.0;
+
+#[derive(Debug, Clone)]
+pub struct OpenH264EncoderConfig {
+    pub width: usize,
+    pub height: usize,
+    pub quality: f32,
+    pub keyframe_interval: Option<usize>,
+}
+
+pub struct OpenH264Encoder {
+    encoder: Encoder,
+    config: OpenH264EncoderConfig,
+    yuvfmt: EncodeYuvFormat,
+    bitrate: u32, // kbs
+}
+
+impl EncoderApi for OpenH264Encoder {
+    fn new(cfg: EncoderCfg, _i444: bool) -> ResultType<Self>
+    where
+        Self: Sized,
+    {
+        match cfg {
+            EncoderCfg::OpenH264(config) => {
+                let bitrate = Self::bitrate(config.width, config.height, config.quality);
+                let mut c = EncoderConfig::new()
+                    .bitrate(BitRate::from_bps(bitrate * 1000))
+                    .max_frame_rate(FrameRate::from_hz(DEFAULT_FPS))
+                    .usage_type(UsageType::ScreenContentRealTime)
+                    .rate_control_mode(RateControlMode::Bitrate)
+                    .num_threads(codec_thread_num(4) as _);
+                if let Some(keyframe_interval) = config.keyframe_interval {
+                    c = c.intra_frame_period(IntraFramePeriod::from_num_frames(
+                        keyframe_interval as _,
+                    ));
+                }
+                let encoder = Encoder::with_api_config(OpenH264API::from_source(), c)
+                    .with_context(|| "Failed to create openh264 encoder")?;
+                Ok(Self {
+                    encoder,
+                    yuvfmt: Self::get_yuvfmt(config.width, config.height),
+                    config,
+                    bitrate,
+                })
+            }
+            _ => Err(anyhow!("encoder type mismatch")),
+        }
+    }
+
+    fn encode_to_message(&mut self, input: EncodeInput, ms: i64) -> ResultType<VideoFrame> {
+        let yuv = input.yuv()?;
+        let f = &self.yuvfmt;
+        if yuv.len() < f.v + f.stride[2] * f.h / 2 {
+            return Err(anyhow!("len not enough"));
+        }
+        let slices = YUVSlices::new(
+            (&yuv[..f.u], &yuv[f.u..f.v], &yuv[f.v..]),
+            (f.w, f.h),
+            (f.stride[0], f.stride[1], f.stride[2]),
+        );
+        let bitstream = self
+            .encoder
+            .encode_at(&slices, Timestamp::from_millis(ms as _))
+            .with_context(|| "Failed to encode")?;
+        let key = matches!(bitstream.frame_type(), FrameType::IDR | FrameType::I);
+        let data = bitstream.to_vec();
+        // Frames are skipped by the rate control.
+        if data.is_empty() {
+            return Err(anyhow!("no valid frame"));
+        }
+        let mut vf = VideoFrame::new();
+        vf.set_h264s(EncodedVideoFrames {
+            frames: vec![EncodedVideoFrame {
+                data: Bytes::from(data),
+                key,
+                pts: ms,
+                ..Default::default()
+            }]
+            .into(),
+            ..Default::default()
+        });
+        Ok(vf)
+    }
+
+    fn yuvfmt(&self) -> EncodeYuvFormat {
+        self.yuvfmt.clone()
+    }
+
+    #[cfg(feature = "vram")]
+    fn input_texture(&self) -> bool {
+        false
+    }
+
+    fn set_quality(&mut self, ratio: f32) -> ResultType<()> {
+        let bitrate = Self::bitrate(self.config.width, self.config.height, ratio);
+        let mut info = SBitrateInfo {
+            iLayer: SPATIAL_LAYER_ALL,
+            iBitrate: (bitrate * 1000) as _,
+        };
+        let ret = unsafe {
+            self.encoder
+                .raw_api()
+                .set_option(ENCODER_OPTION_BITRATE, &mut info as *mut _ as _)
+        };
+        if ret != 0 {
+            log::error!("failed to set openh264 bitrate: {}", ret);
+        } else {
+            self.bitrate = bitrate;
+        }
+        self.config.quality = ratio;
+        Ok(())
+    }
+
+    fn bitrate(&self) -> u32 {
+        self.bitrate
+    }
+
+    fn support_changing_quality(&self) -> bool {
+        true
+    }
+
+    fn latency_free(&self) -> bool {
+        true
+    }
+
+    fn is_hardware(&self) -> bool {
+        false
+    }
+
+    fn disable(&self) {}
+}
+
+impl OpenH264Encoder {
+    fn bitrate(width: usize, height: usize, ratio: f32) -> u32 {
+        (base_bitrate(width as _, height as _) as f32 * ratio) as u32
+    }
+
+    // I420 of even size, the capture is copied to the top left.
+    fn get_yuvfmt(width: usize, height: usize) -> EncodeYuvFormat {
+        let w = (width + 1) & !1;
+        let h = (height + 1) & !1;
+        let u = w * h;
+        EncodeYuvFormat {
+            pixfmt: Pixfmt::I420,
+            w,
+            h,
+            stride: vec![w, w / 2, w / 2],
+            u,
+            v: u + w / 2 * h / 2,
+        }
+    }
+}
+
+pub struct OpenH264Decoder {
+    decoder: Decoder,
+}
+
+impl OpenH264Decoder {
+    pub fn new() -> ResultType<Self> {
+        let decoder = Decoder::new().with_context(|| "Failed to create openh264 decoder")?;
+        Ok(Self { decoder })
+    }
+
+    // rgb [in/out] fmt and stride must be set in ImageRgb
+    pub fn decode(&mut self, data: &[u8], rgb: &mut ImageRgb) -> ResultType<bool> {
+        match self.decoder.decode(data)? {
+            Some(yuv) => {
+                Image(yuv).to(rgb);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+struct Image<'a>(DecodedYUV<'a>);
+
+impl GoogleImage for Image<'_> {
+    #[inline]
+    fn width(&self) -> usize {
+        self.0.dimensions().0
+    }
+
+    #[inline]
+    fn height(&self) -> usize {
+        self.0.dimensions().1
+    }
+
+    #[inline]
+    fn stride(&self) -> Vec<i32> {
+        let (y, u, v) = self.0.strides();
+        vec![y as _, u as _, v as _]
+    }
+
+    #[inline]
+    fn planes(&self) -> Vec<*mut u8> {
+        // Only read by the conversion.
+        vec![
+            self.0.y().as_ptr() as _,
+            self.0.u().as_ptr() as _,
+            self.0.v().as_ptr() as _,
+        ]
+    }
+
+    fn chroma(&self) -> Chroma {
+        Chroma::I420
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yuvfmt_is_padded_to_even_size() {
+        let f = OpenH264Encoder::get_yuvfmt(1919, 1081);
+        assert_eq!((f.w, f.h), (1920, 1082));
+        assert_eq!(f.stride, vec![1920, 960, 960]);
+        assert_eq!(f.u, 1920 * 1082);
+        assert_eq!(f.v, 1920 * 1082 + 960 * 541);
+    }
+}
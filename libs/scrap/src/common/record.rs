@@ -3,22 +3,29 @@ use crate::CodecFormat;
 use hbb_common::anyhow::anyhow;
 use hbb_common::{
     bail, chrono, log,
-    message_proto::{message, video_frame, EncodedVideoFrame, Message},
+    message_proto::{message, video_frame, AudioFormat, EncodedVideoFrame, Message},
+    sodiumoxide::crypto::box_,
     ResultType,
 };
 #[cfg(feature = "hwcodec")]
 use hwcodec::mux::{MuxContext, Muxer};
 use std::{
     fs::{File, OpenOptions},
-    io,
+    io::{self, Seek, SeekFrom, Write},
     ops::{Deref, DerefMut},
     path::PathBuf,
     sync::mpsc::Sender,
     time::Instant,
 };
-use webm::mux::{self, Segment, Track, VideoTrack, Writer};
+use webm::mux::{self, AudioTrack, Segment, Track, VideoTrack, Writer};
+
+mod crypto;
+pub use crypto::{
+    create as create_encrypted, decrypt, EncryptedWriter, EXTENSION as ENCRYPTED_EXTENSION,
+};
 
 const MIN_SECS: u64 = 1;
+const NOT_KEY_FRAME: &str = "first frame is not key frame";
 
 // Replace characters that are invalid in Windows filename components so recordings remain portable.
 // Control characters are also replaced because they can make filenames invalid
@@ -44,6 +51,8 @@ pub struct RecorderContext {
     pub display_idx: usize,
     pub camera: bool,
     pub tx: Option<Sender<RecordState>>,
+    /// Encrypt the recordings for this key.
+    pub public_key: Option<box_::PublicKey>,
 }
 
 #[derive(Debug, Clone)]
@@ -52,6 +61,8 @@ pub struct RecorderContext2 {
     pub width: usize,
     pub height: usize,
     pub format: CodecFormat,
+    /// The Opus stream to record along with the video, if any.
+    pub audio: Option<AudioFormat>,
 }
 
 impl RecorderContext2 {
@@ -59,7 +70,7 @@ impl RecorderContext2 {
         if !PathBuf::from(&ctx.dir).exists() {
             std::fs::create_dir_all(&ctx.dir)?;
         }
-        let file = if ctx.server { "incoming" } else { "outgoing" }.to_string()
+        let mut file = if ctx.server { "incoming" } else { "outgoing" }.to_string()
             + "_"
             + &sanitize_filename_component(&ctx.id)
             + &chrono::Local::now().format("_%Y%m%d%H%M%S%3f_").to_string()
@@ -74,9 +85,15 @@ impl RecorderContext2 {
                 || self.format == CodecFormat::AV1
             {
                 ".webm"
+            } else if ctx.public_key.is_some() {
+                // See `HwRecorder`, the segments are concatenated.
+                ".ts"
             } else {
                 ".mp4"
             };
+        if ctx.public_key.is_some() {
+            file += crypto::EXTENSION;
+        }
         self.filename = PathBuf::from(&ctx.dir)
             .join(file)
             .to_string_lossy()
@@ -93,6 +110,10 @@ pub trait RecorderApi {
     where
         Self: Sized;
     fn write_video(&mut self, frame: &EncodedVideoFrame) -> bool;
+    /// Write an Opus packet, `timestamp` is in nanoseconds on the timeline of the video.
+    fn write_audio(&mut self, _data: &[u8], _timestamp: u64) -> bool {
+        false
+    }
 }
 
 #[derive(Debug)]
@@ -108,6 +129,10 @@ pub struct Recorder {
     ctx: RecorderContext,
     ctx2: Option<RecorderContext2>,
     pts: Option<i64>,
+    // When the frame of `pts` was written, to place the audio packets after it.
+    pts_time: Option<Instant>,
+    audio: Option<AudioFormat>,
+    audio_timestamp: Option<u64>,
     check_failed: bool,
 }
 
@@ -132,6 +157,9 @@ impl Recorder {
             ctx,
             ctx2: None,
             pts: None,
+            pts_time: None,
+            audio: None,
+            audio_timestamp: None,
             check_failed: false,
         })
     }
@@ -139,11 +167,16 @@ impl Recorder {
     fn check(&mut self, w: usize, h: usize, format: CodecFormat) -> ResultType<()> {
         match self.ctx2 {
             Some(ref ctx2) => {
-                if ctx2.width != w || ctx2.height != h || ctx2.format != format {
+                if ctx2.width != w
+                    || ctx2.height != h
+                    || ctx2.format != format
+                    || ctx2.audio != self.audio
+                {
                     let mut ctx2 = RecorderContext2 {
                         width: w,
                         height: h,
                         format,
+                        audio: self.audio.clone(),
                         filename: Default::default(),
                     };
                     ctx2.set_filename(&self.ctx)?;
@@ -156,6 +189,7 @@ impl Recorder {
                     width: w,
                     height: h,
                     format,
+                    audio: self.audio.clone(),
                     filename: Default::default(),
                 };
                 ctx2.set_filename(&self.ctx)?;
@@ -181,17 +215,26 @@ impl Recorder {
             };
             // pts is None when new inner is created
             self.pts = None;
+            self.pts_time = None;
+            self.audio_timestamp = None;
             self.send_state(RecordState::NewFile(ctx2.filename.clone()));
         }
         Ok(())
     }
 
-    pub fn write_message(&mut self, msg: &Message, w: usize, h: usize) {
+    /// Returns an error if the frame could not be recorded, frames skipped while waiting for
+    /// the first key frame of a file are not errors.
+    pub fn write_message(&mut self, msg: &Message, w: usize, h: usize) -> ResultType<()> {
         if let Some(message::Union::VideoFrame(vf)) = &msg.union {
             if let Some(frame) = &vf.union {
-                self.write_frame(frame, w, h).ok();
+                if let Err(e) = self.write_frame(frame, w, h) {
+                    if e.to_string() != NOT_KEY_FRAME {
+                        return Err(e);
+                    }
+                }
             }
         }
+        Ok(())
     }
 
     pub fn write_frame(
@@ -217,38 +260,51 @@ impl Recorder {
             video_frame::Union::Vp8s(vp8s) => {
                 for f in vp8s.frames.iter() {
                     self.check_pts(f.pts, f.key, w, h, format)?;
-                    self.as_mut().map(|x| x.write_video(f));
+                    self.write_video(f)?;
                 }
             }
             video_frame::Union::Vp9s(vp9s) => {
                 for f in vp9s.frames.iter() {
                     self.check_pts(f.pts, f.key, w, h, format)?;
-                    self.as_mut().map(|x| x.write_video(f));
+                    self.write_video(f)?;
                 }
             }
             video_frame::Union::Av1s(av1s) => {
                 for f in av1s.frames.iter() {
                     self.check_pts(f.pts, f.key, w, h, format)?;
-                    self.as_mut().map(|x| x.write_video(f));
+                    self.write_video(f)?;
                 }
             }
             #[cfg(feature = "hwcodec")]
             video_frame::Union::H264s(h264s) => {
                 for f in h264s.frames.iter() {
                     self.check_pts(f.pts, f.key, w, h, format)?;
-                    self.as_mut().map(|x| x.write_video(f));
+                    self.write_video(f)?;
                 }
             }
             #[cfg(feature = "hwcodec")]
             video_frame::Union::H265s(h265s) => {
                 for f in h265s.frames.iter() {
                     self.check_pts(f.pts, f.key, w, h, format)?;
-                    self.as_mut().map(|x| x.write_video(f));
+                    self.write_video(f)?;
                 }
             }
             _ => bail!("unsupported frame type"),
         }
-        self.send_state(RecordState::NewFrame);
+        // The uploader sends what is appended to the file, but the blocks of an encrypted file
+        // change until it is written, so it is sent at the end.
+        if self.ctx.public_key.is_none() {
+            self.send_state(RecordState::NewFrame);
+        }
+        Ok(())
+    }
+
+    fn write_video(&mut self, frame: &EncodedVideoFrame) -> ResultType<()> {
+        if let Some(inner) = self.inner.as_mut() {
+            if !inner.write_video(frame) {
+                bail!("failed to write video frame");
+            }
+        }
         Ok(())
     }
 
@@ -262,10 +318,11 @@ impl Recorder {
     ) -> ResultType<()> {
         // https://stackoverflow.com/questions/76379101/how-to-create-one-playable-webm-file-from-two-different-video-tracks-with-same-c
         if self.pts.is_none() && !key {
-            bail!("first frame is not key frame");
+            bail!(NOT_KEY_FRAME);
         }
         let old_pts = self.pts;
         self.pts = Some(pts);
+        self.pts_time = Some(Instant::now());
         if old_pts.clone().unwrap_or_default() > pts {
             log::info!("pts {:?} -> {}, change record filename", old_pts, pts);
             self.inner = None;
@@ -276,19 +333,103 @@ impl Recorder {
                 log::error!("check failed: {:?}", res);
                 res?;
             }
+            if !key {
+                self.pts = None;
+                bail!(NOT_KEY_FRAME);
+            }
             self.pts = Some(pts);
+            self.pts_time = Some(Instant::now());
         }
         Ok(())
     }
 
+    /// Set the format of the audio to record, `None` to record video only. A new file is
+    /// started if it changes while recording, returns true if so, and its first video frame
+    /// must be a key frame.
+    pub fn set_audio_format(&mut self, audio: Option<AudioFormat>) -> bool {
+        if self.audio == audio {
+            return false;
+        }
+        self.audio = audio;
+        self.inner.is_some()
+    }
+
+    /// Write an Opus packet of the audio set by [`Self::set_audio_format`].
+    ///
+    /// Audio frames carry no timestamp, a packet is placed at the pts of the last video frame
+    /// plus the time elapsed since it was written, so both tracks stay in sync.
+    pub fn write_audio(&mut self, data: &[u8]) {
+        if self.audio.is_none() {
+            return;
+        }
+        let (Some(pts), Some(pts_time)) = (self.pts, self.pts_time) else {
+            return;
+        };
+        let mut timestamp = pts.max(0) as u64 * 1_000_000 + pts_time.elapsed().as_nanos() as u64;
+        if let Some(last) = self.audio_timestamp {
+            timestamp = timestamp.max(last + 1);
+        }
+        if self
+            .inner
+            .as_mut()
+            .map(|x| x.write_audio(data, timestamp))
+            .unwrap_or_default()
+        {
+            self.audio_timestamp = Some(timestamp);
+        }
+    }
+
     fn send_state(&self, state: RecordState) {
         self.ctx.tx.as_ref().map(|tx| tx.send(state));
     }
 }
 
+// The OpusHead of the codec private data, https://www.rfc-editor.org/rfc/rfc7845#section-5.1
+fn opus_head(audio: &AudioFormat) -> Vec<u8> {
+    let mut head = b"OpusHead".to_vec();
+    head.push(1); // version
+    head.push(audio.channels as u8);
+    head.extend_from_slice(&0u16.to_le_bytes()); // pre-skip
+    head.extend_from_slice(&audio.sample_rate.to_le_bytes());
+    head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+    head.push(0); // channel mapping family, mono or stereo
+    head
+}
+
+enum RecordFile {
+    Plain(File),
+    Encrypted(crypto::EncryptedWriter<File>),
+}
+
+impl Write for RecordFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            RecordFile::Plain(f) => f.write(buf),
+            RecordFile::Encrypted(f) => f.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            RecordFile::Plain(f) => f.flush(),
+            RecordFile::Encrypted(f) => f.flush(),
+        }
+    }
+}
+
+impl Seek for RecordFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            RecordFile::Plain(f) => f.seek(pos),
+            RecordFile::Encrypted(f) => f.seek(pos),
+        }
+    }
+}
+
 struct WebmRecorder {
     vt: VideoTrack,
-    webm: Option<Segment<Writer<File>>>,
+    at: Option<AudioTrack>,
+    webm: Option<Segment<Writer<RecordFile>>>,
     ctx: RecorderContext,
     ctx2: RecorderContext2,
     key: bool,
@@ -300,14 +441,25 @@ impl RecorderApi for WebmRecorder {
     fn new(ctx: RecorderContext, ctx2: RecorderContext2) -> ResultType<Self> {
         let out = match {
             OpenOptions::new()
+                .read(true)
                 .write(true)
                 .create_new(true)
                 .open(&ctx2.filename)
         } {
             Ok(file) => file,
-            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => File::create(&ctx2.filename)?,
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => OpenOptions::new()
+                .read(true)
+                .write(true)
+                .truncate(true)
+                .open(&ctx2.filename)?,
             Err(e) => return Err(e.into()),
         };
+        let out = match &ctx.public_key {
+            Some(public_key) => {
+                RecordFile::Encrypted(crypto::EncryptedWriter::new(out, public_key)?)
+            }
+            None => RecordFile::Plain(out),
+        };
         let mut webm = match mux::Segment::new(mux::Writer::new(out)) {
             Some(v) => v,
             None => bail!("Failed to create webm mux"),
@@ -331,8 +483,24 @@ impl RecorderApi for WebmRecorder {
                 bail!("Failed to set codec private");
             }
         }
+        let at = match &ctx2.audio {
+            Some(audio) => {
+                let at = webm.add_audio_track(
+                    audio.sample_rate as _,
+                    audio.channels as _,
+                    None,
+                    mux::AudioCodecId::Opus,
+                );
+                if !webm.set_codec_private(at.track_number(), &opus_head(audio)) {
+                    bail!("Failed to set audio codec private");
+                }
+                Some(at)
+            }
+            None => None,
+        };
         Ok(WebmRecorder {
             vt,
+            at,
             webm: Some(webm),
             ctx,
             ctx2,
@@ -358,6 +526,17 @@ impl RecorderApi for WebmRecorder {
             false
         }
     }
+
+    fn write_audio(&mut self, data: &[u8], timestamp: u64) -> bool {
+        // The file starts with a video key frame.
+        if !self.key {
+            return false;
+        }
+        self.at
+            .as_mut()
+            .map(|at| at.add_frame(data, timestamp, true))
+            .unwrap_or_default()
+    }
 }
 
 impl Drop for WebmRecorder {
@@ -372,9 +551,22 @@ impl Drop for WebmRecorder {
     }
 }
 
+// The hardware muxer writes video only, H264 and H265 recordings have no audio.
+//
+// The muxer can only write to a plaintext file. With a public key it writes MPEG-TS segments,
+// which can be concatenated, and each one is encrypted into the recording as soon as it is
+// finished. A segment ends on the first key frame after `SEGMENT_SECS`, so only the current
+// segment is ever on disk in plaintext.
+#[cfg(feature = "hwcodec")]
+const SEGMENT_SECS: u64 = 10;
+
 #[cfg(feature = "hwcodec")]
 struct HwRecorder {
     muxer: Option<Muxer>,
+    // The file of the muxer, the current segment if there is a key.
+    path: String,
+    encrypted: Option<crypto::EncryptedWriter<File>>,
+    segment_start: Instant,
     ctx: RecorderContext,
     ctx2: RecorderContext2,
     written: bool,
@@ -383,18 +575,55 @@ struct HwRecorder {
 }
 
 #[cfg(feature = "hwcodec")]
-impl RecorderApi for HwRecorder {
-    fn new(ctx: RecorderContext, ctx2: RecorderContext2) -> ResultType<Self> {
-        let muxer = Muxer::new(MuxContext {
-            filename: ctx2.filename.clone(),
+impl HwRecorder {
+    fn new_muxer(path: &str, ctx2: &RecorderContext2) -> ResultType<Muxer> {
+        Muxer::new(MuxContext {
+            filename: path.to_owned(),
             width: ctx2.width,
             height: ctx2.height,
             is265: ctx2.format == CodecFormat::H265,
             framerate: crate::hwcodec::DEFAULT_FPS as _,
         })
-        .map_err(|_| anyhow!("Failed to create hardware muxer"))?;
+        .map_err(|_| anyhow!("Failed to create hardware muxer"))
+    }
+
+    // Encrypt the finished segment into the recording and wipe it.
+    fn append_segment(&mut self) -> ResultType<()> {
+        self.muxer.as_mut().map(|m| m.write_tail().ok());
+        // The file is closed with the muxer.
+        self.muxer = None;
+        let res = match self.encrypted.as_mut() {
+            Some(w) => io::copy(&mut File::open(&self.path)?, w).and_then(|_| w.flush()),
+            None => Ok(()),
+        };
+        if let Err(e) = crypto::remove_securely(&self.path) {
+            log::error!(
+                "Failed to remove the recording segment {}: {}",
+                self.path,
+                e
+            );
+        }
+        res?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "hwcodec")]
+impl RecorderApi for HwRecorder {
+    fn new(ctx: RecorderContext, ctx2: RecorderContext2) -> ResultType<Self> {
+        let (path, encrypted) = match &ctx.public_key {
+            Some(public_key) => (
+                format!("{}.segment.ts", ctx2.filename),
+                Some(crypto::create(&ctx2.filename, public_key)?),
+            ),
+            None => (ctx2.filename.clone(), None),
+        };
+        let muxer = Self::new_muxer(&path, &ctx2)?;
         Ok(HwRecorder {
             muxer: Some(muxer),
+            path,
+            encrypted,
+            segment_start: Instant::now(),
             ctx,
             ctx2,
             written: false,
@@ -406,6 +635,24 @@ impl RecorderApi for HwRecorder {
     fn write_video(&mut self, frame: &EncodedVideoFrame) -> bool {
         if frame.key {
             self.key = true;
+            if self.encrypted.is_some()
+                && self.written
+                && self.segment_start.elapsed().as_secs() >= SEGMENT_SECS
+            {
+                let res = self
+                    .append_segment()
+                    .and_then(|_| Self::new_muxer(&self.path, &self.ctx2));
+                match res {
+                    Ok(muxer) => {
+                        self.muxer = Some(muxer);
+                        self.segment_start = Instant::now();
+                    }
+                    Err(e) => {
+                        log::error!("Failed to start a new recording segment: {}", e);
+                        return false;
+                    }
+                }
+            }
         }
         if self.key {
             let ok = self
@@ -426,13 +673,30 @@ impl RecorderApi for HwRecorder {
 #[cfg(feature = "hwcodec")]
 impl Drop for HwRecorder {
     fn drop(&mut self) {
-        self.muxer.as_mut().map(|m| m.write_tail().ok());
         let mut state = RecordState::WriteTail;
         if !self.written || self.start.elapsed().as_secs() < MIN_SECS {
             // The process cannot access the file because it is being used by another process
             self.muxer = None;
+            self.encrypted = None;
+            if self.ctx.public_key.is_some() {
+                crypto::remove_securely(&self.path).ok();
+            } else {
+                std::fs::remove_file(&self.path).ok();
+            }
             std::fs::remove_file(&self.ctx2.filename).ok();
             state = RecordState::RemoveFile;
+        } else if self.encrypted.is_some() {
+            let res = self
+                .append_segment()
+                .and_then(|_| match self.encrypted.as_mut() {
+                    Some(w) => Ok(w.finish()?),
+                    None => Ok(()),
+                });
+            if let Err(e) = res {
+                log::error!("Failed to encrypt {}: {}", self.ctx2.filename, e);
+            }
+        } else {
+            self.muxer.as_mut().map(|m| m.write_tail().ok());
         }
         self.ctx.tx.as_ref().map(|tx| tx.send(state));
     }
@@ -440,7 +704,7 @@ impl Drop for HwRecorder {
 
 #[cfg(test)]
 mod tests {
-    use super::sanitize_filename_component;
+    use super::*;
 
     #[test]
     fn sanitize_recording_filename_component() {
@@ -457,4 +721,17 @@ mod tests {
             "peer_name_with_bad_chars"
         );
     }
+
+    #[test]
+    fn opus_head_describes_the_stream() {
+        let head = opus_head(&AudioFormat {
+            sample_rate: 48000,
+            channels: 2,
+            ..Default::default()
+        });
+        assert_eq!(head.len(), 19);
+        assert_eq!(&head[..8], b"OpusHead");
+        assert_eq!(head[9], 2);
+        assert_eq!(&head[12..16], &48000u32.to_le_bytes());
+    }
 }
@@ -0,0 +1,438 @@
+//! Recordings encrypted with a public key, only the holder of the secret key can play them.
+//!
+//! The file starts with [`MAGIC`], then the random key and base nonce of the recording, sealed
+//! for the public key. The content follows in blocks of [`BLOCK_SIZE`] bytes, the last one may
+//! be shorter. Each block is the generation it was written in, then the content encrypted with
+//! the key of the recording and the nonce derived from the base nonce, the index of the block,
+//! the generation and whether it is the final block. A block is encrypted again in the next
+//! generation when it changes, so the muxer can seek back to update the headers without reusing
+//! a nonce.
+//!
+//! Deriving the nonce from the index makes moved or duplicated blocks fail to decrypt, and the
+//! final block is only marked when the recording is closed, so a file cut at a block boundary
+//! is reported as truncated.
+
+use hbb_common::{
+    bail,
+    sodiumoxide::crypto::{box_, sealedbox, secretbox},
+    ResultType,
+};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// The extension added to the name of encrypted recordings.
+pub const EXTENSION: &str = ".enc";
+const MAGIC: &[u8; 8] = b"RDRECENC";
+const BLOCK_SIZE: usize = 64 * 1024;
+const SECRET_LEN: usize = secretbox::KEYBYTES + secretbox::NONCEBYTES;
+const HEADER_LEN: usize = MAGIC.len() + sealedbox::SEALBYTES + SECRET_LEN;
+const GENERATION_LEN: usize = 4;
+const BLOCK_OVERHEAD: usize = GENERATION_LEN + secretbox::MACBYTES;
+
+struct BlockKey {
+    key: secretbox::Key,
+    nonce: secretbox::Nonce,
+}
+
+impl BlockKey {
+    fn nonce(&self, index: u64, generation: u32, is_final: bool) -> secretbox::Nonce {
+        let mut nonce = self.nonce.0;
+        for (n, b) in nonce.iter_mut().zip(index.to_le_bytes()) {
+            *n ^= b;
+        }
+        for (n, b) in nonce[8..].iter_mut().zip(generation.to_le_bytes()) {
+            *n ^= b;
+        }
+        nonce[12] ^= is_final as u8;
+        secretbox::Nonce(nonce)
+    }
+
+    fn seal(&self, content: &[u8], index: u64, generation: u32, is_final: bool) -> Vec<u8> {
+        let nonce = self.nonce(index, generation, is_final);
+        let mut buf = generation.to_le_bytes().to_vec();
+        buf.extend(secretbox::seal(content, &nonce, &self.key));
+        buf
+    }
+
+    // Returns the content and the generation of the block.
+    fn open(&self, buf: &[u8], index: u64, is_final: bool) -> ResultType<(Vec<u8>, u32)> {
+        if buf.len() < BLOCK_OVERHEAD {
+            bail!("The recording is truncated");
+        }
+        let mut generation = [0u8; GENERATION_LEN];
+        generation.copy_from_slice(&buf[..GENERATION_LEN]);
+        let generation = u32::from_le_bytes(generation);
+        let nonce = self.nonce(index, generation, is_final);
+        match secretbox::open(&buf[GENERATION_LEN..], &nonce, &self.key) {
+            Ok(block) => Ok((block, generation)),
+            Err(_) => bail!("Failed to decrypt the recording, it is damaged"),
+        }
+    }
+}
+
+pub struct EncryptedWriter<F: Read + Write + Seek> {
+    file: F,
+    key: BlockKey,
+    // The position and length of the content.
+    pos: u64,
+    len: u64,
+    // The content of the block at `index`, to be written if dirty.
+    block: Vec<u8>,
+    index: u64,
+    // The generation to write the block in.
+    generation: u32,
+    dirty: bool,
+    finished: bool,
+}
+
+impl<F: Read + Write + Seek> EncryptedWriter<F> {
+    pub fn new(mut file: F, public_key: &box_::PublicKey) -> io::Result<Self> {
+        let key = BlockKey {
+            key: secretbox::gen_key(),
+            nonce: secretbox::gen_nonce(),
+        };
+        let mut secret = key.key.0.to_vec();
+        secret.extend(key.nonce.0);
+        file.write_all(MAGIC)?;
+        file.write_all(&sealedbox::seal(&secret, public_key))?;
+        Ok(Self {
+            file,
+            key,
+            pos: 0,
+            len: 0,
+            block: Vec::with_capacity(BLOCK_SIZE),
+            index: 0,
+            generation: 0,
+            dirty: false,
+            finished: false,
+        })
+    }
+
+    fn block_offset(index: u64) -> u64 {
+        HEADER_LEN as u64 + index * (BLOCK_SIZE + BLOCK_OVERHEAD) as u64
+    }
+
+    fn last_index(&self) -> u64 {
+        self.len.saturating_sub(1) / BLOCK_SIZE as u64
+    }
+
+    fn flush_block(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let is_final = self.finished && self.index == self.last_index();
+        let buf = self
+            .key
+            .seal(&self.block, self.index, self.generation, is_final);
+        self.file
+            .seek(SeekFrom::Start(Self::block_offset(self.index)))?;
+        self.file.write_all(&buf)?;
+        self.generation = self.generation.checked_add(1).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                "too many updates of a recording block",
+            )
+        })?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn load_block(&mut self, index: u64) -> io::Result<()> {
+        self.flush_block()?;
+        self.index = index;
+        self.generation = 0;
+        self.block.clear();
+        let start = index * BLOCK_SIZE as u64;
+        if start >= self.len {
+            return Ok(());
+        }
+        let len = (self.len - start).min(BLOCK_SIZE as u64) as usize;
+        let mut buf = vec![0u8; len + BLOCK_OVERHEAD];
+        self.file.seek(SeekFrom::Start(Self::block_offset(index)))?;
+        self.file.read_exact(&mut buf)?;
+        let (block, generation) = self
+            .key
+            .open(&buf, index, false)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        self.block = block;
+        self.generation = generation.checked_add(1).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                "too many updates of a recording block",
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Mark the last block as final, nothing can be written afterwards. It is done on drop too.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.load_block(self.last_index())?;
+        self.finished = true;
+        self.dirty = true;
+        self.flush_block()?;
+        self.file.flush()
+    }
+}
+
+impl<F: Read + Write + Seek> Write for EncryptedWriter<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.finished {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "writing to a finished encrypted recording",
+            ));
+        }
+        if self.pos > self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "writing past the end of an encrypted recording",
+            ));
+        }
+        let index = self.pos / BLOCK_SIZE as u64;
+        if index != self.index {
+            self.load_block(index)?;
+        }
+        let offset = (self.pos % BLOCK_SIZE as u64) as usize;
+        let n = buf.len().min(BLOCK_SIZE - offset);
+        let end = offset + n;
+        if self.block.len() < end {
+            self.block.resize(end, 0);
+        }
+        self.block[offset..end].copy_from_slice(&buf[..n]);
+        self.dirty = true;
+        self.pos += n as u64;
+        self.len = self.len.max(self.pos);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_block()?;
+        self.file.flush()
+    }
+}
+
+impl<F: Read + Write + Seek> Seek for EncryptedWriter<F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(off) => self.pos.checked_add_signed(off),
+            SeekFrom::End(off) => self.len.checked_add_signed(off),
+        };
+        let Some(pos) = pos else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            ));
+        };
+        self.pos = pos;
+        Ok(pos)
+    }
+}
+
+impl<F: Read + Write + Seek> Drop for EncryptedWriter<F> {
+    fn drop(&mut self) {
+        self.finish().ok();
+    }
+}
+
+// Read until `buf` is full or the end, returns the length read.
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+// Returns false if the recording has no final block, e.g. the process was killed, everything
+// before is written anyway.
+fn decrypt_stream<R: Read, W: Write>(
+    r: &mut R,
+    w: &mut W,
+    secret_key: &box_::SecretKey,
+) -> ResultType<bool> {
+    let mut header = [0u8; HEADER_LEN];
+    if read_full(r, &mut header)? < HEADER_LEN || &header[..MAGIC.len()] != MAGIC {
+        bail!("Not an encrypted recording");
+    }
+    let Ok(secret) = sealedbox::open(&header[MAGIC.len()..], &secret_key.public_key(), secret_key)
+    else {
+        bail!("The recording is encrypted for another key");
+    };
+    if secret.len() != SECRET_LEN {
+        bail!("Invalid recording key");
+    }
+    let (Some(key), Some(nonce)) = (
+        secretbox::Key::from_slice(&secret[..secretbox::KEYBYTES]),
+        secretbox::Nonce::from_slice(&secret[secretbox::KEYBYTES..]),
+    ) else {
+        bail!("Invalid recording key");
+    };
+    let key = BlockKey { key, nonce };
+    let mut buf = vec![0u8; BLOCK_SIZE + BLOCK_OVERHEAD];
+    let mut next = vec![0u8; BLOCK_SIZE + BLOCK_OVERHEAD];
+    let mut n = read_full(r, &mut buf)?;
+    let mut index = 0;
+    loop {
+        if n == 0 {
+            // Cut at a block boundary.
+            w.flush()?;
+            return Ok(false);
+        }
+        let m = if n < buf.len() {
+            0
+        } else {
+            read_full(r, &mut next)?
+        };
+        if m > 0 {
+            w.write_all(&key.open(&buf[..n], index, false)?.0)?;
+            std::mem::swap(&mut buf, &mut next);
+            n = m;
+            index += 1;
+            continue;
+        }
+        let complete = match key.open(&buf[..n], index, true) {
+            Ok((block, _)) => {
+                w.write_all(&block)?;
+                true
+            }
+            Err(_) => {
+                w.write_all(&key.open(&buf[..n], index, false)?.0)?;
+                false
+            }
+        };
+        w.flush()?;
+        return Ok(complete);
+    }
+}
+
+/// Decrypt the recording `src` into the playable file `dst`. Returns false if the recording
+/// was not closed, e.g. the process was killed, then `dst` has what was written until then.
+pub fn decrypt(src: &Path, dst: &Path, secret_key: &box_::SecretKey) -> ResultType<bool> {
+    let mut r = io::BufReader::new(File::open(src)?);
+    let mut w = io::BufWriter::new(File::create(dst)?);
+    decrypt_stream(&mut r, &mut w, secret_key)
+}
+
+/// Open the encrypted recording `path` for writing, replacing an existing file.
+pub fn create(path: &str, public_key: &box_::PublicKey) -> io::Result<EncryptedWriter<File>> {
+    let out = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    EncryptedWriter::new(out, public_key)
+}
+
+/// Overwrite the plaintext file `path` with zeros before removing it, so the content does not
+/// stay on the disk next to the encrypted recording.
+pub fn remove_securely(path: &str) -> io::Result<()> {
+    let res = (|| {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        let mut left = file.metadata()?.len();
+        let zeros = vec![0u8; BLOCK_SIZE];
+        while left > 0 {
+            let n = left.min(BLOCK_SIZE as u64) as usize;
+            file.write_all(&zeros[..n])?;
+            left -= n as u64;
+        }
+        file.sync_all()
+    })();
+    std::fs::remove_file(path)?;
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn encrypt(content: &[u8], pk: &box_::PublicKey) -> Vec<u8> {
+        let mut w = EncryptedWriter::new(Cursor::new(vec![]), pk).unwrap();
+        w.write_all(content).unwrap();
+        w.finish().unwrap();
+        w.file.get_ref().clone()
+    }
+
+    fn block_range(index: usize) -> std::ops::Range<usize> {
+        let start = HEADER_LEN + index * (BLOCK_SIZE + BLOCK_OVERHEAD);
+        start..start + BLOCK_SIZE + BLOCK_OVERHEAD
+    }
+
+    #[test]
+    fn recordings_round_trip_with_seeks() {
+        let (pk, sk) = box_::gen_keypair();
+        let mut content: Vec<u8> = (0..BLOCK_SIZE * 3 + 100).map(|i| i as u8).collect();
+        let mut w = EncryptedWriter::new(Cursor::new(vec![]), &pk).unwrap();
+        w.write_all(&content).unwrap();
+        // Like the muxer updating the headers when finalizing.
+        w.seek(SeekFrom::Start(10)).unwrap();
+        w.write_all(b"header").unwrap();
+        content[10..16].copy_from_slice(b"header");
+        w.seek(SeekFrom::End(0)).unwrap();
+        w.write_all(b"cues").unwrap();
+        content.extend(b"cues");
+        w.finish().unwrap();
+        let encrypted = w.file.get_ref().clone();
+        assert!(!encrypted.windows(64).any(|x| x == &content[100..164]));
+
+        let mut out = vec![];
+        assert!(decrypt_stream(&mut Cursor::new(&encrypted), &mut out, &sk).unwrap());
+        assert_eq!(out, content);
+
+        let (_, other) = box_::gen_keypair();
+        assert!(decrypt_stream(&mut Cursor::new(&encrypted), &mut vec![], &other).is_err());
+    }
+
+    #[test]
+    fn moved_or_missing_blocks_are_detected() {
+        let (pk, sk) = box_::gen_keypair();
+        let content: Vec<u8> = (0..BLOCK_SIZE * 3).map(|i| (i / 7) as u8).collect();
+        let encrypted = encrypt(&content, &pk);
+
+        let mut swapped = encrypted.clone();
+        let (first, second) = (block_range(0), block_range(1));
+        let block = encrypted[first.clone()].to_vec();
+        swapped[first].copy_from_slice(&encrypted[second.clone()]);
+        swapped[second].copy_from_slice(&block);
+        assert!(decrypt_stream(&mut Cursor::new(&swapped), &mut vec![], &sk).is_err());
+
+        let mut duplicated = encrypted.clone();
+        duplicated[block_range(1)].copy_from_slice(&encrypted[block_range(0)]);
+        assert!(decrypt_stream(&mut Cursor::new(&duplicated), &mut vec![], &sk).is_err());
+
+        let truncated = &encrypted[..block_range(2).start];
+        let mut out = vec![];
+        assert!(!decrypt_stream(&mut Cursor::new(truncated), &mut out, &sk).unwrap());
+        assert_eq!(out, content[..BLOCK_SIZE * 2]);
+    }
+
+    #[test]
+    fn unfinished_recordings_are_reported() {
+        let (pk, sk) = box_::gen_keypair();
+        let mut w = EncryptedWriter::new(Cursor::new(vec![]), &pk).unwrap();
+        w.write_all(b"partial").unwrap();
+        w.flush().unwrap();
+        let encrypted = w.file.get_ref().clone();
+        std::mem::forget(w);
+        let mut out = vec![];
+        assert!(!decrypt_stream(&mut Cursor::new(&encrypted), &mut out, &sk).unwrap());
+        assert_eq!(out, b"partial");
+
+        let mut out = vec![];
+        assert!(decrypt_stream(&mut Cursor::new(encrypt(b"", &pk)), &mut out, &sk).unwrap());
+        assert!(out.is_empty());
+    }
+}
@@ -8,7 +8,7 @@ use hbb_common::log;
 use hbb_common::message_proto::{Chroma, EncodedVideoFrame, EncodedVideoFrames, VideoFrame};
 use hbb_common::ResultType;
 
-use crate::codec::{base_bitrate, codec_thread_num, EncoderApi};
+use crate::codec::{base_bitrate, codec_thread_num, near_lossless_q_max, EncoderApi};
 use crate::{EncodeInput, EncodeYuvFormat, GoogleImage, Pixfmt, STRIDE_ALIGN};
 
 use super::vpx::{vp8e_enc_control_id::*, vpx_codec_err_t::*, *};
@@ -38,6 +38,7 @@ pub struct VpxEncoder {
     height: usize,
     id: VpxVideoCodecId,
     i444: bool,
+    screen_content: bool,
     yuvfmt: EncodeYuvFormat,
 }
 
@@ -83,7 +84,7 @@ impl EncoderApi for VpxEncoder {
                     c.kf_mode = vpx_kf_mode::VPX_KF_DISABLED; // reduce bandwidth a lot
                 }
 
-                let (q_min, q_max) = Self::calc_q_values(config.quality);
+                let (q_min, q_max) = Self::calc_q_values(config.quality, config.screen_content);
                 c.rc_min_quantizer = q_min;
                 c.rc_max_quantizer = q_max;
                 c.rc_target_bitrate =
@@ -152,6 +153,15 @@ impl EncoderApi for VpxEncoder {
                         VP9E_SET_TILE_COLUMNS as _,
                         4 as c_int
                     ));
+
+                    if config.screen_content {
+                        // Screen content mode, favors sharp text and flat areas
+                        call_vpx!(vpx_codec_control_(
+                            &mut ctx,
+                            VP9E_SET_TUNE_CONTENT as _,
+                            vp9e_tune_content::VP9E_CONTENT_SCREEN as c_int
+                        ));
+                    }
                 } else if config.codec == VpxVideoCodecId::VP8 {
                     // https://github.com/webmproject/libvpx/blob/972149cafeb71d6f08df89e91a0130d6a38c4b15/vpx/vp8cx.h#L172
                     // https://groups.google.com/a/webmproject.org/g/webm-discuss/c/DJhSrmfQ61M
@@ -164,6 +174,7 @@ impl EncoderApi for VpxEncoder {
                     height: config.height as _,
                     id: config.codec,
                     i444,
+                    screen_content: config.screen_content,
                     yuvfmt: Self::get_yuvfmt(config.width, config.height, i444),
                 })
             }
@@ -202,7 +213,7 @@ impl EncoderApi for VpxEncoder {
 
     fn set_quality(&mut self, ratio: f32) -> ResultType<()> {
         let mut c = unsafe { *self.ctx.config.enc.to_owned() };
-        let (q_min, q_max) = Self::calc_q_values(ratio);
+        let (q_min, q_max) = Self::calc_q_values(ratio, self.screen_content);
         c.rc_min_quantizer = q_min;
         c.rc_max_quantizer = q_max;
         c.rc_target_bitrate = Self::bitrate(self.width as _, self.height as _, ratio);
@@ -317,7 +328,7 @@ impl VpxEncoder {
     }
 
     #[inline]
-    fn calc_q_values(ratio: f32) -> (u32, u32) {
+    fn calc_q_values(ratio: f32, screen_content: bool) -> (u32, u32) {
         let b = (ratio * 100.0) as u32;
         let b = std::cmp::min(b, 200);
         let q_min1 = 36;
@@ -332,6 +343,9 @@ impl VpxEncoder {
 
         q_min = q_min.clamp(q_min2, q_min1);
         q_max = q_max.clamp(q_max2, q_max1);
+        if screen_content {
+            q_max = near_lossless_q_max(ratio, q_max, 8);
+        }
 
         (q_min, q_max)
     }
@@ -398,6 +412,8 @@ pub struct VpxEncoderConfig {
     pub codec: VpxVideoCodecId,
     /// keyframe interval
     pub keyframe_interval: Option<usize>,
+    /// Tune for screen content, see `Quality::ScreenContent`
+    pub screen_content: bool,
 }
 
 #[derive(Clone, Copy, Debug)]
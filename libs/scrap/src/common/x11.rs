@@ -1,4 +1,4 @@
-use crate::{common::TraitCapturer, x11, Frame, Pixfmt, TraitPixelBuffer};
+use crate::{common::TraitCapturer, x11, DirtyRect, Frame, Pixfmt, TraitPixelBuffer};
 use std::{io, time::Duration};
 
 pub struct Capturer(x11::Capturer);
@@ -24,12 +24,10 @@ impl TraitCapturer for Capturer {
         let width = self.width();
         let height = self.height();
         let pixfmt = self.0.display().pixfmt();
-        Ok(Frame::PixelBuffer(PixelBuffer::new(
-            self.0.frame()?,
-            pixfmt,
-            width,
-            height,
-        )))
+        let (data, dirty_rects) = self.0.frame()?;
+        Ok(Frame::PixelBuffer(
+            PixelBuffer::new(data, pixfmt, width, height).with_dirty_rects(dirty_rects),
+        ))
     }
 }
 
@@ -39,6 +37,7 @@ pub struct PixelBuffer<'a> {
     width: usize,
     height: usize,
     stride: Vec<usize>,
+    dirty_rects: Option<&'a [DirtyRect]>,
 }
 
 impl<'a> PixelBuffer<'a> {
@@ -52,8 +51,14 @@ impl<'a> PixelBuffer<'a> {
             width,
             height,
             stride,
+            dirty_rects: None,
         }
     }
+
+    pub fn with_dirty_rects(mut self, dirty_rects: Option<&'a [DirtyRect]>) -> Self {
+        self.dirty_rects = dirty_rects;
+        self
+    }
 }
 
 impl<'a> TraitPixelBuffer for PixelBuffer<'a> {
@@ -76,6 +81,10 @@ impl<'a> TraitPixelBuffer for PixelBuffer<'a> {
     fn pixfmt(&self) -> crate::Pixfmt {
         self.pixfmt
     }
+
+    fn dirty_rects(&self) -> Option<&[DirtyRect]> {
+        self.dirty_rects
+    }
 }
 
 pub struct Display(x11::Display);
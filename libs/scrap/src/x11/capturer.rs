@@ -1,5 +1,7 @@
+use super::damage::Damage;
 use super::ffi::*;
 use super::Display;
+use crate::DirtyRect;
 use hbb_common::libc;
 use std::{io, ptr, slice};
 
@@ -11,6 +13,11 @@ pub struct Capturer {
 
     size: usize,
     saved_raw_data: Vec<u8>, // for faster compare and copy
+    // Without XDamage, the frames are compared to skip the unchanged ones.
+    damage: Option<Damage>,
+    dirty_rects: Vec<DirtyRect>,
+    // The next frame is sent whole, e.g. the first one.
+    full: bool,
 }
 
 impl Capturer {
@@ -57,6 +64,11 @@ impl Capturer {
             );
         }
 
+        let damage = Damage::new(display.root());
+        if damage.is_none() {
+            hbb_common::log::info!("XDamage is not available, compare the frames");
+        }
+
         let c = Capturer {
             display,
             shmid,
@@ -64,6 +76,9 @@ impl Capturer {
             buffer,
             size,
             saved_raw_data: Vec::new(),
+            damage,
+            dirty_rects: Vec::new(),
+            full: true,
         };
         Ok(c)
     }
@@ -93,11 +108,35 @@ impl Capturer {
         }
     }
 
-    pub fn frame<'b>(&'b mut self) -> std::io::Result<&'b [u8]> {
+    /// The frame and its regions changed since the previous one, if known.
+    pub fn frame<'b>(&'b mut self) -> std::io::Result<(&'b [u8], Option<&'b [DirtyRect]>)> {
+        let rect = self.display.rect();
+        // Taken before the grab, so the changes made meanwhile are in the next frame.
+        let dirty = match &self.damage {
+            Some(damage) => damage.take(rect),
+            None => None,
+        };
+        if let Some(mut dirty) = dirty {
+            if self.full {
+                dirty = vec![DirtyRect {
+                    x: 0,
+                    y: 0,
+                    w: rect.w as _,
+                    h: rect.h as _,
+                }];
+            } else if dirty.is_empty() {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            self.full = false;
+            self.get_image();
+            self.dirty_rects = dirty;
+            let result = unsafe { slice::from_raw_parts(self.buffer, self.size) };
+            return Ok((result, Some(&self.dirty_rects)));
+        }
         self.get_image();
         let result = unsafe { slice::from_raw_parts(self.buffer, self.size) };
         crate::would_block_if_equal(&mut self.saved_raw_data, result)?;
-        Ok(result)
+        Ok((result, None))
     }
 }
 
@@ -0,0 +1,166 @@
+//! The changed regions of the root window with XDamage, so unchanged frames are skipped
+//! without grabbing and comparing them.
+
+use super::ffi::*;
+use super::{Rect, Server};
+use crate::DirtyRect;
+use hbb_common::libc;
+use std::{ptr, slice};
+
+pub struct Damage {
+    // Its own connection, the notify events are dropped without losing the capturer's ones.
+    // The damage and the region are freed by the X server when it is closed.
+    server: Server,
+    damage: xcb_damage_damage_t,
+    region: xcb_xfixes_region_t,
+}
+
+impl Damage {
+    /// `None` if the server doesn't support XDamage.
+    pub fn new(root: xcb_window_t) -> Option<Damage> {
+        let server = Server::connect(ptr::null()).ok()?;
+        let c = server.raw();
+        unsafe {
+            // The extensions have to be initialized with their versions before use.
+            let reply = xcb_xfixes_query_version_reply(
+                c,
+                xcb_xfixes_query_version(c, 2, 0),
+                ptr::null_mut(),
+            );
+            if reply.is_null() {
+                return None;
+            }
+            // Regions are from XFixes 2.
+            let major = (*reply).major_version;
+            libc::free(reply as *mut _);
+            if major < 2 {
+                return None;
+            }
+            let reply = xcb_damage_query_version_reply(
+                c,
+                xcb_damage_query_version(c, 1, 1),
+                ptr::null_mut(),
+            );
+            if reply.is_null() {
+                return None;
+            }
+            libc::free(reply as *mut _);
+
+            // Only the accumulated region is used, non-empty is the least chatty level.
+            let damage = xcb_generate_id(c);
+            let cookie =
+                xcb_damage_create_checked(c, damage, root, XCB_DAMAGE_REPORT_LEVEL_NON_EMPTY);
+            let e = xcb_request_check(c, cookie);
+            if !e.is_null() {
+                libc::free(e as *mut _);
+                return None;
+            }
+            let region = xcb_generate_id(c);
+            xcb_xfixes_create_region(c, region, 0, ptr::null());
+            Some(Damage {
+                server,
+                damage,
+                region,
+            })
+        }
+    }
+
+    /// The regions of `rect` changed since the last call, relative to it. `None` if unknown.
+    pub fn take(&self, rect: Rect) -> Option<Vec<DirtyRect>> {
+        let c = self.server.raw();
+        unsafe {
+            xcb_damage_subtract(c, self.damage, XCB_NONE, self.region);
+            let reply = xcb_xfixes_fetch_region_reply(
+                c,
+                xcb_xfixes_fetch_region(c, self.region),
+                ptr::null_mut(),
+            );
+            // Only the damage notify events come on this connection, they are not used.
+            loop {
+                let event = xcb_poll_for_event(c);
+                if event.is_null() {
+                    break;
+                }
+                libc::free(event as *mut _);
+            }
+            if reply.is_null() {
+                return None;
+            }
+            let rects = slice::from_raw_parts(
+                xcb_xfixes_fetch_region_rectangles(reply),
+                xcb_xfixes_fetch_region_rectangles_length(reply).max(0) as usize,
+            );
+            let dirty = clip(rects, rect);
+            libc::free(reply as *mut _);
+            Some(dirty)
+        }
+    }
+}
+
+fn clip(rects: &[xcb_rectangle_t], display: Rect) -> Vec<DirtyRect> {
+    let (left, top) = (display.x as i32, display.y as i32);
+    let (right, bottom) = (left + display.w as i32, top + display.h as i32);
+    rects
+        .iter()
+        .filter_map(|r| {
+            let x0 = (r.x as i32).max(left);
+            let y0 = (r.y as i32).max(top);
+            let x1 = (r.x as i32 + r.width as i32).min(right);
+            let y1 = (r.y as i32 + r.height as i32).min(bottom);
+            if x0 >= x1 || y0 >= y1 {
+                return None;
+            }
+            Some(DirtyRect {
+                x: (x0 - left) as usize,
+                y: (y0 - top) as usize,
+                w: (x1 - x0) as usize,
+                h: (y1 - y0) as usize,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn damage_is_clipped_to_the_display() {
+        let display = Rect {
+            x: 1920,
+            y: 0,
+            w: 1280,
+            h: 1024,
+        };
+        let rect = |x, y, width, height| xcb_rectangle_t {
+            x,
+            y,
+            width,
+            height,
+        };
+        let rects = [
+            // On the other monitor.
+            rect(0, 0, 100, 100),
+            // Across both.
+            rect(1900, 10, 40, 20),
+            rect(3000, 1000, 500, 500),
+        ];
+        assert_eq!(
+            clip(&rects, display),
+            vec![
+                DirtyRect {
+                    x: 0,
+                    y: 10,
+                    w: 20,
+                    h: 20
+                },
+                DirtyRect {
+                    x: 1080,
+                    y: 1000,
+                    w: 200,
+                    h: 24
+                },
+            ]
+        );
+    }
+}
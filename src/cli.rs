@@ -0,0 +1,1028 @@
+//! Headless command line client, `rustdesk --cli <id> [options] [commands]`.
+//!
+//! The session is driven by the regular [`Session`]/`io_loop` machinery, with [`CliHandler`]
+//! standing in for the Flutter/Sciter UI. UI callbacks are turned into [`Event`]s and consumed
+//! on the calling thread, which runs the requested commands one by one and maps the outcome
+//! to a process exit code, so the client can be used from cron jobs and CI pipelines.
+#[cfg(not(windows))]
+mod terminal;
+
+use crate::{
+    client::{mirror, Data, Interface, QualityStatus},
+    ui_session_interface::{InvokeUiSession, Session},
+};
+use hbb_common::{config::keys, fs, log, message_proto::*, rendezvous_proto::ConnType};
+#[cfg(all(feature = "vram", feature = "flutter"))]
+use std::ffi::c_void;
+use std::{
+    sync::{atomic::AtomicUsize, mpsc, Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+const EXIT_OK: i32 = 0;
+const EXIT_ERROR: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_LOGIN: i32 = 3;
+const EXIT_CONNECTION: i32 = 4;
+const EXIT_TRANSFER: i32 = 5;
+const EXIT_TIMEOUT: i32 = 6;
+
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 60;
+const CLOSE_WAIT: Duration = Duration::from_secs(3);
+
+const USAGE: &str = r#"Usage: rustdesk --cli <id> [options] [commands]
+
+Options:
+  --password <password>      Password of the remote peer
+  --password-env <name>      Read the password from the environment variable <name>
+  --relay                    Force relay connection
+  --allow-insecure           Continue if the connection is not end-to-end encrypted
+  --wait-accept              Wait for the remote side to accept if no password is available
+  --hidden                   Include hidden files
+  --overwrite                Overwrite existing files
+  --skip-existing            Skip existing files
+  --dry-run                  Only print the plan of the mirror commands
+  --checksum                 Mirror commands also compare unchanged files by SHA-256
+  --timeout <seconds>        Give up if nothing happens for <seconds> (default: 60)
+
+Terminal (not available on Windows):
+  --terminal                 Open an interactive remote terminal on the local TTY
+  --service-id <service-id>  Reattach to an existing terminal service
+  --new                      Do not reattach to the last terminal service of this peer
+  --persistent               Keep the remote terminal alive after detaching (saved for the peer)
+  --profile <name>           Launch profile of the remote terminal, e.g. tmux
+  --escape <char|none>       Escape character, default: ~
+                             <Enter><char>. detaches, <char><char> sends <char> itself
+
+Commands (executed in order, over one file transfer session):
+  --ls <remote-dir>          List a remote directory
+  --get <remote> <local>     Download <remote> to the local path <local>
+  --put <local> <remote>     Upload <local> to the remote path <remote>
+  --mkdir <remote-dir>       Create a remote directory
+  --rm <remote-file>         Remove a remote file
+  --rmdir <remote-dir>       Remove a remote directory recursively
+  --mirror-push <local-dir> <remote-dir>
+                             Make <remote-dir> a copy of <local-dir>, deleting extra files
+  --mirror-pull <remote-dir> <local-dir>
+                             Make <local-dir> a copy of <remote-dir>, deleting extra files
+
+Mirror commands compare files by size and modification time. With --checksum, the remote
+copy of each file that looks unchanged is downloaded to compare the content. A missing
+destination directory is created. Directories are never deleted.
+
+Without commands, the client logs in, prints the peer information and exits.
+In terminal mode, the exit code of the remote shell is returned.
+
+Exit codes:
+  0 success, 1 error, 2 usage, 3 login failed, 4 connection failed,
+  5 file transfer failed, 6 timeout"#;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    List(String),
+    Get(String, String),
+    Put(String, String),
+    Mkdir(String),
+    Remove(String),
+    RemoveDir(String),
+    MirrorPush(String, String),
+    MirrorPull(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Options {
+    id: String,
+    password: String,
+    force_relay: bool,
+    allow_insecure: bool,
+    wait_accept: bool,
+    include_hidden: bool,
+    overwrite: Option<bool>,
+    dry_run: bool,
+    checksum: bool,
+    idle_timeout: Duration,
+    commands: Vec<Command>,
+    terminal: bool,
+    service_id: Option<String>,
+    persistent: bool,
+    profile: String,
+    escape: Option<u8>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            id: Default::default(),
+            password: Default::default(),
+            force_relay: false,
+            allow_insecure: false,
+            wait_accept: false,
+            include_hidden: false,
+            overwrite: None,
+            dry_run: false,
+            checksum: false,
+            idle_timeout: Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECS),
+            commands: Default::default(),
+            terminal: false,
+            service_id: None,
+            persistent: false,
+            profile: Default::default(),
+            escape: Some(b'~'),
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options::default();
+    let mut it = args.iter();
+    let value = |it: &mut std::slice::Iter<String>, name: &str| -> Result<String, String> {
+        it.next()
+            .map(|v| v.to_owned())
+            .ok_or_else(|| format!("{} requires a value", name))
+    };
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--password" => opts.password = value(&mut it, arg)?,
+            "--password-env" => {
+                let name = value(&mut it, arg)?;
+                opts.password = std::env::var(&name)
+                    .map_err(|_| format!("Environment variable {} is not set", name))?;
+            }
+            "--relay" => opts.force_relay = true,
+            "--allow-insecure" => opts.allow_insecure = true,
+            "--wait-accept" => opts.wait_accept = true,
+            "--hidden" => opts.include_hidden = true,
+            "--overwrite" => opts.overwrite = Some(true),
+            "--skip-existing" => opts.overwrite = Some(false),
+            "--dry-run" => opts.dry_run = true,
+            "--checksum" => opts.checksum = true,
+            "--timeout" => {
+                let secs = value(&mut it, arg)?;
+                let secs = secs
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("Invalid timeout: {}", secs))?;
+                opts.idle_timeout = Duration::from_secs(secs);
+            }
+            "--ls" => opts.commands.push(Command::List(value(&mut it, arg)?)),
+            "--get" => {
+                let remote = value(&mut it, arg)?;
+                opts.commands
+                    .push(Command::Get(remote, value(&mut it, arg)?));
+            }
+            "--put" => {
+                let local = value(&mut it, arg)?;
+                opts.commands
+                    .push(Command::Put(local, value(&mut it, arg)?));
+            }
+            "--mkdir" => opts.commands.push(Command::Mkdir(value(&mut it, arg)?)),
+            "--rm" => opts.commands.push(Command::Remove(value(&mut it, arg)?)),
+            "--rmdir" => opts.commands.push(Command::RemoveDir(value(&mut it, arg)?)),
+            "--mirror-push" => {
+                let local = value(&mut it, arg)?;
+                opts.commands
+                    .push(Command::MirrorPush(local, value(&mut it, arg)?));
+            }
+            "--mirror-pull" => {
+                let remote = value(&mut it, arg)?;
+                opts.commands
+                    .push(Command::MirrorPull(remote, value(&mut it, arg)?));
+            }
+            "--terminal" => opts.terminal = true,
+            "--service-id" => opts.service_id = Some(value(&mut it, arg)?),
+            "--new" => opts.service_id = Some("".to_owned()),
+            "--persistent" => opts.persistent = true,
+            "--profile" => opts.profile = value(&mut it, arg)?,
+            "--escape" => opts.escape = parse_escape(&value(&mut it, arg)?)?,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ if opts.id.is_empty() => opts.id = arg.to_owned(),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+    if opts.id.is_empty() {
+        return Err("Remote id is required".to_owned());
+    }
+    if opts.terminal && !opts.commands.is_empty() {
+        return Err("File transfer commands cannot be used with --terminal".to_owned());
+    }
+    Ok(opts)
+}
+
+// `none`, a single ASCII character, or `^X` for a control character.
+fn parse_escape(value: &str) -> Result<Option<u8>, String> {
+    let bytes = value.as_bytes();
+    match bytes {
+        _ if value == "none" => Ok(None),
+        [c] if c.is_ascii() => Ok(Some(*c)),
+        [b'^', c] if c.is_ascii_alphabetic() || b"[\\]^_".contains(c) => {
+            Ok(Some(c.to_ascii_uppercase() & 0x1f))
+        }
+        _ => Err(format!("Invalid escape character: {}", value)),
+    }
+}
+
+/// UI events forwarded from the io loop thread to the command runner.
+#[derive(Debug)]
+enum Event {
+    Msgbox {
+        msgtype: String,
+        title: String,
+        text: String,
+    },
+    PeerInfo(PeerInfo),
+    Connected,
+    FolderFiles {
+        id: i32,
+        entries: Vec<FileEntry>,
+        path: String,
+    },
+    // Only resets the idle timeout while a transfer is running.
+    JobProgress,
+    JobDone(i32),
+    JobError(i32, String),
+    OverrideConfirm {
+        id: i32,
+        file_num: i32,
+        to: String,
+        is_upload: bool,
+    },
+    Terminal(TerminalResponse),
+    Stdin(Vec<u8>),
+    StdinClosed,
+}
+
+#[derive(Clone, Default)]
+pub struct CliHandler {
+    events: Option<mpsc::Sender<Event>>,
+}
+
+impl CliHandler {
+    fn emit(&self, event: Event) {
+        if let Some(tx) = self.events.as_ref() {
+            tx.send(event).ok();
+        }
+    }
+}
+
+impl InvokeUiSession for CliHandler {
+    fn set_cursor_data(&self, _cd: CursorData) {}
+
+    fn set_cursor_id(&self, _id: String) {}
+
+    fn set_cursor_position(&self, _cp: CursorPosition) {}
+
+    fn set_display(&self, _x: i32, _y: i32, _w: i32, _h: i32, _cursor_embedded: bool, _scale: f64) {
+    }
+
+    fn switch_display(&self, _display: &SwitchDisplay) {}
+
+    fn set_peer_info(&self, peer_info: &PeerInfo) {
+        self.emit(Event::PeerInfo(peer_info.clone()));
+    }
+
+    fn set_displays(&self, _displays: &Vec<DisplayInfo>) {}
+
+    fn set_platform_additions(&self, _data: &str) {}
+
+    fn on_connected(&self, _conn_type: ConnType) {
+        self.emit(Event::Connected);
+    }
+
+    fn update_privacy_mode(&self) {}
+
+    fn set_permission(&self, _name: &str, _value: bool) {}
+
+    fn close_success(&self) {}
+
+    fn update_quality_status(&self, _qs: QualityStatus) {}
+
+    fn set_connection_type(&self, is_secured: bool, direct: bool, stream_type: &str) {
+        log::info!(
+            "cli connection type, secured: {}, direct: {}, stream: {}",
+            is_secured,
+            direct,
+            stream_type
+        );
+    }
+
+    fn set_fingerprint(&self, _fingerprint: String) {}
+
+    fn job_error(&self, id: i32, err: String, _file_num: i32) {
+        self.emit(Event::JobError(id, err));
+    }
+
+    fn job_done(&self, id: i32, _file_num: i32) {
+        self.emit(Event::JobDone(id));
+    }
+
+    fn clear_all_jobs(&self) {}
+
+    fn new_message(&self, msg: String) {
+        eprintln!("{}", msg);
+    }
+
+    fn update_transfer_list(&self) {}
+
+    fn load_last_job(&self, _cnt: i32, _job_json: &str, _auto_start: bool) {}
+
+    fn update_folder_files(
+        &self,
+        id: i32,
+        entries: &Vec<FileEntry>,
+        path: String,
+        is_local: bool,
+        _only_count: bool,
+    ) {
+        if !is_local {
+            self.emit(Event::FolderFiles {
+                id,
+                entries: entries.clone(),
+                path,
+            });
+        }
+    }
+
+    fn confirm_delete_files(&self, _id: i32, _i: i32, _name: String) {}
+
+    fn override_file_confirm(
+        &self,
+        id: i32,
+        file_num: i32,
+        to: String,
+        is_upload: bool,
+        _is_identical: bool,
+    ) {
+        self.emit(Event::OverrideConfirm {
+            id,
+            file_num,
+            to,
+            is_upload,
+        });
+    }
+
+    fn update_block_input_state(&self, _on: bool) {}
+
+    fn job_progress(&self, _id: i32, _file_num: i32, _speed: f64, _finished_size: f64) {
+        self.emit(Event::JobProgress);
+    }
+
+    fn adapt_size(&self) {}
+
+    fn on_rgba(&self, _display: usize, _rgba: &mut scrap::ImageRgb) {}
+
+    fn msgbox(&self, msgtype: &str, title: &str, text: &str, _link: &str, _retry: bool) {
+        self.emit(Event::Msgbox {
+            msgtype: msgtype.to_owned(),
+            title: title.to_owned(),
+            text: text.to_owned(),
+        });
+    }
+
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    fn clipboard(&self, _content: String) {}
+
+    fn cancel_msgbox(&self, _tag: &str) {}
+
+    fn switch_back(&self, _id: &str) {}
+
+    fn portable_service_running(&self, _running: bool) {}
+
+    fn on_voice_call_started(&self) {}
+
+    fn on_voice_call_closed(&self, _reason: &str) {}
+
+    fn on_voice_call_waiting(&self) {}
+
+    fn on_voice_call_incoming(&self) {}
+
+    fn get_rgba(&self, _display: usize) -> *const u8 {
+        std::ptr::null()
+    }
+
+    fn next_rgba(&self, _display: usize) {}
+
+    #[cfg(all(feature = "vram", feature = "flutter"))]
+    fn on_texture(&self, _display: usize, _texture: *mut c_void) {}
+
+    fn set_multiple_windows_session(&self, _sessions: Vec<WindowsSession>) {}
+
+    fn set_current_display(&self, _disp_idx: i32) {}
+
+    #[cfg(feature = "flutter")]
+    fn is_multi_ui_session(&self) -> bool {
+        false
+    }
+
+    fn update_record_status(&self, _start: bool) {}
+
+    fn printer_request(&self, _id: i32, _path: String) {}
+
+    fn handle_screenshot_resp(&self, _sid: String, _msg: String) {}
+
+    fn handle_terminal_response(&self, response: TerminalResponse) {
+        self.emit(Event::Terminal(response));
+    }
+}
+
+fn new_session(
+    opts: &Options,
+    conn_type: ConnType,
+    events: mpsc::Sender<Event>,
+) -> Session<CliHandler> {
+    let session: Session<CliHandler> = Session {
+        password: opts.password.clone(),
+        server_keyboard_enabled: Arc::new(RwLock::new(true)),
+        server_file_transfer_enabled: Arc::new(RwLock::new(true)),
+        server_clipboard_enabled: Arc::new(RwLock::new(true)),
+        reconnect_count: Arc::new(AtomicUsize::new(0)),
+        ui_handler: CliHandler {
+            events: Some(events),
+        },
+        ..Default::default()
+    };
+    let mut lc = session.lc.write().unwrap();
+    lc.initialize(
+        opts.id.clone(),
+        conn_type,
+        None,
+        opts.force_relay,
+        None,
+        None,
+        None,
+    );
+    if conn_type == ConnType::TERMINAL {
+        if let Some(service_id) = opts.service_id.as_ref() {
+            let key = lc.get_key_terminal_service_id().to_owned();
+            lc.set_option(key, service_id.clone());
+        }
+        if opts.persistent && !lc.get_toggle_option(keys::OPTION_TERMINAL_PERSISTENT) {
+            let mut config = lc.load_config();
+            config.terminal_persistent.v = true;
+            lc.save_config(config);
+        }
+    }
+    drop(lc);
+    session
+}
+
+/// Run the headless client and return the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let opts = match parse_args(args) {
+        Ok(opts) => opts,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    #[cfg(windows)]
+    if opts.terminal {
+        eprintln!("The terminal mode is not supported on Windows");
+        return EXIT_USAGE;
+    }
+    let conn_type = if opts.terminal {
+        ConnType::TERMINAL
+    } else {
+        ConnType::FILE_TRANSFER
+    };
+    let (tx, rx) = mpsc::channel();
+    let session = new_session(&opts, conn_type, tx);
+    session.reconnect(false);
+    let mut runner = Runner {
+        session: session.clone(),
+        events: rx,
+        opts,
+        next_job_id: 0,
+    };
+    let code = match runner.run() {
+        Ok(()) => EXIT_OK,
+        Err(code) => code,
+    };
+    close_session(&session);
+    code
+}
+
+fn close_session(session: &Session<CliHandler>) {
+    session.close();
+    // Give the io loop a chance to send the close reason and save the job state.
+    if let Some(handle) = session.thread.lock().unwrap().take() {
+        let start = Instant::now();
+        while !handle.is_finished() && start.elapsed() < CLOSE_WAIT {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+fn fail(code: i32, msg: &str) -> i32 {
+    eprintln!("{}", msg);
+    code
+}
+
+fn is_fatal_msgbox(msgtype: &str) -> bool {
+    msgtype.contains("error")
+        || msgtype.starts_with("relay-hint")
+        || msgtype.starts_with("restarting")
+}
+
+struct Runner {
+    session: Session<CliHandler>,
+    events: mpsc::Receiver<Event>,
+    opts: Options,
+    next_job_id: i32,
+}
+
+impl Runner {
+    fn run(&mut self) -> Result<(), i32> {
+        self.wait_connected()?;
+        #[cfg(not(windows))]
+        if self.opts.terminal {
+            return terminal::run(self);
+        }
+        let commands = self.opts.commands.clone();
+        for cmd in commands {
+            self.exec(cmd)?;
+        }
+        Ok(())
+    }
+
+    fn next_event(&mut self) -> Result<Event, i32> {
+        match self.events.recv_timeout(self.opts.idle_timeout) {
+            Ok(Event::Msgbox {
+                msgtype,
+                title,
+                text,
+            }) => {
+                self.check_msgbox(&msgtype, &title, &text)?;
+                Ok(Event::Msgbox {
+                    msgtype,
+                    title,
+                    text,
+                })
+            }
+            Ok(event) => Ok(event),
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(fail(EXIT_TIMEOUT, "Timeout")),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                Err(fail(EXIT_CONNECTION, "Connection closed"))
+            }
+        }
+    }
+
+    fn check_msgbox(&self, msgtype: &str, title: &str, text: &str) -> Result<(), i32> {
+        let tr = |s: &str| crate::client::translate(s.to_owned());
+        match msgtype {
+            "input-password" => {
+                if self.opts.wait_accept {
+                    eprintln!(
+                        "{}",
+                        tr("Please wait for the remote side to accept your session request...")
+                    );
+                    Ok(())
+                } else {
+                    Err(fail(EXIT_LOGIN, &tr("Password Required")))
+                }
+            }
+            "re-input-password" => Err(fail(EXIT_LOGIN, &tr(title))),
+            "input-2fa" => Err(fail(EXIT_LOGIN, &tr("2FA code"))),
+            "wait-remote-accept-nook" => {
+                eprintln!("{}", tr(text));
+                Ok(())
+            }
+            "insecure-connection-nocancel-hasclose" => {
+                self.session
+                    .continue_insecure_connection(self.opts.allow_insecure);
+                if self.opts.allow_insecure {
+                    Ok(())
+                } else {
+                    Err(fail(EXIT_CONNECTION, &tr(text)))
+                }
+            }
+            _ if is_fatal_msgbox(msgtype) => {
+                let code = if title == "Login Error" {
+                    EXIT_LOGIN
+                } else {
+                    EXIT_CONNECTION
+                };
+                Err(fail(code, &format!("{}: {}", tr(title), tr(text))))
+            }
+            _ => {
+                log::info!("cli msgbox: {} {} {}", msgtype, title, text);
+                Ok(())
+            }
+        }
+    }
+
+    fn wait_connected(&mut self) -> Result<(), i32> {
+        loop {
+            match self.next_event()? {
+                Event::PeerInfo(pi) => {
+                    if self.opts.commands.is_empty() && !self.opts.terminal {
+                        println!("id: {}", self.opts.id);
+                        println!("hostname: {}", pi.hostname);
+                        println!("username: {}", pi.username);
+                        println!("platform: {}", pi.platform);
+                        println!("version: {}", pi.version);
+                    }
+                }
+                Event::Connected => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    fn new_job_id(&mut self) -> i32 {
+        self.next_job_id += 1;
+        self.next_job_id
+    }
+
+    fn exec(&mut self, cmd: Command) -> Result<(), i32> {
+        let hidden = self.opts.include_hidden;
+        match cmd {
+            Command::List(path) => {
+                let mut file_action = FileAction::new();
+                file_action.set_read_dir(ReadDir {
+                    path: path.clone(),
+                    include_hidden: hidden,
+                    ..Default::default()
+                });
+                let mut msg_out = Message::new();
+                msg_out.set_file_action(file_action);
+                self.session.send(Data::Message(msg_out));
+                self.wait_dir(&path)
+            }
+            Command::Get(remote, local) => {
+                let id = self.new_job_id();
+                self.session.send(Data::SendFiles((
+                    id,
+                    fs::JobType::Generic,
+                    remote.clone(),
+                    local.clone(),
+                    0,
+                    hidden,
+                    true,
+                )));
+                self.wait_job(
+                    id,
+                    &format!("get {} -> {}", remote, local),
+                    self.opts.overwrite,
+                )
+            }
+            Command::Put(local, remote) => {
+                let id = self.new_job_id();
+                self.session.send(Data::SendFiles((
+                    id,
+                    fs::JobType::Generic,
+                    local.clone(),
+                    remote.clone(),
+                    0,
+                    hidden,
+                    false,
+                )));
+                self.wait_job(
+                    id,
+                    &format!("put {} -> {}", local, remote),
+                    self.opts.overwrite,
+                )
+            }
+            Command::Mkdir(path) => {
+                let id = self.new_job_id();
+                self.session.send(Data::CreateDir((id, path.clone(), true)));
+                self.wait_job(id, &format!("mkdir {}", path), None)
+            }
+            Command::Remove(path) => {
+                let id = self.new_job_id();
+                self.session
+                    .send(Data::RemoveFile((id, path.clone(), 0, true)));
+                self.wait_job(id, &format!("rm {}", path), None)
+            }
+            Command::RemoveDir(path) => {
+                let id = self.new_job_id();
+                self.session.send(Data::RemoveDir((id, path.clone())));
+                self.wait_job(id, &format!("rmdir {}", path), None)
+            }
+            Command::MirrorPush(local, remote) => self.mirror(&local, &remote, true),
+            Command::MirrorPull(remote, local) => self.mirror(&remote, &local, false),
+        }
+    }
+
+    fn list_local_files(&self, path: &str) -> Result<Vec<FileEntry>, i32> {
+        if !std::path::Path::new(path).exists() {
+            return Ok(vec![]);
+        }
+        fs::get_recursive_files(path, self.opts.include_hidden)
+            .map_err(|e| fail(EXIT_TRANSFER, &format!("list {}: {}", path, e)))
+    }
+
+    // The inner error is the one of the peer, for the caller to decide if it is fatal.
+    fn list_remote_files(&mut self, path: &str) -> Result<Result<Vec<FileEntry>, String>, i32> {
+        let id = self.new_job_id();
+        let mut file_action = FileAction::new();
+        file_action.set_all_files(ReadAllFiles {
+            id,
+            path: path.to_owned(),
+            include_hidden: self.opts.include_hidden,
+            ..Default::default()
+        });
+        let mut msg_out = Message::new();
+        msg_out.set_file_action(file_action);
+        self.session.send(Data::Message(msg_out));
+        loop {
+            match self.next_event()? {
+                Event::FolderFiles {
+                    id: job_id,
+                    entries,
+                    ..
+                } if job_id == id => return Ok(Ok(entries)),
+                Event::JobError(job_id, err) if job_id == id => return Ok(Err(err)),
+                _ => {}
+            }
+        }
+    }
+
+    // The destination is created if it is missing, a dry run only assumes it is empty.
+    fn list_remote_destination(&mut self, path: &str) -> Result<Vec<FileEntry>, i32> {
+        if !self.opts.dry_run {
+            let id = self.new_job_id();
+            self.session.send(Data::CreateDir((id, path.to_owned(), true)));
+            self.wait_job(id, &format!("mkdir {}", path), None)?;
+        }
+        match self.list_remote_files(path)? {
+            Ok(entries) => Ok(entries),
+            Err(err) if self.opts.dry_run => {
+                println!("list {}: {}, assuming it does not exist", path, err);
+                Ok(vec![])
+            }
+            Err(err) => Err(fail(EXIT_TRANSFER, &format!("list {}: {}", path, err))),
+        }
+    }
+
+    // Downloads the remote copy of each of `names` to compare it with the local one, returns
+    // the names whose content differs.
+    fn compare_digests(
+        &mut self,
+        remote_dir: &str,
+        local_dir: &str,
+        names: &[String],
+    ) -> Result<Vec<String>, i32> {
+        let remote_sep = self.session.get_path_sep(true);
+        let local_sep = self.session.get_path_sep(false);
+        let tmp = std::env::temp_dir().join(format!("rustdesk-mirror-{}", std::process::id()));
+        let tmp = tmp.to_string_lossy().to_string();
+        let mut changed = vec![];
+        for name in names.iter() {
+            let remote = mirror::join(remote_dir, name, remote_sep);
+            let id = self.new_job_id();
+            self.session.send(Data::SendFiles((
+                id,
+                fs::JobType::Generic,
+                remote.clone(),
+                tmp.clone(),
+                0,
+                self.opts.include_hidden,
+                true,
+            )));
+            let res = self
+                .wait_job(id, &format!("checksum {}", remote), Some(true))
+                .and_then(|_| {
+                    let local = mirror::join(local_dir, name, local_sep);
+                    let digests = mirror::digest(&tmp).and_then(|remote_digest| {
+                        Ok((remote_digest, mirror::digest(&local)?))
+                    });
+                    digests.map_err(|e| fail(EXIT_TRANSFER, &format!("checksum {}: {}", name, e)))
+                });
+            std::fs::remove_file(&tmp).ok();
+            let (remote_digest, local_digest) = res?;
+            if remote_digest != local_digest {
+                changed.push(name.clone());
+            }
+        }
+        Ok(changed)
+    }
+
+    // Copies `src` to `dst` and deletes the files of `dst` which are not in `src`.
+    fn mirror(&mut self, src: &str, dst: &str, is_upload: bool) -> Result<(), i32> {
+        let (src_files, dst_files) = if is_upload {
+            let src_files = self.list_local_files(src)?;
+            (src_files, self.list_remote_destination(dst)?)
+        } else {
+            let src_files = self
+                .list_remote_files(src)?
+                .map_err(|err| fail(EXIT_TRANSFER, &format!("list {}: {}", src, err)))?;
+            (src_files, self.list_local_files(dst)?)
+        };
+        let mut plan = mirror::plan(&src_files, &dst_files)
+            .map_err(|e| fail(EXIT_TRANSFER, &format!("mirror {} -> {}: {}", src, dst, e)))?;
+        if self.opts.checksum && !plan.unchanged.is_empty() {
+            let (remote_dir, local_dir) = if is_upload { (dst, src) } else { (src, dst) };
+            let unchanged = std::mem::take(&mut plan.unchanged);
+            plan.updates
+                .extend(self.compare_digests(remote_dir, local_dir, &unchanged)?);
+            plan.updates.sort();
+        }
+        for name in plan.adds.iter() {
+            println!("+ {}", name);
+        }
+        for name in plan.updates.iter() {
+            println!("~ {}", name);
+        }
+        for name in plan.deletes.iter() {
+            println!("- {}", name);
+        }
+        println!(
+            "mirror {} -> {}: {} to add, {} to update, {} to delete",
+            src,
+            dst,
+            plan.adds.len(),
+            plan.updates.len(),
+            plan.deletes.len()
+        );
+        if self.opts.dry_run || plan.is_empty() {
+            return Ok(());
+        }
+        let src_sep = self.session.get_path_sep(!is_upload);
+        let dst_sep = self.session.get_path_sep(is_upload);
+        for name in plan.adds.iter().chain(plan.updates.iter()) {
+            let from = mirror::join(src, name, src_sep);
+            let to = mirror::join(dst, name, dst_sep);
+            let id = self.new_job_id();
+            self.session.send(Data::SendFiles((
+                id,
+                fs::JobType::Generic,
+                from.clone(),
+                to.clone(),
+                0,
+                self.opts.include_hidden,
+                !is_upload,
+            )));
+            self.wait_job(id, &format!("copy {} -> {}", from, to), Some(true))?;
+        }
+        for name in plan.deletes.iter() {
+            let path = mirror::join(dst, name, dst_sep);
+            if is_upload {
+                let id = self.new_job_id();
+                self.session
+                    .send(Data::RemoveFile((id, path.clone(), 0, true)));
+                self.wait_job(id, &format!("rm {}", path), None)?;
+            } else {
+                std::fs::remove_file(&path)
+                    .map_err(|e| fail(EXIT_TRANSFER, &format!("rm {}: {}", path, e)))?;
+                println!("rm {}: done", path);
+            }
+        }
+        Ok(())
+    }
+
+    fn wait_dir(&mut self, path: &str) -> Result<(), i32> {
+        loop {
+            match self.next_event()? {
+                Event::FolderFiles {
+                    id: 0,
+                    entries,
+                    path: p,
+                } if p == path => {
+                    for entry in entries.iter() {
+                        let is_dir = matches!(
+                            entry.entry_type.enum_value(),
+                            Ok(FileType::Dir) | Ok(FileType::DirLink) | Ok(FileType::DirDrive)
+                        );
+                        println!(
+                            "{} {:>14} {:>12} {}",
+                            if is_dir { "d" } else { "-" },
+                            entry.size,
+                            entry.modified_time,
+                            entry.name
+                        );
+                    }
+                    return Ok(());
+                }
+                Event::JobError(0, err) => {
+                    return Err(fail(EXIT_TRANSFER, &format!("ls {}: {}", path, err)));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn wait_job(&mut self, id: i32, desc: &str, overwrite: Option<bool>) -> Result<(), i32> {
+        loop {
+            match self.next_event()? {
+                Event::JobDone(job_id) if job_id == id => {
+                    println!("{}: done", desc);
+                    return Ok(());
+                }
+                Event::JobError(job_id, err) if job_id == id => {
+                    return Err(fail(EXIT_TRANSFER, &format!("{}: {}", desc, err)));
+                }
+                Event::OverrideConfirm {
+                    id: job_id,
+                    file_num,
+                    to,
+                    is_upload,
+                } if job_id == id => match overwrite {
+                    Some(need_override) => {
+                        self.session.send(Data::SetConfirmOverrideFile((
+                            id,
+                            file_num,
+                            need_override,
+                            true,
+                            is_upload,
+                        )));
+                    }
+                    None => {
+                        self.session.send(Data::CancelJob(id));
+                        return Err(fail(
+                            EXIT_TRANSFER,
+                            &format!(
+                                "{}: {} already exists, use --overwrite or --skip-existing",
+                                desc, to
+                            ),
+                        ));
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_args_collects_commands_in_order() {
+        let opts = parse_args(&args(&[
+            "123456789",
+            "--password",
+            "secret",
+            "--overwrite",
+            "--mkdir",
+            "/tmp/backup",
+            "--get",
+            "/etc/hosts",
+            "/tmp/hosts",
+            "--ls",
+            "/tmp",
+        ]))
+        .unwrap();
+        assert_eq!(opts.id, "123456789");
+        assert_eq!(opts.password, "secret");
+        assert_eq!(opts.overwrite, Some(true));
+        assert_eq!(
+            opts.commands,
+            vec![
+                Command::Mkdir("/tmp/backup".to_owned()),
+                Command::Get("/etc/hosts".to_owned(), "/tmp/hosts".to_owned()),
+                Command::List("/tmp".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_args_accepts_mirror_commands() {
+        let opts = parse_args(&args(&[
+            "123456789",
+            "--dry-run",
+            "--checksum",
+            "--mirror-push",
+            "/etc/app",
+            "/etc/app",
+            "--mirror-pull",
+            "C:\\data",
+            "/tmp/data",
+        ]))
+        .unwrap();
+        assert!(opts.dry_run);
+        assert!(opts.checksum);
+        assert_eq!(
+            opts.commands,
+            vec![
+                Command::MirrorPush("/etc/app".to_owned(), "/etc/app".to_owned()),
+                Command::MirrorPull("C:\\data".to_owned(), "/tmp/data".to_owned()),
+            ]
+        );
+        assert!(parse_args(&args(&["123", "--mirror-pull", "/etc/app"])).is_err());
+    }
+
+    #[test]
+    fn parse_args_rejects_invalid_input() {
+        assert!(parse_args(&args(&[])).is_err());
+        assert!(parse_args(&args(&["123", "--get", "/etc/hosts"])).is_err());
+        assert!(parse_args(&args(&["123", "--timeout", "0"])).is_err());
+        assert!(parse_args(&args(&["123", "456"])).is_err());
+        assert!(parse_args(&args(&["123", "--unknown"])).is_err());
+        assert!(parse_args(&args(&["123", "--terminal", "--ls", "/"])).is_err());
+        assert!(parse_args(&args(&["123", "--escape", "ab"])).is_err());
+    }
+
+    #[test]
+    fn parse_escape_accepts_chars_and_control_chars() {
+        assert_eq!(parse_escape("~"), Ok(Some(b'~')));
+        assert_eq!(parse_escape("^]"), Ok(Some(0x1d)));
+        assert_eq!(parse_escape("^a"), Ok(Some(0x01)));
+        assert_eq!(parse_escape("none"), Ok(None));
+    }
+}
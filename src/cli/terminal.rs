@@ -0,0 +1,324 @@
+//! Interactive remote terminal for `rustdesk --cli <id> --terminal`.
+//!
+//! The local TTY is put in raw mode and bridged to terminal 1 of the remote
+//! terminal service. Window size changes are forwarded, and the session can be
+//! detached with `<Enter><escape>.` while a persistent service keeps running.
+
+use super::{fail, is_fatal_msgbox, Event, Runner, EXIT_CONNECTION, EXIT_ERROR, EXIT_OK};
+use crate::client::{Data, Interface};
+use hbb_common::{bytes::Bytes, compress::decompress, libc, log, message_proto::*};
+use std::{
+    io::{Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    time::{Duration, Instant},
+};
+
+const TERMINAL_ID: i32 = 1;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// Replies of the local terminal to queries in the replayed output are stale, drop them.
+const REPLAY_INPUT_GRACE: Duration = Duration::from_millis(300);
+const DEFAULT_SIZE: (u32, u32) = (24, 80);
+
+static WINDOW_RESIZED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigwinch(_: libc::c_int) {
+    WINDOW_RESIZED.store(true, Ordering::SeqCst);
+}
+
+enum Exit {
+    Detached,
+    Closed(i32),
+    Msgbox(String, String, String),
+    Error(String),
+}
+
+pub(super) fn run(runner: &mut Runner) -> Result<(), i32> {
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } == 0 {
+        return Err(fail(
+            EXIT_ERROR,
+            "The terminal mode requires a TTY on stdin",
+        ));
+    }
+    let (rows, cols) = window_size();
+    open_terminal(runner, rows, cols);
+    unsafe {
+        libc::signal(libc::SIGWINCH, on_sigwinch as libc::sighandler_t);
+    }
+    if let Some(tx) = runner.session.ui_handler.events.clone() {
+        std::thread::spawn(move || read_stdin(tx));
+    }
+    let exit = {
+        let _raw = RawMode::enter().map_err(|e| fail(EXIT_ERROR, &e.to_string()))?;
+        event_loop(runner)
+    };
+    unsafe {
+        libc::signal(libc::SIGWINCH, libc::SIG_DFL);
+    }
+    match exit {
+        Exit::Detached => {
+            let lc = runner.session.lc.read().unwrap();
+            let service_id = lc.get_option(lc.get_key_terminal_service_id());
+            eprintln!("\r\nDetached from terminal service {}", service_id);
+            if !service_id.is_empty() {
+                eprintln!(
+                    "Reattach with: rustdesk --cli {} --terminal --service-id {}",
+                    runner.opts.id, service_id
+                );
+            }
+            Ok(())
+        }
+        Exit::Closed(code) => {
+            if code == EXIT_OK {
+                Ok(())
+            } else {
+                Err(code)
+            }
+        }
+        Exit::Msgbox(msgtype, title, text) => {
+            runner.check_msgbox(&msgtype, &title, &text)?;
+            Err(EXIT_CONNECTION)
+        }
+        Exit::Error(msg) => Err(fail(EXIT_ERROR, &msg)),
+    }
+}
+
+fn event_loop(runner: &mut Runner) -> Exit {
+    let mut stdout = std::io::stdout();
+    let mut parser = EscapeParser::new(runner.opts.escape);
+    let mut drop_input_until: Option<Instant> = None;
+    loop {
+        if WINDOW_RESIZED.swap(false, Ordering::SeqCst) {
+            let (rows, cols) = window_size();
+            runner.session.resize_terminal(TERMINAL_ID, rows, cols);
+        }
+        let event = match runner.events.recv_timeout(POLL_INTERVAL) {
+            Ok(event) => event,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return Exit::Error("Connection closed".to_owned())
+            }
+        };
+        match event {
+            Event::Msgbox {
+                msgtype,
+                title,
+                text,
+            } => {
+                if is_fatal_msgbox(&msgtype) {
+                    return Exit::Msgbox(msgtype, title, text);
+                }
+                log::info!("{}: {}", title, text);
+            }
+            Event::Terminal(response) => match response.union {
+                Some(terminal_response::Union::Opened(opened)) => {
+                    if !opened.success {
+                        return Exit::Error(format!("Failed to open terminal: {}", opened.message));
+                    }
+                    if opened.replay_terminal_output {
+                        drop_input_until = Some(Instant::now() + REPLAY_INPUT_GRACE);
+                    }
+                }
+                Some(terminal_response::Union::Data(data)) => {
+                    if data.terminal_id != TERMINAL_ID {
+                        continue;
+                    }
+                    let bytes = if data.compressed {
+                        decompress(&data.data)
+                    } else {
+                        data.data.to_vec()
+                    };
+                    if stdout
+                        .write_all(&bytes)
+                        .and_then(|_| stdout.flush())
+                        .is_err()
+                    {
+                        return Exit::Error("Failed to write to stdout".to_owned());
+                    }
+                }
+                Some(terminal_response::Union::Closed(closed)) => {
+                    if closed.terminal_id == TERMINAL_ID {
+                        return Exit::Closed(closed.exit_code);
+                    }
+                }
+                Some(terminal_response::Union::Error(err)) => {
+                    if err.terminal_id == TERMINAL_ID {
+                        return Exit::Error(err.message);
+                    }
+                }
+                _ => {}
+            },
+            Event::Stdin(input) => {
+                if let Some(until) = drop_input_until {
+                    if Instant::now() < until {
+                        continue;
+                    }
+                    drop_input_until = None;
+                }
+                let (data, detach) = parser.feed(&input);
+                if !data.is_empty() {
+                    send_input(runner, data);
+                }
+                if detach {
+                    return Exit::Detached;
+                }
+            }
+            Event::StdinClosed => return Exit::Detached,
+            _ => {}
+        }
+    }
+}
+
+fn open_terminal(runner: &Runner, rows: u32, cols: u32) {
+    let mut action = TerminalAction::new();
+    action.set_open(OpenTerminal {
+        terminal_id: TERMINAL_ID,
+        rows,
+        cols,
+        profile: runner.opts.profile.clone(),
+        ..Default::default()
+    });
+    let mut msg_out = Message::new();
+    msg_out.set_terminal_action(action);
+    runner.session.send(Data::Message(msg_out));
+}
+
+fn send_input(runner: &Runner, data: Vec<u8>) {
+    let mut action = TerminalAction::new();
+    action.set_data(TerminalData {
+        terminal_id: TERMINAL_ID,
+        data: Bytes::from(data),
+        ..Default::default()
+    });
+    let mut msg_out = Message::new();
+    msg_out.set_terminal_action(action);
+    runner.session.send(Data::Message(msg_out));
+}
+
+fn read_stdin(tx: mpsc::Sender<Event>) {
+    let mut stdin = std::io::stdin();
+    let mut buf = [0u8; 4096];
+    loop {
+        match stdin.read(&mut buf) {
+            Ok(0) | Err(_) => {
+                tx.send(Event::StdinClosed).ok();
+                break;
+            }
+            Ok(n) => {
+                if tx.send(Event::Stdin(buf[..n].to_vec())).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn window_size() -> (u32, u32) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+        && size.ws_row > 0
+        && size.ws_col > 0
+    {
+        (size.ws_row as _, size.ws_col as _)
+    } else {
+        DEFAULT_SIZE
+    }
+}
+
+struct RawMode(libc::termios);
+
+impl RawMode {
+    fn enter() -> std::io::Result<Self> {
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let saved = termios;
+        unsafe { libc::cfmakeraw(&mut termios) };
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self(saved))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0) };
+    }
+}
+
+/// ssh style escape handling, only recognized right after a newline.
+struct EscapeParser {
+    escape: Option<u8>,
+    at_line_start: bool,
+    pending: bool,
+}
+
+impl EscapeParser {
+    fn new(escape: Option<u8>) -> Self {
+        Self {
+            escape,
+            at_line_start: true,
+            pending: false,
+        }
+    }
+
+    /// Returns the bytes to send and whether the detach sequence was typed.
+    fn feed(&mut self, input: &[u8]) -> (Vec<u8>, bool) {
+        let Some(escape) = self.escape else {
+            return (input.to_vec(), false);
+        };
+        let mut out = Vec::with_capacity(input.len());
+        for &b in input {
+            if self.pending {
+                self.pending = false;
+                match b {
+                    b'.' => return (out, true),
+                    _ if b == escape => {
+                        out.push(b);
+                        self.at_line_start = false;
+                        continue;
+                    }
+                    _ => out.push(escape),
+                }
+            } else if self.at_line_start && b == escape {
+                self.pending = true;
+                continue;
+            }
+            out.push(b);
+            self.at_line_start = b == b'\r' || b == b'\n';
+        }
+        (out, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_parser_detaches_at_line_start() {
+        let mut parser = EscapeParser::new(Some(b'~'));
+        assert_eq!(parser.feed(b"ls\r"), (b"ls\r".to_vec(), false));
+        assert_eq!(parser.feed(b"~"), (vec![], false));
+        assert_eq!(parser.feed(b"."), (vec![], true));
+
+        let mut parser = EscapeParser::new(Some(b'~'));
+        assert_eq!(parser.feed(b"\r~."), (b"\r".to_vec(), true));
+    }
+
+    #[test]
+    fn escape_parser_passes_other_input_through() {
+        let mut parser = EscapeParser::new(Some(b'~'));
+        assert_eq!(parser.feed(b"cd ~.\r"), (b"cd ~.\r".to_vec(), false));
+        assert_eq!(parser.feed(b"~~"), (b"~".to_vec(), false));
+        assert_eq!(parser.feed(b"."), (b".".to_vec(), false));
+        assert_eq!(parser.feed(b"\r~x"), (b"\r~x".to_vec(), false));
+
+        let mut parser = EscapeParser::new(None);
+        assert_eq!(parser.feed(b"~."), (b"~.".to_vec(), false));
+    }
+}
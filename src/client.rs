@@ -74,7 +74,7 @@ use hbb_common::{
 };
 pub use helper::*;
 use scrap::{
-    codec::Decoder,
+    codec::{Decoder, BR_SCREEN_CONTENT, SCREEN_CONTENT_FLAG},
     record::{Recorder, RecorderContext},
     CodecFormat, ImageFormat, ImageRgb, ImageTexture,
 };
@@ -92,6 +92,7 @@ pub use super::lang::*;
 pub mod file_trait;
 pub mod helper;
 pub mod io_loop;
+pub mod mirror;
 pub mod screenshot;
 
 pub const MILLI1: Duration = Duration::from_millis(1);
@@ -121,6 +122,8 @@ pub const SCRAP_X11_REF_URL: &str = "https://rustdesk.com/docs/en/manual/linux/#
 
 #[cfg(not(target_os = "linux"))]
 pub const AUDIO_BUFFER_MS: usize = 3000;
+/// Per-peer option to leave the audio out of screen recordings.
+pub const OPTION_DISABLE_RECORD_AUDIO: &str = "disable-record-audio";
 
 #[cfg(feature = "flutter")]
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -244,30 +247,18 @@ impl Client {
             bail!("Incoming only mode");
         }
         // to-do: remember the port for each peer, so that we can retry easier
-        if hbb_common::is_ip_str(peer) {
-            return Ok((
-                (
-                    connect_tcp_local(check_port(peer, RELAY_PORT + 1), None, CONNECT_TIMEOUT)
-                        .await?,
-                    true,
-                    None,
-                    None,
-                    "TCP",
-                ),
-                (0, "".to_owned()),
-                false,
-            ));
-        }
-        // Allow connect to {domain}:{port}
-        if hbb_common::is_domain_port_str(peer) {
+        let direct_addr = if hbb_common::is_ip_str(peer) {
+            Some(check_port(peer, RELAY_PORT + 1))
+        } else if hbb_common::is_domain_port_str(peer) || crate::ws_fallback::is_ws_endpoint(peer) {
+            // Allow connect to {domain}:{port} and WebSocket endpoints
+            Some(peer.to_owned())
+        } else {
+            None
+        };
+        if let Some(addr) = direct_addr {
+            let (stream, typ) = Self::connect_direct(addr).await?;
             return Ok((
-                (
-                    connect_tcp_local(peer, None, CONNECT_TIMEOUT).await?,
-                    true,
-                    None,
-                    None,
-                    "TCP",
-                ),
+                (stream, true, None, None, typ),
                 (0, "".to_owned()),
                 false,
             ));
@@ -356,6 +347,24 @@ impl Client {
         }
     }
 
+    // A `ws://` or `wss://` address is a WebSocket endpoint of the peer, see `ws_fallback`,
+    // which is also tried when the TCP connection fails.
+    async fn connect_direct(addr: String) -> ResultType<(Stream, &'static str)> {
+        if crate::ws_fallback::is_ws_endpoint(&addr) {
+            return Ok((connect_tcp(addr, CONNECT_TIMEOUT).await?, "WebSocket"));
+        }
+        match connect_tcp_local(addr.clone(), None, CONNECT_TIMEOUT).await {
+            Ok(stream) => Ok((stream, "TCP")),
+            Err(e) => match crate::ws_fallback::direct_endpoint(&addr) {
+                Some(endpoint) => {
+                    log::info!("fall back to websocket: {}", endpoint);
+                    Ok((connect_tcp(endpoint, CONNECT_TIMEOUT).await?, "WebSocket"))
+                }
+                None => Err(e),
+            },
+        }
+    }
+
     async fn _start_inner(
         peer: String,
         key: String,
@@ -397,6 +406,16 @@ impl Client {
         } else if !contained {
             crate::refresh_rendezvous_server();
         }
+        if socket.is_err() {
+            if let Some(endpoint) = crate::ws_fallback::rendezvous_endpoint(&rendezvous_server) {
+                log::info!("fall back to websocket: {}", endpoint);
+                let ws_socket = connect_tcp(&*endpoint, CONNECT_TIMEOUT).await;
+                if ws_socket.is_ok() {
+                    socket = ws_socket;
+                    rendezvous_server = endpoint;
+                }
+            }
+        }
         log::info!("rendezvous server: {}", rendezvous_server);
         let mut socket = socket?;
         let my_addr = socket.local_addr();
@@ -408,7 +427,10 @@ impl Client {
         let mut is_local = false;
         let mut feedback = 0;
         use hbb_common::protobuf::Enum;
-        let nat_type = if interface.is_force_relay() {
+        // Only relay connections are possible over websocket.
+        let force_relay =
+            interface.is_force_relay() || crate::ws_fallback::is_ws_endpoint(&rendezvous_server);
+        let nat_type = if force_relay {
             NatType::SYMMETRIC
         } else {
             NatType::from_i32(my_nat_type).unwrap_or(NatType::UNKNOWN_NAT)
@@ -455,7 +477,7 @@ impl Client {
             conn_type: conn_type.into(),
             version: crate::VERSION.to_owned(),
             udp_port: udp_nat_port as _,
-            force_relay: interface.is_force_relay(),
+            force_relay,
             socket_addr_v6: ipv6.1.unwrap_or_default(),
             switch_code,
             ..Default::default()
@@ -540,10 +562,17 @@ impl Client {
                             }
                         }
                         signed_id_pk = rr.pk().into();
+                        let relay_server =
+                            crate::ws_fallback::relay_endpoint(&rendezvous_server, rr.relay_server);
+                        let typ = if use_ws() || crate::ws_fallback::is_ws_endpoint(&relay_server) {
+                            "WebSocket"
+                        } else {
+                            "Relay"
+                        };
                         let fut = Self::create_relay(
                             &peer,
                             rr.uuid,
-                            rr.relay_server,
+                            relay_server,
                             &key,
                             conn_type,
                             my_addr.is_ipv4(),
@@ -551,7 +580,7 @@ impl Client {
                         connect_futures.push(
                             async move {
                                 let conn = fut.await?;
-                                Ok((conn, None, if use_ws() { "WebSocket" } else { "Relay" }))
+                                Ok((conn, None, typ))
                             }
                             .boxed(),
                         );
@@ -888,11 +917,12 @@ impl Client {
         if !succeed {
             bail!("Timeout");
         }
+        let relay_server = crate::ws_fallback::relay_endpoint(rendezvous_server, relay_server);
         Self::create_relay(peer, uuid, relay_server, key, conn_type, ipv4).await
     }
 
     /// Create a relay connection to the server.
-    async fn create_relay(
+    pub(crate) async fn create_relay(
         peer: &str,
         uuid: String,
         relay_server: String,
@@ -900,12 +930,14 @@ impl Client {
         conn_type: ConnType,
         ipv4: bool,
     ) -> ResultType<Stream> {
-        let mut conn = connect_tcp(
-            ipv4_to_ipv6(check_port(relay_server, RELAY_PORT), ipv4),
-            CONNECT_TIMEOUT,
-        )
-        .await
-        .with_context(|| "Failed to connect to relay server")?;
+        let relay_server = if crate::ws_fallback::is_ws_endpoint(&relay_server) {
+            relay_server
+        } else {
+            ipv4_to_ipv6(check_port(relay_server, RELAY_PORT), ipv4)
+        };
+        let mut conn = connect_tcp(relay_server, CONNECT_TIMEOUT)
+            .await
+            .with_context(|| "Failed to connect to relay server")?;
         let mut msg_out = RendezvousMessage::new();
         msg_out.set_request_relay(RequestRelay {
             licence_key: key.to_owned(),
@@ -1560,6 +1592,7 @@ pub struct VideoHandler {
     pub texture: ImageTexture,
     recorder: Arc<Mutex<Option<Recorder>>>,
     record: bool,
+    record_audio: Option<AudioFormat>,
     _display: usize, // useful for debug
     fail_counter: usize,
     first_frame: bool,
@@ -1592,6 +1625,7 @@ impl VideoHandler {
             texture: Default::default(),
             recorder: Default::default(),
             record: false,
+            record_audio: None,
             _display,
             fail_counter: 0,
             first_frame: true,
@@ -1678,14 +1712,41 @@ impl VideoHandler {
                 display_idx,
                 camera,
                 tx: None,
+                public_key: crate::common::recording_public_key(),
             })
-            .map_or(Default::default(), |r| Arc::new(Mutex::new(Some(r))));
+            .map_or(Default::default(), |mut r| {
+                r.set_audio_format(self.record_audio.clone());
+                Arc::new(Mutex::new(Some(r)))
+            });
         } else {
             self.recorder = Default::default();
         }
 
         self.record = start;
     }
+
+    /// Set the audio format to record, `None` to record video only.
+    /// Returns true if a key frame is needed to start a new recording file.
+    pub fn set_record_audio(&mut self, format: Option<AudioFormat>) -> bool {
+        self.record_audio = format.clone();
+        self.recorder
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(|r| r.set_audio_format(format))
+            .unwrap_or_default()
+    }
+
+    /// Record an audio frame.
+    pub fn write_audio(&mut self, frame: &AudioFrame) {
+        if self.record {
+            self.recorder
+                .lock()
+                .unwrap()
+                .as_mut()
+                .map(|r| r.write_audio(&frame.data));
+        }
+    }
 }
 
 // The source of sent password
@@ -2266,6 +2327,8 @@ impl LoginConfigHandler {
         let q = self.image_quality.clone();
         if let Some(q) = self.get_image_quality_enum(&q, ignore_default) {
             msg.image_quality = q.into();
+        } else if q == "screen" {
+            msg.custom_image_quality = self.get_screen_content_image_quality();
         } else if q == "custom" {
             let config = self.load_config();
             let allow_more = !crate::using_public_server() || self.direct == Some(true);
@@ -2330,6 +2393,19 @@ impl LoginConfigHandler {
         )
     }
 
+    /// Get the custom image quality requesting the screen content profile.
+    /// The quality above the best one, near-lossless, is only requested on a direct connection or
+    /// with a self-hosted server, like the custom image quality.
+    fn get_screen_content_image_quality(&self) -> i32 {
+        let allow_more = !crate::using_public_server() || self.direct == Some(true);
+        let quality = if allow_more {
+            (BR_SCREEN_CONTENT * 50.0) as i32
+        } else {
+            100
+        };
+        quality << 8 | SCREEN_CONTENT_FLAG
+    }
+
     /// Parse the image quality option.
     /// Return [`ImageQuality`] if the option is valid, otherwise return `None`.
     ///
@@ -2448,12 +2524,22 @@ impl LoginConfigHandler {
     /// * `value` - The image quality.
     pub fn save_image_quality(&mut self, value: String) -> Option<Message> {
         let mut res = None;
-        if let Some(q) = self.get_image_quality_enum(&value, false) {
-            let mut misc = Misc::new();
-            misc.set_option(OptionMessage {
+        let option = if let Some(q) = self.get_image_quality_enum(&value, false) {
+            Some(OptionMessage {
                 image_quality: q.into(),
                 ..Default::default()
-            });
+            })
+        } else if value == "screen" {
+            Some(OptionMessage {
+                custom_image_quality: self.get_screen_content_image_quality(),
+                ..Default::default()
+            })
+        } else {
+            None
+        };
+        if let Some(option) = option {
+            let mut misc = Misc::new();
+            misc.set_option(option);
             let mut msg_out = Message::new();
             msg_out.set_misc(misc);
             res = Some(msg_out);
@@ -2892,6 +2978,19 @@ pub fn start_video_thread<F, T>(
         let mut count = 0;
         let mut duration = std::time::Duration::ZERO;
         let mut skip_beginning = 0;
+        let mut audio_format = None;
+        let record_audio = |audio_format: &Option<AudioFormat>| {
+            if session
+                .lc
+                .read()
+                .unwrap()
+                .get_toggle_option(OPTION_DISABLE_RECORD_AUDIO)
+            {
+                None
+            } else {
+                audio_format.clone()
+            }
+        };
         loop {
             if let Ok(data) = video_receiver.recv() {
                 match data {
@@ -2924,6 +3023,7 @@ pub fn start_video_thread<F, T>(
                             let record_state = session.lc.read().unwrap().record_state;
                             let record_permission = session.lc.read().unwrap().record_permission;
                             let id = session.lc.read().unwrap().id.clone();
+                            handler.set_record_audio(record_audio(&audio_format));
                             if record_state && record_permission {
                                 handler.record_screen(true, id, display, is_view_camera);
                             }
@@ -3006,9 +3106,23 @@ pub fn start_video_thread<F, T>(
                     MediaData::RecordScreen(start) => {
                         let id = session.lc.read().unwrap().id.clone();
                         if let Some(handler) = video_handler.as_mut() {
+                            handler.set_record_audio(record_audio(&audio_format));
                             handler.record_screen(start, id, display, is_view_camera);
                         }
                     }
+                    MediaData::AudioFormat(f) => {
+                        audio_format = Some(f);
+                        if let Some(handler) = video_handler.as_mut() {
+                            if handler.set_record_audio(record_audio(&audio_format)) {
+                                session.refresh_video(display as _);
+                            }
+                        }
+                    }
+                    MediaData::AudioFrame(af) => {
+                        if let Some(handler) = video_handler.as_mut() {
+                            handler.write_audio(&af);
+                        }
+                    }
                     _ => {}
                 }
             } else {
@@ -3851,6 +3965,7 @@ pub enum Data {
     SetConfirmOverrideFile((i32, i32, bool, bool, bool)),
     AddJob((i32, JobType, String, String, i32, bool, bool)),
     ResumeJob((i32, bool)),
+    SetJobPriority((i32, i32)),
     RecordScreen(bool),
     ElevateDirect,
     ElevateWithLogon(String, String),
@@ -159,6 +159,11 @@ pub trait FileManager: Interface {
         self.send(Data::ResumeJob((id, is_remote)));
     }
 
+    // Only upload jobs are scheduled locally.
+    fn set_job_priority(&self, id: i32, priority: i32) {
+        self.send(Data::SetJobPriority((id, priority)));
+    }
+
     fn set_confirm_override_file(
         &self,
         id: i32,
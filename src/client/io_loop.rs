@@ -8,6 +8,8 @@ use crate::{
         QualityStatus, MILLI1, SEC30,
     },
     common::get_default_sound_input,
+    file_delta,
+    transfer_limit::{TransferLimits, TransferScheduler},
     ui_session_interface::{InvokeUiSession, Session},
 };
 
@@ -69,6 +71,8 @@ pub struct Remote<T: InvokeUiSession> {
     read_jobs: Vec<fs::TransferJob>,
     write_jobs: Vec<fs::TransferJob>,
     remove_jobs: HashMap<i32, RemoveJob>,
+    deltas: file_delta::Deltas,
+    transfer_scheduler: TransferScheduler,
     timer: crate::RustDeskInterval,
     last_update_jobs_status: (Instant, HashMap<i32, u64>),
     is_connected: bool,
@@ -82,6 +86,8 @@ pub struct Remote<T: InvokeUiSession> {
     video_threads: HashMap<usize, VideoThread>,
     chroma: Arc<RwLock<Option<Chroma>>>,
     last_record_state: bool,
+    // For the recorders of the video threads started later.
+    audio_format: Option<AudioFormat>,
     sent_close_reason: bool,
 }
 
@@ -108,6 +114,7 @@ impl<T: InvokeUiSession> Remote<T> {
         receiver: mpsc::UnboundedReceiver<Data>,
         sender: mpsc::UnboundedSender<Data>,
     ) -> Self {
+        let transfer_limits = Self::transfer_limits(&handler);
         Self {
             handler,
             audio_sender: crate::client::start_audio_thread(),
@@ -116,6 +123,8 @@ impl<T: InvokeUiSession> Remote<T> {
             read_jobs: Vec::new(),
             write_jobs: Vec::new(),
             remove_jobs: Default::default(),
+            deltas: Default::default(),
+            transfer_scheduler: TransferScheduler::new(transfer_limits),
             timer: crate::rustdesk_interval(time::interval(SEC30)),
             last_update_jobs_status: (Instant::now(), Default::default()),
             is_connected: false,
@@ -131,10 +140,18 @@ impl<T: InvokeUiSession> Remote<T> {
             video_threads: Default::default(),
             chroma: Default::default(),
             last_record_state: false,
+            audio_format: None,
             sent_close_reason: false,
         }
     }
 
+    // Saved in the options of the peer config, so they can be changed while transferring.
+    // Only the uploads are paced here, the downloads are paced by the peer with its own limits.
+    fn transfer_limits(handler: &Session<T>) -> TransferLimits {
+        let lc = handler.lc.read().unwrap();
+        TransferLimits::from_options(|name| lc.get_option(name))
+    }
+
     pub async fn io_loop(&mut self, key: &str, token: &str, round: u32) {
         #[cfg(target_os = "windows")]
         let _file_clip_context_holder = {
@@ -286,7 +303,13 @@ impl<T: InvokeUiSession> Remote<T> {
                                 break;
                             }
                             if !self.read_jobs.is_empty() {
-                                if let Err(err) = fs::handle_read_jobs(&mut self.read_jobs, &mut peer).await {
+                                for msg in self.deltas.next_messages(&mut self.read_jobs).await {
+                                    allow_err!(peer.send(&msg).await);
+                                }
+                                let mut ready = self.transfer_scheduler.take_ready(&mut self.read_jobs, None);
+                                let res = fs::handle_read_jobs(&mut ready, &mut peer).await;
+                                self.transfer_scheduler.give_back(ready, &mut self.read_jobs);
+                                if let Err(err) = res {
                                     self.handler.msgbox("error", "Connection Error", &err.to_string(), "");
                                     break;
                                 }
@@ -296,6 +319,7 @@ impl<T: InvokeUiSession> Remote<T> {
                             }
                         }
                         _ = status_timer.tick() => {
+                            self.transfer_scheduler.set_limits(Self::transfer_limits(&self.handler));
                             if self.handler.is_restarting_remote_device()
                                 && last_recv_time.elapsed() >= RESTART_REMOTE_DEVICE_NO_DATA_TIMEOUT
                             {
@@ -801,6 +825,11 @@ impl<T: InvokeUiSession> Remote<T> {
                         if remember {
                             job.set_overwrite_strategy(Some(need_override));
                         }
+                        let version = self.handler.lc.read().unwrap().version;
+                        if need_override && file_delta::is_eligible(job, file_num, version) {
+                            allow_err!(peer.send(&file_delta::new_request(id, file_num)).await);
+                            return true;
+                        }
                         job.confirm(&FileTransferSendConfirmRequest {
                             id,
                             file_num,
@@ -818,6 +847,13 @@ impl<T: InvokeUiSession> Remote<T> {
                         if remember {
                             job.set_overwrite_strategy(Some(need_override));
                         }
+                        let version = self.handler.lc.read().unwrap().version;
+                        if need_override && file_delta::is_eligible(job, file_num, version) {
+                            if let Some(msg) = self.deltas.start_write(job, file_num).await {
+                                allow_err!(peer.send(&msg).await);
+                                return true;
+                            }
+                        }
                         let mut msg = Message::new();
                         let mut file_action = FileAction::new();
                         let req = FileTransferSendConfirmRequest {
@@ -874,6 +910,9 @@ impl<T: InvokeUiSession> Remote<T> {
             Data::CancelJob(id) => {
                 self.cancel_transfer_job(id, peer).await;
             }
+            Data::SetJobPriority((id, priority)) => {
+                self.transfer_scheduler.set_priority(id, priority);
+            }
             Data::RemoveDir((id, path)) => {
                 let mut msg_out = Message::new();
                 let mut file_action = FileAction::new();
@@ -1082,6 +1121,7 @@ impl<T: InvokeUiSession> Remote<T> {
         }
         let _ = fs::remove_job(id, &mut self.read_jobs);
         self.remove_jobs.remove(&id);
+        self.deltas.remove_job(id);
     }
 
     pub async fn sync_jobs_status_to_local(&mut self) -> bool {
@@ -1583,7 +1623,21 @@ impl<T: InvokeUiSession> Remote<T> {
                                                     offset = digest.transferred_size as _;
                                                 }
                                             }
-                                            if let Some(overwrite) = overwrite_strategy {
+                                            let version = self.handler.lc.read().unwrap().version;
+                                            if overwrite_strategy == Some(true)
+                                                && offset == 0
+                                                && file_delta::is_eligible(
+                                                    job,
+                                                    digest.file_num,
+                                                    version,
+                                                )
+                                            {
+                                                let msg = file_delta::new_request(
+                                                    digest.id,
+                                                    digest.file_num,
+                                                );
+                                                allow_err!(peer.send(&msg).await);
+                                            } else if let Some(overwrite) = overwrite_strategy {
                                                 let req = FileTransferSendConfirmRequest {
                                                     id: digest.id,
                                                     file_num: digest.file_num,
@@ -1651,7 +1705,24 @@ impl<T: InvokeUiSession> Remote<T> {
                                                             overwrite_strategy = Some(true);
                                                             offset = digest.transferred_size as _;
                                                         }
-                                                        if let Some(overwrite) = overwrite_strategy
+                                                        let signature = if overwrite_strategy
+                                                            == Some(true)
+                                                            && offset == 0
+                                                            && file_delta::is_eligible(
+                                                                job,
+                                                                digest.file_num,
+                                                                peer_ver,
+                                                            ) {
+                                                            self.deltas
+                                                                .start_write(job, digest.file_num)
+                                                                .await
+                                                        } else {
+                                                            None
+                                                        };
+                                                        if let Some(msg) = signature {
+                                                            allow_err!(peer.send(&msg).await);
+                                                        } else if let Some(overwrite) =
+                                                            overwrite_strategy
                                                         {
                                                             let req =
                                                                 FileTransferSendConfirmRequest {
@@ -1761,7 +1832,24 @@ impl<T: InvokeUiSession> Remote<T> {
                                 }
                             }
                         }
+                        Some(file_response::Union::Signature(s)) => {
+                            if let Err(err) = self.deltas.start_read(&mut self.read_jobs, &s) {
+                                allow_err!(
+                                    peer.send(&fs::new_error(s.id, err.to_string(), s.file_num))
+                                        .await
+                                );
+                                self.handle_job_status(s.id, s.file_num, Some(err.to_string()));
+                            }
+                        }
+                        Some(file_response::Union::Delta(d)) => {
+                            let (id, file_num) = (d.id, d.file_num);
+                            if let Err(err) = self.deltas.write(d) {
+                                self.cancel_transfer_job(id, peer).await;
+                                self.handle_job_status(id, file_num, Some(err.to_string()));
+                            }
+                        }
                         Some(file_response::Union::Error(e)) => {
+                            self.deltas.remove_job(e.id);
                             let job_type = fs::remove_job(e.id, &mut self.write_jobs)
                                 .or_else(|| fs::remove_job(e.id, &mut self.read_jobs))
                                 .map(|j| j.r#type)
@@ -1780,6 +1868,13 @@ impl<T: InvokeUiSession> Remote<T> {
                 }
                 Some(message::Union::Misc(misc)) => match misc.union {
                     Some(misc::Union::AudioFormat(f)) => {
+                        // The recorders of the displays need it for the audio track.
+                        for (_, v) in self.video_threads.iter() {
+                            v.video_sender
+                                .send(MediaData::AudioFormat(f.clone()))
+                                .ok();
+                        }
+                        self.audio_format = Some(f.clone());
                         self.audio_sender.send(MediaData::AudioFormat(f)).ok();
                     }
                     Some(misc::Union::ChatMessage(c)) => {
@@ -1994,6 +2089,13 @@ impl<T: InvokeUiSession> Remote<T> {
                 }
                 Some(message::Union::AudioFrame(frame)) => {
                     if !self.handler.lc.read().unwrap().disable_audio.v {
+                        if self.last_record_state {
+                            for (_, v) in self.video_threads.iter() {
+                                v.video_sender
+                                    .send(MediaData::AudioFrame(Box::new(frame.clone())))
+                                    .ok();
+                            }
+                        }
                         self.audio_sender
                             .send(MediaData::AudioFrame(Box::new(frame)))
                             .ok();
@@ -2397,6 +2499,9 @@ impl<T: InvokeUiSession> Remote<T> {
     fn new_video_thread(&mut self, display: usize) {
         let video_queue = Arc::new(RwLock::new(ArrayQueue::new(client::VIDEO_QUEUE_SIZE)));
         let (video_sender, video_receiver) = std::sync::mpsc::channel::<MediaData>();
+        if let Some(f) = self.audio_format.clone() {
+            video_sender.send(MediaData::AudioFormat(f)).ok();
+        }
         let decode_fps = Arc::new(RwLock::new(None));
         let frame_count = Arc::new(RwLock::new(0));
         let discard_queue = Arc::new(RwLock::new(false));
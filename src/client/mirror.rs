@@ -0,0 +1,182 @@
+//! One-way directory mirror for file transfer.
+//!
+//! Both sides are listed recursively (`ReadAllFiles` for the remote side), then the plan
+//! copies files that are missing or differ in size or modification time, which is the
+//! digest file transfer uses to detect identical files, and deletes files that no longer
+//! exist at the source. Files left unchanged can further be compared by their [`digest`].
+//!
+//! The names come from the listing of the peer, they are rejected unless they are relative
+//! paths staying inside the mirrored directory.
+
+use hbb_common::{
+    fs::validate_file_name_no_traversal,
+    message_proto::{FileEntry, FileType},
+};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, io::Read};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct MirrorPlan {
+    /// Relative paths, separated by `/`
+    pub adds: Vec<String>,
+    pub updates: Vec<String>,
+    pub deletes: Vec<String>,
+    /// Same size and modification time on both sides
+    pub unchanged: Vec<String>,
+}
+
+impl MirrorPlan {
+    pub fn is_empty(&self) -> bool {
+        self.adds.is_empty() && self.updates.is_empty() && self.deletes.is_empty()
+    }
+}
+
+// Same rules as the file list of a transfer job, plus no absolute paths or drive prefixes,
+// the name is joined to the mirrored directory.
+fn validate_name(name: &str) -> Result<(), String> {
+    let is_drive = matches!(name.as_bytes(), [letter, b':', ..] if letter.is_ascii_alphabetic());
+    if is_drive
+        || name
+            .split('/')
+            .any(|component| component.is_empty() || component == "." || component == "..")
+    {
+        return Err(format!("Unsafe file name in the listing: {}", name));
+    }
+    validate_file_name_no_traversal(name).map_err(|e| format!("{}: {}", name, e))
+}
+
+fn files(entries: &[FileEntry]) -> Result<HashMap<String, &FileEntry>, String> {
+    entries
+        .iter()
+        .filter(|e| {
+            matches!(
+                e.entry_type.enum_value(),
+                Ok(FileType::File) | Ok(FileType::FileLink)
+            )
+        })
+        .map(|e| {
+            let name = e.name.replace('\\', "/");
+            validate_name(&name)?;
+            Ok((name, e))
+        })
+        .collect()
+}
+
+/// Compare the recursive listings of the source and destination directories.
+pub fn plan(src: &[FileEntry], dst: &[FileEntry]) -> Result<MirrorPlan, String> {
+    let src = files(src)?;
+    let dst = files(dst)?;
+    let mut plan = MirrorPlan::default();
+    for (name, s) in src.iter() {
+        match dst.get(name) {
+            None => plan.adds.push(name.clone()),
+            Some(d) if d.size != s.size || d.modified_time != s.modified_time => {
+                plan.updates.push(name.clone())
+            }
+            _ => plan.unchanged.push(name.clone()),
+        }
+    }
+    for name in dst.keys() {
+        if !src.contains_key(name) {
+            plan.deletes.push(name.clone());
+        }
+    }
+    plan.adds.sort();
+    plan.updates.sort();
+    plan.deletes.sort();
+    plan.unchanged.sort();
+    Ok(plan)
+}
+
+/// SHA-256 of the content of a local file.
+pub fn digest(path: &str) -> std::io::Result<Vec<u8>> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Join a relative path of the plan to a directory, using the separator of its side.
+pub fn join(dir: &str, name: &str, sep: &str) -> String {
+    let name = name.replace('/', sep);
+    if dir.ends_with(sep) {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}{}{}", dir, sep, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, size: u64, modified_time: u64) -> FileEntry {
+        FileEntry {
+            name: name.to_owned(),
+            entry_type: FileType::File.into(),
+            size,
+            modified_time,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn plan_finds_adds_updates_and_deletes() {
+        let src = vec![
+            file("a.conf", 10, 100),
+            file("dir\\b.conf", 20, 100),
+            file("same.conf", 5, 50),
+            file("newer.conf", 5, 60),
+        ];
+        let dst = vec![
+            file("dir/b.conf", 21, 100),
+            file("same.conf", 5, 50),
+            file("newer.conf", 5, 50),
+            file("old.conf", 1, 1),
+        ];
+        assert_eq!(
+            plan(&src, &dst),
+            Ok(MirrorPlan {
+                adds: vec!["a.conf".to_owned()],
+                updates: vec!["dir/b.conf".to_owned(), "newer.conf".to_owned()],
+                deletes: vec!["old.conf".to_owned()],
+                unchanged: vec!["same.conf".to_owned()],
+            })
+        );
+        assert!(plan(&src, &src).unwrap().is_empty());
+    }
+
+    #[test]
+    fn plan_rejects_names_outside_the_directory() {
+        let good = vec![file("a.conf", 1, 1)];
+        for name in [
+            "../a.conf",
+            "dir/../../a.conf",
+            "/etc/passwd",
+            "\\\\server\\share",
+            "C:\\Windows\\a.conf",
+            "c:a.conf",
+            "dir//a.conf",
+        ] {
+            let bad = vec![file(name, 1, 1)];
+            assert!(plan(&bad, &good).is_err(), "{}", name);
+            assert!(plan(&good, &bad).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn join_uses_the_separator_of_the_side() {
+        assert_eq!(
+            join("C:\\data", "dir/b.conf", "\\"),
+            "C:\\data\\dir\\b.conf"
+        );
+        assert_eq!(join("/etc/app/", "dir/b.conf", "/"), "/etc/app/dir/b.conf");
+    }
+}
@@ -190,6 +190,21 @@ pub fn is_support_file_transfer_resume_num(ver: i64) -> bool {
     ver >= hbb_common::get_version_number("1.4.2")
 }
 
+#[inline]
+pub fn is_support_file_transfer_delta(ver: &str) -> bool {
+    is_support_file_transfer_delta_num(hbb_common::get_version_number(ver))
+}
+
+#[inline]
+pub fn is_support_file_transfer_delta_num(ver: i64) -> bool {
+    ver >= hbb_common::get_version_number("1.4.9")
+}
+
+#[inline]
+pub fn is_support_port_forward_multiplex_num(ver: i64) -> bool {
+    ver >= hbb_common::get_version_number("1.4.9")
+}
+
 /// Minimum server version required for relative mouse mode support.
 /// This constant must mirror Flutter's `kMinVersionForRelativeMouseMode` in `consts.dart`.
 const MIN_VERSION_RELATIVE_MOUSE_MODE: &str = "1.4.5";
@@ -2090,6 +2105,23 @@ pub fn create_symmetric_key_msg(their_pk_b: [u8; 32]) -> (Bytes, Bytes, secretbo
     (Vec::from(our_pk_b.0).into(), sealed_key.into(), key)
 }
 
+pub const OPTION_RECORDING_PUBLIC_KEY: &str = "recording-public-key";
+
+/// The key to encrypt the screen recordings with, the base64 of a box public key.
+pub fn recording_public_key() -> Option<box_::PublicKey> {
+    let key = Config::get_option(OPTION_RECORDING_PUBLIC_KEY);
+    if key.trim().is_empty() {
+        return None;
+    }
+    let key = decode64(key.trim())
+        .ok()
+        .and_then(|k| box_::PublicKey::from_slice(&k));
+    if key.is_none() {
+        log::error!("Invalid {}, recordings are not encrypted", OPTION_RECORDING_PUBLIC_KEY);
+    }
+    key
+}
+
 #[inline]
 pub fn using_public_server() -> bool {
     crate::get_custom_rendezvous_server(get_option("custom-rendezvous-server")).is_empty()
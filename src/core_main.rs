@@ -51,17 +51,19 @@ pub fn core_main() -> Option<Vec<String>> {
         if i == 0 {
             arg_exe = arg;
         } else if i > 0 {
+            // `--cli` takes options such as `--terminal`, they are not flutter connections.
             #[cfg(feature = "flutter")]
-            if [
-                "--connect",
-                "--play",
-                "--file-transfer",
-                "--view-camera",
-                "--port-forward",
-                "--terminal",
-                "--rdp",
-            ]
-            .contains(&arg.as_str())
+            if args.first().map(String::as_str) != Some("--cli")
+                && [
+                    "--connect",
+                    "--play",
+                    "--file-transfer",
+                    "--view-camera",
+                    "--port-forward",
+                    "--terminal",
+                    "--rdp",
+                ]
+                .contains(&arg.as_str())
             {
                 _is_flutter_invoke_new_connection = true;
             }
@@ -709,6 +711,169 @@ pub fn core_main() -> Option<Vec<String>> {
                 }
             }
             return None;
+        } else if args[0] == "--cli" {
+            // Headless client, exit with the status of the session so scripts can check it.
+            std::process::exit(crate::cli::run(&args[1..]));
+        } else if args[0] == "--replay-terminal" {
+            // Replay a terminal recording, e.g. `--replay-terminal <file.cast> [speed]`.
+            if args.len() < 2 {
+                eprintln!("Usage: --replay-terminal <file.cast> [speed]");
+                std::process::exit(2);
+            }
+            let speed = args.get(2).and_then(|s| s.parse().ok()).unwrap_or(1.0);
+            let path = std::path::Path::new(&args[1]);
+            if let Err(e) = crate::server::terminal_record::replay(path, speed) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return None;
+        } else if args[0] == "--gen-recording-keypair" {
+            // The public key is for the `recording-public-key` option, keep the secret key
+            // to decrypt the recordings.
+            let (pk, sk) = hbb_common::sodiumoxide::crypto::box_::gen_keypair();
+            println!("public key: {}", crate::encode64(pk.0));
+            println!("secret key: {}", crate::encode64(sk.0));
+            return None;
+        } else if args[0] == "--decrypt-recording" {
+            // Restore a playable recording, the output defaults to the name without `.enc`.
+            // The secret key is read from a file, or stdin with `-`, not to leave it in the
+            // process list or the shell history.
+            if args.len() < 3 {
+                eprintln!("Usage: --decrypt-recording <file.enc> <secret key file|-> [output]");
+                std::process::exit(2);
+            }
+            let key = if args[2] == "-" {
+                let mut key = String::new();
+                std::io::stdin().read_line(&mut key).map(|_| key)
+            } else {
+                std::fs::read_to_string(&args[2])
+            };
+            let key = match key {
+                Ok(key) => key,
+                Err(e) => {
+                    eprintln!("Failed to read the secret key: {}", e);
+                    std::process::exit(2);
+                }
+            };
+            let Some(sk) = crate::decode64(key.trim())
+                .ok()
+                .and_then(|k| hbb_common::sodiumoxide::crypto::box_::SecretKey::from_slice(&k))
+            else {
+                eprintln!("Invalid secret key");
+                std::process::exit(2);
+            };
+            let src = std::path::PathBuf::from(&args[1]);
+            let dst = match args.get(3) {
+                Some(dst) => std::path::PathBuf::from(dst),
+                None => match args[1].strip_suffix(scrap::record::ENCRYPTED_EXTENSION) {
+                    Some(dst) => std::path::PathBuf::from(dst),
+                    None => {
+                        eprintln!("Please specify the output file");
+                        std::process::exit(2);
+                    }
+                },
+            };
+            match scrap::record::decrypt(&src, &dst, &sk) {
+                Ok(true) => {}
+                Ok(false) => {
+                    eprintln!("The recording was not closed properly, it may be incomplete");
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::fs::remove_file(&dst).ok();
+                    std::process::exit(1);
+                }
+            }
+            println!("{}", dst.display());
+            return None;
+        } else if args[0] == "--search-recordings" {
+            // List the incoming recordings from their sidecars, e.g.
+            // `--search-recordings --peer 123456789 --from 2024-05-01 --to 2024-05-31`.
+            let usage = "Usage: --search-recordings [--peer <id>] [--from <YYYY-MM-DD>] [--to <YYYY-MM-DD>]";
+            let (mut peer, mut from, mut to) = (None, None, None);
+            for pair in args[1..].chunks(2) {
+                let [name, value] = pair else {
+                    eprintln!("{}", usage);
+                    std::process::exit(2);
+                };
+                let date = || hbb_common::chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").ok();
+                let valid = match name.as_str() {
+                    "--peer" => {
+                        peer = Some(value.as_str());
+                        true
+                    }
+                    "--from" => {
+                        from = date();
+                        from.is_some()
+                    }
+                    "--to" => {
+                        to = date();
+                        to.is_some()
+                    }
+                    _ => false,
+                };
+                if !valid {
+                    eprintln!("{}", usage);
+                    std::process::exit(2);
+                }
+            }
+            let dir = std::path::PathBuf::from(crate::server::recording::dir());
+            match crate::server::recording::search(&dir, peer, from, to) {
+                Ok(found) => {
+                    for sidecar in found {
+                        let peers: Vec<&str> = sidecar["peers"]
+                            .as_array()
+                            .map(|peers| peers.iter().filter_map(|p| p["id"].as_str()).collect())
+                            .unwrap_or_default();
+                        println!(
+                            "{}\t{}\t{}",
+                            sidecar["start"].as_str().unwrap_or_default(),
+                            sidecar["recording"].as_str().unwrap_or_default(),
+                            peers.join(",")
+                        );
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+            return None;
+        } else if args[0] == "--audit-spool" {
+            // List the audit records waiting for the api server, or deliver them now with
+            // `--audit-spool flush`. Run it as the user of the service, whose spool it is.
+            use hbb_common::chrono::{Local, TimeZone};
+            let dir = crate::server::audit_spool::dir();
+            let res = match args.get(1).map(|s| s.as_str()) {
+                None | Some("list") => crate::server::audit_spool::entries(&dir).map(|entries| {
+                    for (seq, entry) in entries {
+                        let queued = Local
+                            .timestamp_opt(entry.queued, 0)
+                            .single()
+                            .map(|t| t.to_rfc3339())
+                            .unwrap_or_default();
+                        println!(
+                            "{}\t{}\t{}\t{}\t{}",
+                            seq, queued, entry.attempts, entry.url, entry.last_error
+                        );
+                    }
+                }),
+                Some("flush") => crate::server::audit_spool::flush()
+                    .map(|delivered| println!("{} records delivered", delivered)),
+                _ => {
+                    eprintln!("Usage: --audit-spool [list|flush]");
+                    eprintln!(
+                        "Records are delivered at least once: one retried for minutes may be \
+                         stored twice, with the same record_id."
+                    );
+                    std::process::exit(2);
+                }
+            };
+            if let Err(e) = res {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return None;
         } else if args[0] == "--cm" {
             // call connection manager to establish connections
             // meanwhile, return true to call flutter window to show control panel
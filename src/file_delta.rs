@@ -0,0 +1,890 @@
+//! rsync style delta transfer of files.
+//!
+//! When a file is overwritten, the receiver computes a [`Signature`] of its existing copy, a weak
+//! rolling checksum and a strong hash for each block, and sends it instead of a send confirm.
+//! The sender scans its file with the rolling checksum and sends [`DeltaOp`]s, copying blocks
+//! the receiver already has and sending only the data in between. The receiver rebuilds the
+//! file next to the old one and replaces it once the size and checksum of the whole file match.
+//!
+//! On the wire the receiver sends `FileResponse::signature` and the sender answers with
+//! `FileResponse::delta` messages. For uploads the receiver is the controlled side, the client
+//! asks for its signature with the `delta` send confirm. The file is skipped in the
+//! `TransferJob`s of both sides, by the sender only once the delta is sent, so the next file
+//! doesn't start before. The client only asks for a delta if the peer supports it, see
+//! `is_support_file_transfer_delta()`, otherwise the whole file is sent as before.
+
+use hbb_common::{
+    bail,
+    bytes::Bytes,
+    fs::{self, TransferJob},
+    log,
+    message_proto::*,
+    tokio, ResultType,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    time::{Duration, UNIX_EPOCH},
+};
+
+pub const MIN_BLOCK_SIZE: usize = 4 * 1024;
+pub const MAX_BLOCK_SIZE: usize = 1024 * 1024;
+// Files smaller than this are always sent completely.
+pub const MIN_DELTA_FILE_SIZE: u64 = 1024 * 1024;
+const STRONG_HASH_LEN: usize = 16;
+const MAX_LITERAL_LEN: usize = 128 * 1024;
+// The receiver copies at most this much for one operation, and for one message.
+const MAX_COPY_LEN: usize = 8 * 1024 * 1024;
+
+pub type StrongHash = [u8; STRONG_HASH_LEN];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockSignature {
+    pub weak: u32,
+    pub strong: StrongHash,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub block_size: usize,
+    pub file_size: u64,
+    pub blocks: Vec<BlockSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeltaOp {
+    /// Copy `count` blocks of the receiver's file, starting at block `index`.
+    Copy { index: u32, count: u32 },
+    /// New data.
+    Data(Vec<u8>),
+}
+
+/// Block size of the signature, about the square root of the file size like rsync.
+pub fn block_size(file_size: u64) -> usize {
+    let size = (file_size as f64).sqrt() as usize;
+    let size = size.div_ceil(1024) * 1024;
+    size.clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE)
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct RollingChecksum {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl RollingChecksum {
+    fn new(data: &[u8]) -> Self {
+        let mut sum = Self::default();
+        for &x in data {
+            sum.a = sum.a.wrapping_add(x as u32);
+            sum.b = sum.b.wrapping_add(sum.a);
+        }
+        sum.len = data.len() as u32;
+        sum
+    }
+
+    fn roll(&mut self, out: u8, r#in: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(r#in as u32);
+        self.b = self
+            .b
+            .wrapping_sub(self.len.wrapping_mul(out as u32))
+            .wrapping_add(self.a);
+    }
+
+    fn digest(&self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+}
+
+fn strong_hash(data: &[u8]) -> StrongHash {
+    let mut hash = StrongHash::default();
+    hash.copy_from_slice(&Sha256::digest(data)[..STRONG_HASH_LEN]);
+    hash
+}
+
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// Compute the signature of the receiver's copy of the file.
+pub fn signature<R: Read>(reader: &mut R, block_size: usize) -> io::Result<Signature> {
+    let mut buf = vec![0u8; block_size];
+    let mut blocks = Vec::new();
+    let mut file_size = 0;
+    loop {
+        let n = read_full(reader, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        file_size += n as u64;
+        blocks.push(BlockSignature {
+            weak: RollingChecksum::new(&buf[..n]).digest(),
+            strong: strong_hash(&buf[..n]),
+        });
+        if n < block_size {
+            break;
+        }
+    }
+    Ok(Signature {
+        block_size,
+        file_size,
+        blocks,
+    })
+}
+
+impl Signature {
+    fn to_message(&self, id: i32, file_num: i32) -> Message {
+        let mut fr = FileResponse::new();
+        fr.set_signature(FileTransferSignature {
+            id,
+            file_num,
+            block_size: self.block_size as _,
+            file_size: self.file_size,
+            blocks: self
+                .blocks
+                .iter()
+                .map(|b| FileBlockSignature {
+                    weak: b.weak,
+                    strong: Bytes::copy_from_slice(&b.strong),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        });
+        let mut msg = Message::new();
+        msg.set_file_response(fr);
+        msg
+    }
+
+    /// `None` if the signature of the peer is inconsistent.
+    fn from_proto(s: &FileTransferSignature) -> Option<Self> {
+        let block_size = s.block_size as usize;
+        if !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size)
+            || s.blocks.len() as u64 != s.file_size.div_ceil(block_size as u64)
+        {
+            return None;
+        }
+        let blocks = s
+            .blocks
+            .iter()
+            .map(|b| {
+                Some(BlockSignature {
+                    weak: b.weak,
+                    strong: b.strong.as_ref().try_into().ok()?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            block_size,
+            file_size: s.file_size,
+            blocks,
+        })
+    }
+}
+
+impl DeltaOp {
+    fn len(&self, block_size: usize) -> usize {
+        match self {
+            DeltaOp::Copy { count, .. } => *count as usize * block_size,
+            DeltaOp::Data(data) => data.len(),
+        }
+    }
+
+    fn into_proto(self) -> FileDeltaOp {
+        let mut op = FileDeltaOp::new();
+        match self {
+            DeltaOp::Copy { index, count } => op.set_copy(FileDeltaCopy {
+                index,
+                count,
+                ..Default::default()
+            }),
+            DeltaOp::Data(data) => op.set_data(data.into()),
+        }
+        op
+    }
+
+    fn from_proto(op: FileDeltaOp) -> Option<Self> {
+        match op.union? {
+            file_delta_op::Union::Copy(c) => Some(DeltaOp::Copy {
+                index: c.index,
+                count: c.count,
+            }),
+            file_delta_op::Union::Data(data) => Some(DeltaOp::Data(data.to_vec())),
+        }
+    }
+}
+
+/// Scans the sender's file and produces the operations to rebuild it from the receiver's file,
+/// a few at a time. The whole file is hashed on the way for the final check of the receiver.
+pub struct DeltaEncoder<R> {
+    reader: R,
+    signature: Signature,
+    by_weak: HashMap<u32, Vec<u32>>,
+    last_block_len: usize,
+    // `buf[start..]` is the data not processed yet, the window is `buf[start..start + len]`.
+    buf: Vec<u8>,
+    start: usize,
+    eof: bool,
+    sum: Option<RollingChecksum>,
+    ops: VecDeque<DeltaOp>,
+    pending_copy: Option<(u32, u32)>,
+    literal: Vec<u8>,
+    hasher: Sha256,
+    file_size: u64,
+    finished: bool,
+}
+
+impl<R: Read> DeltaEncoder<R> {
+    pub fn new(reader: R, signature: Signature) -> Self {
+        let mut by_weak: HashMap<u32, Vec<u32>> = HashMap::new();
+        for (i, block) in signature.blocks.iter().enumerate() {
+            by_weak.entry(block.weak).or_default().push(i as u32);
+        }
+        let last_block_len = match signature.file_size % signature.block_size.max(1) as u64 {
+            0 => signature.block_size,
+            n => n as usize,
+        };
+        Self {
+            reader,
+            signature,
+            by_weak,
+            last_block_len,
+            buf: Vec::new(),
+            start: 0,
+            eof: false,
+            sum: None,
+            ops: VecDeque::new(),
+            pending_copy: None,
+            literal: Vec::new(),
+            hasher: Sha256::new(),
+            file_size: 0,
+            finished: false,
+        }
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.signature.block_size
+    }
+
+    /// Size of the scanned file, once [`Self::next_op`] returned `None`.
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    /// SHA-256 of the scanned file, once [`Self::next_op`] returned `None`.
+    pub fn checksum(&self) -> Vec<u8> {
+        self.hasher.clone().finalize().to_vec()
+    }
+
+    /// The next operation, `None` at the end of the file.
+    pub fn next_op(&mut self) -> io::Result<Option<DeltaOp>> {
+        loop {
+            if let Some(op) = self.ops.pop_front() {
+                return Ok(Some(op));
+            }
+            if self.finished {
+                return Ok(None);
+            }
+            self.step()?;
+        }
+    }
+
+    fn read(&mut self, from: usize) -> io::Result<usize> {
+        let n = read_full(&mut self.reader, &mut self.buf[from..])?;
+        self.hasher.update(&self.buf[from..from + n]);
+        self.file_size += n as u64;
+        self.buf.truncate(from + n);
+        Ok(n)
+    }
+
+    fn step(&mut self) -> io::Result<()> {
+        if self.signature.blocks.is_empty() {
+            // Nothing to copy from, the file is sent as it is.
+            self.buf.resize(MAX_LITERAL_LEN, 0);
+            if self.read(0)? == 0 {
+                self.finished = true;
+            } else {
+                self.ops
+                    .push_back(DeltaOp::Data(std::mem::take(&mut self.buf)));
+            }
+            return Ok(());
+        }
+        let block_size = self.signature.block_size;
+        if !self.eof && self.buf.len() - self.start < block_size + 1 {
+            self.buf.drain(..self.start);
+            self.start = 0;
+            let old_len = self.buf.len();
+            self.buf.resize(old_len + block_size * 2, 0);
+            self.eof = self.read(old_len)? < block_size * 2;
+        }
+        let remaining = self.buf.len() - self.start;
+        if remaining == 0 {
+            self.flush_copy();
+            self.flush_literal();
+            self.finished = true;
+            return Ok(());
+        }
+        let len = remaining.min(block_size);
+        let window = &self.buf[self.start..self.start + len];
+        let current = *self.sum.get_or_insert_with(|| RollingChecksum::new(window));
+        let matched = self.by_weak.get(&current.digest()).and_then(|candidates| {
+            let strong = strong_hash(window);
+            candidates.iter().copied().find(|&i| {
+                let block = &self.signature.blocks[i as usize];
+                let block_len = if i as usize == self.signature.blocks.len() - 1 {
+                    self.last_block_len
+                } else {
+                    block_size
+                };
+                block_len == len && block.strong == strong
+            })
+        });
+        if let Some(index) = matched {
+            self.copy(index);
+            self.start += len;
+            self.sum = None;
+            return Ok(());
+        }
+        let byte = self.buf[self.start];
+        self.push_literal(byte);
+        if remaining > block_size {
+            if let Some(sum) = self.sum.as_mut() {
+                sum.roll(byte, self.buf[self.start + block_size]);
+            }
+        } else {
+            // The window shrinks at the end of the file.
+            self.sum = None;
+        }
+        self.start += 1;
+        Ok(())
+    }
+
+    fn copy(&mut self, index: u32) {
+        self.flush_literal();
+        let max_count = (MAX_COPY_LEN / self.signature.block_size).max(1) as u32;
+        match self.pending_copy.as_mut() {
+            Some((start, count)) if *start + *count == index && *count < max_count => *count += 1,
+            _ => {
+                self.flush_copy();
+                self.pending_copy = Some((index, 1));
+            }
+        }
+    }
+
+    fn push_literal(&mut self, byte: u8) {
+        self.flush_copy();
+        self.literal.push(byte);
+        if self.literal.len() >= MAX_LITERAL_LEN {
+            self.flush_literal();
+        }
+    }
+
+    fn flush_copy(&mut self) {
+        if let Some((index, count)) = self.pending_copy.take() {
+            self.ops.push_back(DeltaOp::Copy { index, count });
+        }
+    }
+
+    fn flush_literal(&mut self) {
+        if !self.literal.is_empty() {
+            self.ops
+                .push_back(DeltaOp::Data(std::mem::take(&mut self.literal)));
+        }
+    }
+}
+
+/// Rebuild the file from the receiver's copy `basis` and the operations of [`DeltaEncoder`].
+pub fn apply<B, W, I>(basis: &mut B, block_size: usize, ops: I, out: &mut W) -> io::Result<u64>
+where
+    B: Read + Seek,
+    W: Write,
+    I: IntoIterator<Item = DeltaOp>,
+{
+    let mut written = 0;
+    let mut buf = vec![0u8; block_size];
+    for op in ops {
+        match op {
+            DeltaOp::Copy { index, count } => {
+                basis.seek(SeekFrom::Start(index as u64 * block_size as u64))?;
+                for _ in 0..count {
+                    let n = read_full(basis, &mut buf)?;
+                    if n == 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "block out of range",
+                        ));
+                    }
+                    out.write_all(&buf[..n])?;
+                    written += n as u64;
+                }
+            }
+            DeltaOp::Data(data) => {
+                out.write_all(&data)?;
+                written += data.len() as u64;
+            }
+        }
+    }
+    Ok(written)
+}
+
+/// Path of the file `file_num` of a job reading or writing files.
+fn job_file_path(job: &TransferJob, file_num: i32) -> Option<String> {
+    let file = job.files().get(file_num as usize)?;
+    match &job.data_source {
+        fs::DataSource::FilePath(p) => Some(fs::get_string(&TransferJob::join(p, &file.name))),
+        _ => None,
+    }
+}
+
+/// Whether the file `file_num` of the job is sent as a delta to or from a peer of `version`.
+pub fn is_eligible(job: &TransferJob, file_num: i32, version: i64) -> bool {
+    crate::is_support_file_transfer_delta_num(version)
+        && job.r#type == fs::JobType::Generic
+        && job
+            .files()
+            .get(file_num as usize)
+            .is_some_and(|f| f.size >= MIN_DELTA_FILE_SIZE)
+}
+
+/// Ask the receiver for its signature instead of confirming to overwrite the file, for uploads.
+pub fn new_request(id: i32, file_num: i32) -> Message {
+    fs::new_send_confirm(FileTransferSendConfirmRequest {
+        id,
+        file_num,
+        union: Some(file_transfer_send_confirm_request::Union::Delta(true)),
+        ..Default::default()
+    })
+}
+
+async fn skip_file(job: &mut TransferJob, file_num: i32) {
+    job.confirm(&FileTransferSendConfirmRequest {
+        id: job.id(),
+        file_num,
+        union: Some(file_transfer_send_confirm_request::Union::Skip(true)),
+        ..Default::default()
+    })
+    .await;
+}
+
+/// The sending side of a delta.
+pub struct DeltaRead {
+    id: i32,
+    file_num: i32,
+    encoder: DeltaEncoder<File>,
+    last_modified: u64,
+    done: bool,
+}
+
+impl DeltaRead {
+    fn new(id: i32, file_num: i32, path: &str, signature: Signature) -> ResultType<Self> {
+        let file = File::open(path)?;
+        let last_modified = file
+            .metadata()?
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Ok(Self {
+            id,
+            file_num,
+            encoder: DeltaEncoder::new(file, signature),
+            last_modified,
+            done: false,
+        })
+    }
+
+    /// The next message of the delta, `None` once the last one was returned.
+    fn next_message(&mut self) -> ResultType<Option<Message>> {
+        if self.done {
+            return Ok(None);
+        }
+        let mut delta = FileTransferDelta {
+            id: self.id,
+            file_num: self.file_num,
+            ..Default::default()
+        };
+        let (mut data_len, mut len) = (0, 0);
+        while data_len < MAX_LITERAL_LEN && len < MAX_COPY_LEN {
+            let Some(op) = self.encoder.next_op()? else {
+                self.done = true;
+                delta.last = true;
+                delta.file_size = self.encoder.file_size();
+                delta.last_modified = self.last_modified;
+                delta.checksum = self.encoder.checksum().into();
+                break;
+            };
+            if let DeltaOp::Data(data) = &op {
+                data_len += data.len();
+            }
+            len += op.len(self.encoder.block_size());
+            delta.ops.push(op.into_proto());
+        }
+        let mut fr = FileResponse::new();
+        fr.set_delta(delta);
+        let mut msg = Message::new();
+        msg.set_file_response(fr);
+        Ok(Some(msg))
+    }
+}
+
+/// The receiving side of a delta, the file is rebuilt in `<path>.delta` and then moved to `path`.
+pub struct DeltaWrite {
+    id: i32,
+    file_num: i32,
+    path: PathBuf,
+    tmp_path: PathBuf,
+    block_size: usize,
+    // The old file and the new one, closed before the rename.
+    files: Option<(File, File)>,
+    hasher: Sha256,
+    written: u64,
+    done: bool,
+}
+
+impl DeltaWrite {
+    /// The write and the message with the signature of the existing file.
+    fn new(id: i32, file_num: i32, path: PathBuf) -> ResultType<(Self, Message)> {
+        let mut basis = File::open(&path)?;
+        let signature = signature(&mut basis, block_size(basis.metadata()?.len()))?;
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".delta");
+        let tmp_path = PathBuf::from(tmp_path);
+        let out = File::create(&tmp_path)?;
+        let msg = signature.to_message(id, file_num);
+        Ok((
+            Self {
+                id,
+                file_num,
+                path,
+                tmp_path,
+                block_size: signature.block_size,
+                files: Some((basis, out)),
+                hasher: Sha256::new(),
+                written: 0,
+                done: false,
+            },
+            msg,
+        ))
+    }
+
+    /// Apply a message of the sender, `true` once the file is replaced.
+    fn write(&mut self, delta: FileTransferDelta) -> ResultType<bool> {
+        let Some((basis, out)) = self.files.as_mut() else {
+            bail!("Delta already finished");
+        };
+        let Some(ops) = delta
+            .ops
+            .into_iter()
+            .map(DeltaOp::from_proto)
+            .collect::<Option<Vec<_>>>()
+        else {
+            bail!("Invalid delta operation");
+        };
+        let mut buf = Vec::new();
+        apply(basis, self.block_size, ops, &mut buf)?;
+        self.hasher.update(&buf);
+        out.write_all(&buf)?;
+        self.written += buf.len() as u64;
+        if !delta.last {
+            return Ok(false);
+        }
+        if self.written != delta.file_size
+            || self.hasher.clone().finalize().as_slice() != delta.checksum.as_ref()
+        {
+            bail!("Delta checksum mismatch");
+        }
+        out.sync_all()?;
+        if delta.last_modified > 0 {
+            out.set_modified(UNIX_EPOCH + Duration::from_secs(delta.last_modified))?;
+        }
+        self.files = None;
+        std::fs::rename(&self.tmp_path, &self.path)?;
+        self.done = true;
+        Ok(true)
+    }
+}
+
+impl Drop for DeltaWrite {
+    fn drop(&mut self) {
+        if !self.done {
+            self.files = None;
+            std::fs::remove_file(&self.tmp_path).ok();
+        }
+    }
+}
+
+/// The deltas of a connection in progress.
+#[derive(Default)]
+pub struct Deltas {
+    // Their read jobs wait for the confirm of the file until they are sent.
+    reads: Vec<DeltaRead>,
+    // Their files are already skipped in the write jobs.
+    writes: Vec<DeltaWrite>,
+}
+
+impl Deltas {
+    /// Receive the file `file_num` of the write job as a delta. The signature to send to the
+    /// sender, `None` if it failed and the file should be sent whole.
+    pub async fn start_write(&mut self, job: &mut TransferJob, file_num: i32) -> Option<Message> {
+        let path = job_file_path(job, file_num)?;
+        let id = job.id();
+        // The whole file is read, off the async runtime.
+        let res = match tokio::task::spawn_blocking(move || {
+            DeltaWrite::new(id, file_num, PathBuf::from(path))
+        })
+        .await
+        {
+            Ok(res) => res,
+            Err(e) => Err(e.into()),
+        };
+        match res {
+            Ok((write, msg)) => {
+                skip_file(job, file_num).await;
+                self.writes.push(write);
+                Some(msg)
+            }
+            Err(e) => {
+                log::warn!("Failed to start the delta of job {}: {}", id, e);
+                None
+            }
+        }
+    }
+
+    /// Send the file of the signature of the receiver as a delta. On error the read job is
+    /// removed, the error is for the receiver.
+    pub fn start_read(
+        &mut self,
+        jobs: &mut Vec<TransferJob>,
+        signature: &FileTransferSignature,
+    ) -> ResultType<()> {
+        let (id, file_num) = (signature.id, signature.file_num);
+        let res = (|| {
+            let Some(job) = fs::get_job(id, jobs) else {
+                bail!("No such job");
+            };
+            // Only the file waiting for its confirm.
+            if job.file_num() != file_num {
+                bail!("Unexpected file signature");
+            }
+            let Some(path) = job_file_path(job, file_num) else {
+                bail!("No such file");
+            };
+            let Some(signature) = Signature::from_proto(signature) else {
+                bail!("Invalid file signature");
+            };
+            DeltaRead::new(id, file_num, &path, signature)
+        })();
+        match res {
+            Ok(read) => {
+                self.reads.push(read);
+                Ok(())
+            }
+            Err(e) => {
+                fs::remove_job(id, jobs);
+                Err(e)
+            }
+        }
+    }
+
+    /// The next message of each delta being sent. The files of the finished ones are skipped in
+    /// their jobs, the failed ones get an error message and their jobs are removed.
+    pub async fn next_messages(&mut self, jobs: &mut Vec<TransferJob>) -> Vec<Message> {
+        self.reads
+            .retain(|read| jobs.iter().any(|job| job.id() == read.id));
+        let mut msgs = vec![];
+        let mut finished = vec![];
+        for read in self.reads.iter_mut() {
+            match read.next_message() {
+                Ok(Some(msg)) => {
+                    msgs.push(msg);
+                    if read.done {
+                        finished.push((read.id, read.file_num, false));
+                    }
+                }
+                Ok(None) => finished.push((read.id, read.file_num, false)),
+                Err(e) => {
+                    log::error!("Failed to send the delta of job {}: {}", read.id, e);
+                    msgs.push(fs::new_error(read.id, e, read.file_num));
+                    finished.push((read.id, read.file_num, true));
+                }
+            }
+        }
+        for (id, file_num, failed) in finished {
+            self.reads
+                .retain(|read| read.id != id || read.file_num != file_num);
+            if failed {
+                fs::remove_job(id, jobs);
+            } else if let Some(job) = fs::get_job(id, jobs) {
+                skip_file(job, file_num).await;
+            }
+        }
+        msgs
+    }
+
+    /// Apply a delta message of the sender to its file.
+    pub fn write(&mut self, delta: FileTransferDelta) -> ResultType<()> {
+        let Some(i) = self
+            .writes
+            .iter()
+            .position(|w| w.id == delta.id && w.file_num == delta.file_num)
+        else {
+            return Ok(());
+        };
+        match self.writes[i].write(delta) {
+            Ok(false) => Ok(()),
+            Ok(true) => {
+                self.writes.remove(i);
+                Ok(())
+            }
+            Err(e) => {
+                self.writes.remove(i);
+                Err(e)
+            }
+        }
+    }
+
+    /// Drop the deltas of a cancelled or failed job.
+    pub fn remove_job(&mut self, id: i32) {
+        self.reads.retain(|read| read.id != id);
+        self.writes.retain(|write| write.id != id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn encode(new: &[u8], sig: Signature) -> (Vec<DeltaOp>, Vec<u8>) {
+        let mut encoder = DeltaEncoder::new(Cursor::new(new), sig);
+        let mut ops = Vec::new();
+        while let Some(op) = encoder.next_op().unwrap() {
+            ops.push(op);
+        }
+        assert_eq!(encoder.file_size(), new.len() as u64);
+        (ops, encoder.checksum())
+    }
+
+    fn round_trip(old: &[u8], new: &[u8], block_size: usize) -> Vec<DeltaOp> {
+        let sig = signature(&mut Cursor::new(old), block_size).unwrap();
+        let (ops, checksum) = encode(new, sig);
+        assert_eq!(checksum, Sha256::digest(new).to_vec());
+        let mut out = Vec::new();
+        apply(&mut Cursor::new(old), block_size, ops.clone(), &mut out).unwrap();
+        assert_eq!(out, new);
+        ops
+    }
+
+    fn data(len: usize, seed: u32) -> Vec<u8> {
+        let mut x = seed;
+        (0..len)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                (x >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rolling_checksum_matches_full_computation() {
+        let buf = data(100, 1);
+        let mut sum = RollingChecksum::new(&buf[0..16]);
+        for i in 0..buf.len() - 16 {
+            sum.roll(buf[i], buf[i + 16]);
+            assert_eq!(
+                sum.digest(),
+                RollingChecksum::new(&buf[i + 1..i + 17]).digest()
+            );
+        }
+    }
+
+    #[test]
+    fn delta_sends_only_changed_blocks() {
+        let old = data(64 * 1024 + 100, 2);
+        let mut new = old.clone();
+        new[10_000] ^= 0xff;
+        new.splice(30_000..30_000, b"inserted".iter().copied());
+        let ops = round_trip(&old, &new, 4096);
+        let literal: usize = ops
+            .iter()
+            .map(|op| match op {
+                DeltaOp::Data(d) => d.len(),
+                _ => 0,
+            })
+            .sum();
+        assert!(literal <= 3 * 4096, "literal {}", literal);
+    }
+
+    #[test]
+    fn delta_handles_edge_cases() {
+        round_trip(b"", b"new file", 4096);
+        round_trip(b"old file", b"", 4096);
+        let old = data(10_000, 3);
+        assert_eq!(
+            round_trip(&old, &old, 4096),
+            vec![DeltaOp::Copy { index: 0, count: 3 }]
+        );
+        round_trip(&old, &data(9_000, 4), 4096);
+        round_trip(&old, &old[100..], 4096);
+    }
+
+    #[test]
+    fn block_size_is_clamped() {
+        assert_eq!(block_size(0), MIN_BLOCK_SIZE);
+        assert_eq!(block_size(4 << 30), 65536);
+        assert_eq!(block_size(u64::MAX), MAX_BLOCK_SIZE);
+    }
+
+    #[test]
+    fn signatures_of_the_peer_are_checked() {
+        let old = data(10_000, 5);
+        let sig = signature(&mut Cursor::new(&old), 4096).unwrap();
+        let msg = sig.to_message(1, 0);
+        let proto = msg.file_response().signature().clone();
+        assert_eq!(Signature::from_proto(&proto), Some(sig));
+        let mut truncated = proto.clone();
+        truncated.blocks.pop();
+        assert_eq!(Signature::from_proto(&truncated), None);
+        let mut small = proto;
+        small.block_size = 16;
+        assert_eq!(Signature::from_proto(&small), None);
+    }
+
+    #[test]
+    fn files_are_replaced_by_their_delta() {
+        let dir = std::env::temp_dir().join(format!("file_delta_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file");
+        let old = data(200_000, 6);
+        let mut new = old.clone();
+        new[150_000] ^= 0xff;
+        std::fs::write(&path, &old).unwrap();
+        let new_path = dir.join("new");
+        std::fs::write(&new_path, &new).unwrap();
+
+        let (mut write, msg) = DeltaWrite::new(1, 0, path.clone()).unwrap();
+        let sig = Signature::from_proto(msg.file_response().signature()).unwrap();
+        let mut read = DeltaRead::new(1, 0, new_path.to_str().unwrap(), sig).unwrap();
+        let mut done = false;
+        while let Some(msg) = read.next_message().unwrap() {
+            done = write.write(msg.file_response().delta().clone()).unwrap();
+        }
+        assert!(done);
+        assert_eq!(std::fs::read(&path).unwrap(), new);
+        assert!(!write.tmp_path.exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
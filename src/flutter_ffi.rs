@@ -881,6 +881,12 @@ pub fn session_resume_job(session_id: SessionID, act_id: i32, is_remote: bool) {
     }
 }
 
+pub fn session_set_job_priority(session_id: SessionID, act_id: i32, priority: i32) {
+    if let Some(session) = sessions::get_session_by_session_id(&session_id) {
+        session.set_job_priority(act_id, priority);
+    }
+}
+
 pub fn session_rename_file(
     session_id: SessionID,
     act_id: i32,
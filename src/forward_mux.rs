@@ -0,0 +1,478 @@
+//! Several TCP connections over one port forwarding connection.
+//!
+//! Like UDP forwarding, the stream keeps its framing after login and each message is a
+//! [`Frame`] of one of the connections: the kind as 1 byte, the id of the connection as 4
+//! bytes big endian, then the payload. The side accepting the local connections opens them
+//! with [`Frame::Open`], the other side connects to the target and relays. A side sends
+//! [`Frame::Shutdown`] once its socket is at EOF, the other side shuts down the writing of its
+//! socket and the connection ends when both have. Either side aborts a connection with
+//! [`Frame::Close`].
+//!
+//! Each side may send [`INITIAL_WINDOW`] data frames of a connection ahead, then one more for
+//! every [`Frame::Window`] credit the other side returns once it has written a frame to its
+//! socket, so a slow connection doesn't hold up the others. A connection getting more frames
+//! than its window is closed.
+//!
+//! It is used by:
+//! - Reverse port forwarding, with the `reverse://` prefix on the remote host of the
+//!   `PortForward` login, the remote port being the port to listen on at the controlled side.
+//!   The connections accepted there are opened back to the local port of the forward on the
+//!   controlling side.
+//! - Multiplexed port forwarding, with the `mux://` remote host followed by the comma separated
+//!   `host:port` targets the session may open, e.g. `mux://localhost:22,db:5432`. The TCP
+//!   forwards of a session share the connection, each open frame has one of these targets. The
+//!   controlled side shows the declared targets for approval and refuses any other.
+
+use hbb_common::{
+    bytes::{BufMut, Bytes, BytesMut},
+    log,
+    tokio::{
+        self,
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+        sync::{mpsc, oneshot, Semaphore},
+    },
+};
+use std::{collections::HashMap, sync::Arc};
+
+pub const REVERSE_PREFIX: &str = "reverse://";
+pub const MUX_HOST: &str = "mux://";
+pub const INITIAL_WINDOW: usize = 16;
+const HEADER_LEN: usize = 5;
+const READ_BUF_SIZE: usize = 64 * 1024;
+
+const KIND_OPEN: u8 = 0;
+const KIND_DATA: u8 = 1;
+const KIND_CLOSE: u8 = 2;
+const KIND_WINDOW: u8 = 3;
+const KIND_SHUTDOWN: u8 = 4;
+
+/// The bind address of a reverse forward without the `reverse://` prefix, `None` otherwise.
+pub fn strip_reverse_prefix(host: &str) -> Option<&str> {
+    host.strip_prefix(REVERSE_PREFIX)
+}
+
+/// The remote host of a multiplexed forward declaring `targets`.
+pub fn mux_host(targets: &[String]) -> String {
+    format!("{}{}", MUX_HOST, targets.join(","))
+}
+
+/// The targets declared in a `mux://` host, `None` for other hosts.
+pub fn parse_mux_host(host: &str) -> Option<Vec<String>> {
+    let targets = host.strip_prefix(MUX_HOST)?;
+    Some(
+        targets
+            .split(',')
+            .map(|x| x.trim().to_owned())
+            .filter(|x| !x.is_empty())
+            .collect(),
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    /// `target` is empty if the other side knows where to connect.
+    Open {
+        id: u32,
+        target: String,
+    },
+    Data {
+        id: u32,
+        data: Bytes,
+    },
+    Close {
+        id: u32,
+    },
+    /// `frames` more data frames may be sent.
+    Window {
+        id: u32,
+        frames: u32,
+    },
+    /// No more data frames are sent.
+    Shutdown {
+        id: u32,
+    },
+}
+
+impl Frame {
+    pub fn encode(&self) -> Bytes {
+        let (kind, id) = match self {
+            Frame::Open { id, .. } => (KIND_OPEN, *id),
+            Frame::Data { id, .. } => (KIND_DATA, *id),
+            Frame::Close { id } => (KIND_CLOSE, *id),
+            Frame::Window { id, .. } => (KIND_WINDOW, *id),
+            Frame::Shutdown { id } => (KIND_SHUTDOWN, *id),
+        };
+        let mut buf = BytesMut::with_capacity(HEADER_LEN);
+        buf.put_u8(kind);
+        buf.put_u32(id);
+        match self {
+            Frame::Open { target, .. } => buf.put_slice(target.as_bytes()),
+            Frame::Data { data, .. } => buf.put_slice(data),
+            Frame::Close { .. } | Frame::Shutdown { .. } => {}
+            Frame::Window { frames, .. } => buf.put_u32(*frames),
+        }
+        buf.freeze()
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Frame> {
+        if buf.len() < HEADER_LEN {
+            return None;
+        }
+        let id = u32::from_be_bytes(buf[1..HEADER_LEN].try_into().ok()?);
+        let payload = &buf[HEADER_LEN..];
+        match buf[0] {
+            KIND_OPEN => Some(Frame::Open {
+                id,
+                target: String::from_utf8(payload.to_vec()).ok()?,
+            }),
+            KIND_DATA => Some(Frame::Data {
+                id,
+                data: Bytes::copy_from_slice(payload),
+            }),
+            KIND_CLOSE => Some(Frame::Close { id }),
+            KIND_WINDOW => Some(Frame::Window {
+                id,
+                frames: u32::from_be_bytes(payload.try_into().ok()?),
+            }),
+            KIND_SHUTDOWN => Some(Frame::Shutdown { id }),
+            _ => None,
+        }
+    }
+}
+
+struct Conn {
+    // Dropped once the other side is at EOF, the socket is shut down when the data is written.
+    tx: Option<mpsc::UnboundedSender<Bytes>>,
+    // The data frames received and not written yet, at most the initial window.
+    queued: usize,
+    // The socket of this side is at EOF.
+    eof: bool,
+    credit: Arc<Semaphore>,
+    // Dropped to stop reading the socket.
+    _close: oneshot::Sender<()>,
+}
+
+/// A connection opened by the other side, until its socket is connected. The data received
+/// meanwhile is queued.
+pub struct Pending {
+    id: u32,
+    rx: mpsc::UnboundedReceiver<Bytes>,
+    credit: Arc<Semaphore>,
+    close: oneshot::Receiver<()>,
+    frames: mpsc::UnboundedSender<Frame>,
+}
+
+impl Pending {
+    pub fn start(self, socket: TcpStream) {
+        let Pending {
+            id,
+            mut rx,
+            credit,
+            close,
+            frames,
+        } = self;
+        let (mut read, mut write) = socket.into_split();
+        let window = frames.clone();
+        tokio::spawn(async move {
+            while let Some(data) = rx.recv().await {
+                if let Err(err) = write.write_all(&data).await {
+                    log::debug!("forward connection {} write error: {}", id, err);
+                    window.send(Frame::Close { id }).ok();
+                    return;
+                }
+                window.send(Frame::Window { id, frames: 1 }).ok();
+            }
+            write.shutdown().await.ok();
+        });
+        let reader = async move {
+            let mut buf = vec![0u8; READ_BUF_SIZE];
+            loop {
+                let Ok(permit) = credit.acquire().await else {
+                    break;
+                };
+                permit.forget();
+                match read.read(&mut buf).await {
+                    Ok(0) => {
+                        // The other direction is kept until the other side shuts it down.
+                        frames.send(Frame::Shutdown { id }).ok();
+                        return;
+                    }
+                    Ok(n) => {
+                        let data = Bytes::copy_from_slice(&buf[..n]);
+                        if frames.send(Frame::Data { id, data }).is_err() {
+                            return;
+                        }
+                    }
+                    Err(err) => {
+                        log::debug!("forward connection {} read error: {}", id, err);
+                        break;
+                    }
+                }
+            }
+            frames.send(Frame::Close { id }).ok();
+        };
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = reader => {}
+                _ = close => {}
+            }
+        });
+    }
+
+    /// The connection failed, it is closed on both sides.
+    pub fn fail(self) {
+        self.frames.send(Frame::Close { id: self.id }).ok();
+    }
+}
+
+/// The local connections of one side.
+pub struct Mux {
+    conns: HashMap<u32, Conn>,
+    tx: mpsc::UnboundedSender<Frame>,
+    rx: mpsc::UnboundedReceiver<Frame>,
+    next_id: u32,
+}
+
+impl Default for Mux {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self {
+            conns: Default::default(),
+            tx,
+            rx,
+            next_id: 0,
+        }
+    }
+}
+
+impl Mux {
+    /// Add an accepted connection, the returned frame opens it on the other side.
+    pub fn accept(&mut self, socket: TcpStream, target: String) -> Frame {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.reserve(id).start(socket);
+        Frame::Open { id, target }
+    }
+
+    /// Add the connection of an open frame of the other side, to be connected by the caller.
+    pub fn reserve(&mut self, id: u32) -> Pending {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (close_tx, close) = oneshot::channel();
+        let credit = Arc::new(Semaphore::new(INITIAL_WINDOW));
+        self.conns.insert(
+            id,
+            Conn {
+                tx: Some(tx),
+                queued: 0,
+                eof: false,
+                credit: credit.clone(),
+                _close: close_tx,
+            },
+        );
+        Pending {
+            id,
+            rx,
+            credit,
+            close,
+            frames: self.tx.clone(),
+        }
+    }
+
+    /// The next frame to send to the other side.
+    pub async fn next(&mut self) -> Option<Frame> {
+        let frame = self.rx.recv().await?;
+        match frame {
+            Frame::Close { id } => {
+                self.conns.remove(&id);
+            }
+            Frame::Window { id, frames } => {
+                if let Some(conn) = self.conns.get_mut(&id) {
+                    conn.queued = conn.queued.saturating_sub(frames as usize);
+                }
+            }
+            Frame::Shutdown { id } => {
+                if let Some(conn) = self.conns.get_mut(&id) {
+                    conn.eof = true;
+                    if conn.tx.is_none() {
+                        self.conns.remove(&id);
+                    }
+                }
+            }
+            _ => {}
+        }
+        Some(frame)
+    }
+
+    /// Relay a frame of the other side, open frames are returned to be connected by the
+    /// caller with [`Self::reserve`].
+    pub fn handle(&mut self, frame: Frame) -> Option<(u32, String)> {
+        match frame {
+            Frame::Open { id, target } => return Some((id, target)),
+            Frame::Data { id, data } => {
+                if let Some(conn) = self.conns.get_mut(&id) {
+                    conn.queued += 1;
+                    if conn.queued > INITIAL_WINDOW {
+                        log::warn!("forward connection {} sent over its window", id);
+                        self.conns.remove(&id);
+                        self.tx.send(Frame::Close { id }).ok();
+                    } else if let Some(tx) = conn.tx.as_ref() {
+                        tx.send(data).ok();
+                    }
+                }
+            }
+            Frame::Close { id } => {
+                self.conns.remove(&id);
+            }
+            Frame::Shutdown { id } => {
+                if let Some(conn) = self.conns.get_mut(&id) {
+                    conn.tx = None;
+                    if conn.eof {
+                        self.conns.remove(&id);
+                    }
+                }
+            }
+            Frame::Window { id, frames } => {
+                if let Some(conn) = self.conns.get(&id) {
+                    // Never more than the initial window, whatever the other side sends.
+                    let room = INITIAL_WINDOW.saturating_sub(conn.credit.available_permits());
+                    conn.credit.add_permits((frames as usize).min(room));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_round_trip() {
+        for frame in [
+            Frame::Open {
+                id: 1,
+                target: "".to_owned(),
+            },
+            Frame::Open {
+                id: 2,
+                target: "localhost:22".to_owned(),
+            },
+            Frame::Data {
+                id: u32::MAX,
+                data: Bytes::from_static(b"data"),
+            },
+            Frame::Close { id: 3 },
+            Frame::Window { id: 4, frames: 2 },
+            Frame::Shutdown { id: 5 },
+        ] {
+            assert_eq!(Frame::decode(&frame.encode()), Some(frame));
+        }
+        assert_eq!(Frame::decode(&[KIND_DATA, 0, 0]), None);
+        assert_eq!(Frame::decode(&[9, 0, 0, 0, 0]), None);
+        assert_eq!(Frame::decode(&[KIND_WINDOW, 0, 0, 0, 0, 1]), None);
+    }
+
+    #[test]
+    fn mux_host_round_trip() {
+        let targets = vec!["localhost:22".to_owned(), "[::1]:5432".to_owned()];
+        assert_eq!(mux_host(&targets), "mux://localhost:22,[::1]:5432");
+        assert_eq!(parse_mux_host(&mux_host(&targets)), Some(targets));
+        assert_eq!(parse_mux_host("mux://"), Some(vec![]));
+        assert_eq!(parse_mux_host("localhost"), None);
+    }
+
+    async fn next(mux: &mut Mux) -> Option<Frame> {
+        tokio::time::timeout(std::time::Duration::from_millis(200), mux.next())
+            .await
+            .ok()
+            .flatten()
+    }
+
+    #[tokio::test]
+    async fn data_is_held_back_without_credit() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        tokio::spawn(async move {
+            client.write_all(&vec![0u8; 4 << 20]).await.ok();
+        });
+        let mut mux = Mux::default();
+        let Frame::Open { id, .. } = mux.accept(socket, "".to_owned()) else {
+            panic!("not an open frame");
+        };
+        for _ in 0..INITIAL_WINDOW {
+            assert!(matches!(next(&mut mux).await, Some(Frame::Data { .. })));
+        }
+        assert_eq!(next(&mut mux).await, None);
+        mux.handle(Frame::Window { id, frames: 1 });
+        assert!(matches!(next(&mut mux).await, Some(Frame::Data { .. })));
+        assert_eq!(next(&mut mux).await, None);
+    }
+
+    async fn socket_pair() -> (TcpStream, TcpStream) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        (client, socket)
+    }
+
+    #[tokio::test]
+    async fn connections_over_their_window_are_closed() {
+        let (_client, socket) = socket_pair().await;
+        let mut mux = Mux::default();
+        mux.reserve(7).start(socket);
+        let data = Bytes::from_static(b"data");
+        for _ in 0..=INITIAL_WINDOW {
+            mux.handle(Frame::Data {
+                id: 7,
+                data: data.clone(),
+            });
+        }
+        assert!(mux.conns.is_empty());
+        loop {
+            match next(&mut mux).await {
+                Some(Frame::Close { id }) => {
+                    assert_eq!(id, 7);
+                    break;
+                }
+                Some(Frame::Window { .. }) => {}
+                frame => panic!("unexpected {:?}", frame),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn connections_are_half_closed() {
+        let (mut client, socket) = socket_pair().await;
+        let mut mux = Mux::default();
+        let Frame::Open { id, .. } = mux.accept(socket, "".to_owned()) else {
+            panic!("not an open frame");
+        };
+        client.write_all(b"ping").await.unwrap();
+        client.shutdown().await.unwrap();
+        assert_eq!(
+            next(&mut mux).await,
+            Some(Frame::Data {
+                id,
+                data: Bytes::from_static(b"ping")
+            })
+        );
+        assert_eq!(next(&mut mux).await, Some(Frame::Shutdown { id }));
+        // The answer still gets through after the EOF of the client.
+        mux.handle(Frame::Data {
+            id,
+            data: Bytes::from_static(b"pong"),
+        });
+        mux.handle(Frame::Shutdown { id });
+        assert!(mux.conns.is_empty());
+        let mut out = vec![];
+        client.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, b"pong");
+    }
+}
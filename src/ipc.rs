@@ -234,6 +234,14 @@ pub enum FS {
         include_hidden: bool,
         conn_id: i32,
     },
+    // Serialized `FileTransferSignature` of a CM-read job, sent with `send_raw()` like `WriteBlock`.
+    ReadDelta {
+        data: Bytes,
+    },
+    // Serialized `FileTransferDelta` of a write job, sent with `send_raw()` like `WriteBlock`.
+    WriteDelta {
+        data: Bytes,
+    },
 }
 
 #[cfg(target_os = "windows")]
@@ -401,6 +409,8 @@ pub enum Data {
     StartVoiceCall,
     VoiceCallResponse(bool),
     CloseVoiceCall(String),
+    /// The recording of incoming sessions failed while it is enforced, with the error.
+    RecordingFailed(String),
     #[cfg(windows)]
     SyncWinCpuUsage(Option<f64>),
     FileTransferLog((String, String)),
@@ -758,5 +758,9 @@ pub static ref T: std::collections::HashMap<&'static str, &'static str> =
         ("Continue", "متابعة"),
         ("Browser didn't open? Use the url below to sign in.", "لم يفتح المتصفح؟ استخدم الرابط أدناه لتسجيل الدخول."),
         ("Lock canvas", "قفل اللوحة"),
+        ("Mute recordings", ""),
+        ("incoming-session-recorded-tip", ""),
+        ("Screen content", ""),
+        ("Unattended access is not allowed at this time", ""),
     ].iter().cloned().collect();
 }
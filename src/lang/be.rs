@@ -758,5 +758,9 @@ pub static ref T: std::collections::HashMap<&'static str, &'static str> =
         ("Continue", "Працягнуць"),
         ("Browser didn't open? Use the url below to sign in.", "Браўзер не адкрыўся? Скарыстайцеся спасылкай ніжэй, каб увайсці."),
         ("Lock canvas", "Заблакіраваць палатно"),
+        ("Mute recordings", ""),
+        ("incoming-session-recorded-tip", ""),
+        ("Screen content", ""),
+        ("Unattended access is not allowed at this time", ""),
     ].iter().cloned().collect();
 }
@@ -758,5 +758,9 @@ pub static ref T: std::collections::HashMap<&'static str, &'static str> =
         ("Continue", "Продължи"),
         ("Browser didn't open? Use the url below to sign in.", "Браузърът не се отвори? Използвайте URL адреса по-долу, за да се впишете."),
         ("Lock canvas", "Заключване на платното"),
+        ("Mute recordings", ""),
+        ("incoming-session-recorded-tip", ""),
+        ("Screen content", ""),
+        ("Unattended access is not allowed at this time", ""),
     ].iter().cloned().collect();
 }
@@ -758,5 +758,9 @@ pub static ref T: std::collections::HashMap<&'static str, &'static str> =
         ("Continue", "Continua"),
         ("Browser didn't open? Use the url below to sign in.", "No s'ha obert el navegador? Utilitzeu l'URL de sota per iniciar la sessió."),
         ("Lock canvas", "Bloca el llenç"),
+        ("Mute recordings", ""),
+        ("incoming-session-recorded-tip", ""),
+        ("Screen content", ""),
+        ("Unattended access is not allowed at this time", ""),
     ].iter().cloned().collect();
 }
@@ -758,5 +758,9 @@ pub static ref T: std::collections::HashMap<&'static str, &'static str> =
         ("Continue", "继续"),
         ("Browser didn't open? Use the url below to sign in.", "浏览器未打开？请使用下方网址登录。"),
         ("Lock canvas", "锁定画布"),
+        ("Mute recordings", "静音录像"),
+        ("incoming-session-recorded-tip", "此会话正在被远程设备录制。"),
+        ("Screen content", "屏幕内容"),
+        ("Unattended access is not allowed at this time", "当前时间不允许无人值守访问"),
     ].iter().cloned().collect();
 }
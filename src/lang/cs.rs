@@ -758,5 +758,9 @@ pub static ref T: std::collections::HashMap<&'static str, &'static str> =
         ("Continue", "Pokračovat"),
         ("Browser didn't open? Use the url below to sign in.", "Neotevřel se prohlížeč? Pro přihlášení použijte URL níže."),
         ("Lock canvas", "Zamknout zobrazení"),
+        ("Mute recordings", ""),
+        ("incoming-session-recorded-tip", ""),
+        ("Screen content", ""),
+        ("Unattended access is not allowed at this time", ""),
     ].iter().cloned().collect();
 }
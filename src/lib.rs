@@ -40,8 +40,11 @@ mod auth_2fa;
 #[cfg(not(target_os = "ios"))]
 mod clipboard;
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod cli;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
 pub mod core_main;
 mod custom_server;
+pub mod file_delta;
 mod lang;
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 mod port_forward;
@@ -55,6 +58,7 @@ mod whiteboard;
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 mod updater;
 
+mod transfer_limit;
 mod ui_cm_interface;
 mod ui_interface;
 mod ui_session_interface;
@@ -70,3 +74,8 @@ pub mod privacy_mode;
 pub mod virtual_display_manager;
 
 mod kcp_stream;
+mod ws_fallback;
+#[cfg(not(any(target_os = "ios")))]
+mod udp_forward;
+#[cfg(not(any(target_os = "ios")))]
+mod forward_mux;
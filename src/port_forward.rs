@@ -1,6 +1,9 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    sync::{Arc, RwLock},
+    time::Instant,
+};
 
-use crate::client::*;
+use crate::{client::*, forward_mux, udp_forward};
 use hbb_common::{
     allow_err, bail,
     config::READ_TIMEOUT,
@@ -10,11 +13,19 @@ use hbb_common::{
     protobuf::Message as _,
     rendezvous_proto::ConnType,
     tcp, timeout,
-    tokio::{self, net::TcpStream, sync::mpsc},
+    tokio::{
+        self,
+        net::{TcpStream, UdpSocket},
+        sync::mpsc,
+    },
     tokio_util::codec::{BytesCodec, Framed},
     ResultType, Stream,
 };
 
+mod socks5;
+
+const RECONNECT_INTERVAL_SECS: f32 = 3.;
+
 fn run_rdp(port: u16, name: &str) {
     std::process::Command::new("cmdkey")
         .arg("/delete:localhost")
@@ -79,41 +90,129 @@ pub async fn listen(
     lc: Arc<RwLock<LoginConfigHandler>>,
     remote_host: String,
     remote_port: i32,
+    mux: Option<MuxHandle>,
 ) -> ResultType<()> {
+    if udp_forward::strip_prefix(&remote_host).is_some() {
+        return listen_udp(
+            id,
+            password,
+            port,
+            interface,
+            ui_receiver,
+            key,
+            token,
+            lc,
+            remote_host,
+            remote_port,
+        )
+        .await;
+    }
+    if forward_mux::strip_reverse_prefix(&remote_host).is_some() {
+        return listen_reverse(
+            id,
+            password,
+            port,
+            interface,
+            ui_receiver,
+            key,
+            token,
+            lc,
+            remote_host,
+            remote_port,
+        )
+        .await;
+    }
     let listener = tcp::new_listener(format!("127.0.0.1:{}", port), true).await?;
     let addr = listener.local_addr()?;
     log::info!("listening on port {:?}", addr);
     let is_rdp = port == 0;
+    let is_socks5 = socks5::is_socks5(&remote_host, remote_port);
     if is_rdp {
         run_rdp(addr.port(), &rdp_display_name(&lc, &id));
     }
     let mut ui_receiver = ui_receiver;
+    // The accepted sockets with their target, after the SOCKS5 handshake if any.
+    let (accepted_tx, mut accepted_rx) = mpsc::unbounded_channel();
     loop {
         tokio::select! {
             Ok((forward, addr)) = listener.accept() => {
                 log::info!("new connection from {:?}", addr);
-                lc.write().unwrap().port_forward = (remote_host.clone(), remote_port);
+                if is_socks5 {
+                    // In its own task, a slow client must not hold the other ones.
+                    let accepted_tx = accepted_tx.clone();
+                    tokio::spawn(async move {
+                        let mut forward = forward;
+                        match timeout(READ_TIMEOUT, socks5::accept(&mut forward)).await {
+                            Ok(Ok((host, port))) => {
+                                accepted_tx.send((forward, addr, (host, port as i32))).ok();
+                            }
+                            Ok(Err(err)) => {
+                                log::warn!("SOCKS5 handshake from {:?} failed: {}", addr, err);
+                            }
+                            Err(_) => {
+                                log::warn!("SOCKS5 handshake from {:?} timed out", addr);
+                            }
+                        }
+                    });
+                } else {
+                    accepted_tx.send((forward, addr, (remote_host.clone(), remote_port))).ok();
+                }
+            }
+            Some((forward, addr, target)) = accepted_rx.recv() => {
+                log::info!("forwarding to {}:{}", target.0, target.1);
+                let declared = mux.as_ref().and_then(|mux| {
+                    mux_target(&target.0, target.1)
+                        .filter(|x| mux.declares(x))
+                        .map(|x| (mux, x))
+                });
+                // Held by the first login while the version of the peer is unknown, it tells
+                // whether the forwards after it can share the connection.
+                let mut _negotiating = None;
+                if let Some((mux, _)) = declared.as_ref() {
+                    if lc.read().unwrap().version == 0 {
+                        _negotiating = Some(mux.2.clone().lock_owned().await);
+                    }
+                }
+                let multiplex = crate::common::is_support_port_forward_multiplex_num(lc.read().unwrap().version);
+                if let Some((mux, mux_target)) = declared.filter(|_| multiplex) {
+                    mux.0.send((forward, mux_target)).ok();
+                    continue;
+                }
+                lc.write().unwrap().port_forward = target;
                 let id = id.clone();
                 let password = password.clone();
                 let mut forward = Framed::new(forward, BytesCodec::new());
                 let mut close_port_forward = false;
-                match connect_and_login(&id, &password, &mut ui_receiver, interface.clone(), &mut forward, key, token, is_rdp, &mut close_port_forward).await {
+                let res = connect_and_login(&id, &password, &mut ui_receiver, interface.clone(), Some(&mut forward), key, token, is_rdp, &mut close_port_forward).await;
+                match res {
                     Ok(Some(stream)) => {
                         let interface = interface.clone();
                         tokio::spawn(async move {
+                            if is_socks5 {
+                                allow_err!(socks5::reply(forward.get_mut(), socks5::REPLY_SUCCEEDED).await);
+                            }
                             if let Err(err) = run_forward(forward, stream).await {
                                 interface.msgbox("error", "Error", &err.to_string(), "");
                             }
                             log::info!("connection from {:?} closed", addr);
                        });
                     }
-                    _ if close_port_forward => {
-                        break;
-                    }
-                    Err(err) => {
-                        interface.on_establish_connection_error(err.to_string());
+                    res => {
+                        if is_socks5 {
+                            tokio::spawn(async move {
+                                allow_err!(socks5::reply(forward.get_mut(), socks5::REPLY_GENERAL_FAILURE).await);
+                            });
+                        }
+                        match res {
+                            _ if close_port_forward => {
+                                break;
+                            }
+                            Err(err) => {
+                                interface.on_establish_connection_error(err.to_string());
+                            }
+                            _ => {}
+                        }
                     }
-                    _ => {}
                 }
             }
             d = ui_receiver.recv() => {
@@ -133,12 +232,400 @@ pub async fn listen(
     Ok(())
 }
 
+// One connection carries all the flows, it is made on the first datagram and again on the
+// next one if it is closed.
+async fn listen_udp(
+    id: String,
+    password: String,
+    port: i32,
+    interface: impl Interface,
+    ui_receiver: mpsc::UnboundedReceiver<Data>,
+    key: &str,
+    token: &str,
+    lc: Arc<RwLock<LoginConfigHandler>>,
+    remote_host: String,
+    remote_port: i32,
+) -> ResultType<()> {
+    let socket = UdpSocket::bind(format!("127.0.0.1:{}", port)).await?;
+    log::info!("listening on udp port {:?}", socket.local_addr()?);
+    let mut ui_receiver = ui_receiver;
+    let mut flows = udp_forward::Flows::default();
+    let mut stream: Option<Stream> = None;
+    let mut buf = vec![0u8; udp_forward::MAX_DATAGRAM_SIZE];
+    let mut timer =
+        crate::rustdesk_interval(tokio::time::interval(udp_forward::FLOW_IDLE_TIMEOUT / 4));
+    loop {
+        tokio::select! {
+            res = socket.recv_from(&mut buf) => {
+                let (n, addr) = match res {
+                    Ok(res) => res,
+                    Err(err) => {
+                        // e.g. ICMP port unreachable of a reply on Windows
+                        log::debug!("udp forward recv error: {}", err);
+                        continue;
+                    }
+                };
+                if stream.is_none() {
+                    log::info!("new udp flow from {:?}", addr);
+                    lc.write().unwrap().port_forward = (remote_host.clone(), remote_port);
+                    let mut close_port_forward = false;
+                    match connect_and_login(&id, &password, &mut ui_receiver, interface.clone(), None, key, token, false, &mut close_port_forward).await {
+                        Ok(Some(s)) => {
+                            log::info!("new udp port forwarding connection started");
+                            stream = Some(s);
+                        }
+                        _ if close_port_forward => {
+                            break;
+                        }
+                        Err(err) => {
+                            interface.on_establish_connection_error(err.to_string());
+                            continue;
+                        }
+                        _ => {
+                            continue;
+                        }
+                    }
+                }
+                if let Some(s) = stream.as_mut() {
+                    let flow = flows.id(addr, Instant::now());
+                    if let Err(err) = s.send_bytes(udp_forward::encode(flow, &buf[..n])).await {
+                        log::info!("udp port forwarding connection closed: {}", err);
+                        stream = None;
+                        flows.clear();
+                    }
+                }
+            }
+            res = async {
+                match stream.as_mut() {
+                    Some(s) => s.next().await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                if let Some(Ok(frame)) = res {
+                    if let Some((flow, payload)) = udp_forward::decode(&frame) {
+                        if let Some(addr) = flows.addr(flow, Instant::now()) {
+                            allow_err!(socket.send_to(payload, addr).await);
+                        }
+                    }
+                } else {
+                    log::info!("udp port forwarding connection closed");
+                    stream = None;
+                    flows.clear();
+                }
+            }
+            _ = timer.tick() => {
+                flows.remove_idle(Instant::now());
+            }
+            d = ui_receiver.recv() => {
+                if let Some(Data::Close) = d {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether a forward added to the session can be started. The remote port of a SOCKS5 forward
+/// is 0, each accepted client tells its own target.
+pub fn is_valid_forward(port: i32, remote_host: &str, remote_port: i32) -> bool {
+    port > 0 && (remote_port > 0 || socks5::is_socks5(remote_host, remote_port))
+}
+
+/// The forward of `--port-forward remote-id listen-port remote-host remote-port`, without the
+/// remote id.
+pub fn parse_forward_args(args: &[String]) -> Option<(i32, String, i32)> {
+    let [port, remote_host, remote_port] = args else {
+        return None;
+    };
+    let port = port.parse::<i32>().ok()?;
+    let remote_port = remote_port.parse::<i32>().ok()?;
+    is_valid_forward(port, remote_host, remote_port)
+        .then(|| (port, remote_host.clone(), remote_port))
+}
+
+/// The `host:port` a forward connects to over the shared connection, `None` if it can't be
+/// multiplexed.
+pub fn mux_target(remote_host: &str, remote_port: i32) -> Option<String> {
+    if remote_port <= 0
+        || udp_forward::strip_prefix(remote_host).is_some()
+        || forward_mux::strip_reverse_prefix(remote_host).is_some()
+        || socks5::is_socks5(remote_host, remote_port)
+    {
+        return None;
+    }
+    Some(format!("{}:{}", remote_host, remote_port))
+}
+
+/// The connection shared by the TCP forwards of a session, see `forward_mux`. It is only used
+/// once a login has told the version of the peer, older peers don't support it. The first
+/// forward makes its own connection to learn it, the other ones wait for its login.
+///
+/// The targets are declared at login for the controlled side to approve, forwards added to
+/// the session later make their own connections.
+#[derive(Clone)]
+pub struct MuxHandle(
+    mpsc::UnboundedSender<(TcpStream, String)>,
+    Arc<Vec<String>>,
+    Arc<tokio::sync::Mutex<()>>,
+);
+
+impl MuxHandle {
+    pub fn start(
+        id: String,
+        password: String,
+        interface: impl Interface,
+        ui_receiver: mpsc::UnboundedReceiver<Data>,
+        key: String,
+        token: String,
+        lc: Arc<RwLock<LoginConfigHandler>>,
+        targets: Vec<String>,
+    ) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let host = forward_mux::mux_host(&targets);
+        tokio::spawn(async move {
+            run_mux(
+                id,
+                password,
+                interface,
+                ui_receiver,
+                &key,
+                &token,
+                lc,
+                host,
+                rx,
+            )
+            .await;
+            log::info!("multiplexed port forwarding exit");
+        });
+        Self(tx, Arc::new(targets), Default::default())
+    }
+
+    fn declares(&self, target: &str) -> bool {
+        self.1.iter().any(|x| x == target)
+    }
+}
+
+// The login is made on the first connection and again on the next one if it is closed.
+async fn run_mux(
+    id: String,
+    password: String,
+    interface: impl Interface,
+    ui_receiver: mpsc::UnboundedReceiver<Data>,
+    key: &str,
+    token: &str,
+    lc: Arc<RwLock<LoginConfigHandler>>,
+    host: String,
+    mut requests: mpsc::UnboundedReceiver<(TcpStream, String)>,
+) {
+    let mut ui_receiver = ui_receiver;
+    let mut stream: Option<Stream> = None;
+    let mut mux = forward_mux::Mux::default();
+    loop {
+        let mut closed = false;
+        tokio::select! {
+            req = requests.recv() => {
+                let Some((socket, target)) = req else {
+                    break;
+                };
+                if stream.is_none() {
+                    lc.write().unwrap().port_forward = (host.clone(), 0);
+                    let mut close_port_forward = false;
+                    match connect_and_login(&id, &password, &mut ui_receiver, interface.clone(), None, key, token, false, &mut close_port_forward).await {
+                        Ok(Some(s)) => {
+                            log::info!("multiplexed port forwarding connection started");
+                            stream = Some(s);
+                        }
+                        _ if close_port_forward => {
+                            break;
+                        }
+                        Err(err) => {
+                            interface.on_establish_connection_error(err.to_string());
+                            continue;
+                        }
+                        _ => {
+                            continue;
+                        }
+                    }
+                }
+                if let Some(s) = stream.as_mut() {
+                    let frame = mux.accept(socket, target);
+                    closed = s.send_bytes(frame.encode()).await.is_err();
+                }
+            }
+            Some(frame) = mux.next() => {
+                if let Some(s) = stream.as_mut() {
+                    closed = s.send_bytes(frame.encode()).await.is_err();
+                }
+            }
+            res = async {
+                match stream.as_mut() {
+                    Some(s) => s.next().await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                match res.and_then(|res| res.ok()).and_then(|bytes| forward_mux::Frame::decode(&bytes)) {
+                    Some(frame) => {
+                        if let Some((id, _)) = mux.handle(frame) {
+                            // Connections are only opened from this side.
+                            mux.reserve(id).fail();
+                        }
+                    }
+                    None => closed = true,
+                }
+            }
+            d = ui_receiver.recv() => {
+                if let Some(Data::Close) | None = d {
+                    break;
+                }
+            }
+        }
+        if closed {
+            log::info!("multiplexed port forwarding connection closed");
+            stream = None;
+            mux = forward_mux::Mux::default();
+        }
+    }
+}
+
+// Nothing listens here, the controlled side listens on `remote_port` and the connections
+// accepted there are made to the local `port`. The login is made again if the connection
+// drops, until the forward is closed.
+async fn listen_reverse(
+    id: String,
+    password: String,
+    port: i32,
+    interface: impl Interface,
+    ui_receiver: mpsc::UnboundedReceiver<Data>,
+    key: &str,
+    token: &str,
+    lc: Arc<RwLock<LoginConfigHandler>>,
+    remote_host: String,
+    remote_port: i32,
+) -> ResultType<()> {
+    let target = format!("127.0.0.1:{}", port);
+    let mut ui_receiver = ui_receiver;
+    let mut started = false;
+    loop {
+        lc.write().unwrap().port_forward = (remote_host.clone(), remote_port);
+        let mut close_port_forward = false;
+        let stream = match connect_and_login(
+            &id,
+            &password,
+            &mut ui_receiver,
+            interface.clone(),
+            None,
+            key,
+            token,
+            false,
+            &mut close_port_forward,
+        )
+        .await
+        {
+            Ok(Some(stream)) => stream,
+            _ if close_port_forward => break,
+            Err(err) if started => {
+                // The peer may be restarting, the forward is kept until it is closed.
+                log::warn!("reverse port forwarding reconnection failed: {}", err);
+                if !wait_reconnect(&mut ui_receiver).await {
+                    break;
+                }
+                continue;
+            }
+            Err(err) => {
+                interface.on_establish_connection_error(err.to_string());
+                break;
+            }
+            Ok(None) => {
+                // The login error is shown by `handle_login_error`.
+                log::warn!("reverse port forwarding login to {} failed", id);
+                break;
+            }
+        };
+        started = true;
+        log::info!(
+            "reverse port forwarding from remote port {} to {} started",
+            remote_port,
+            target
+        );
+        if !run_reverse_forward(stream, &target, &mut ui_receiver).await {
+            break;
+        }
+        log::info!("reverse port forwarding connection closed, reconnecting");
+        if !wait_reconnect(&mut ui_receiver).await {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Returns false if the forward is closed meanwhile.
+async fn wait_reconnect(ui_receiver: &mut mpsc::UnboundedReceiver<Data>) -> bool {
+    let sleep = hbb_common::sleep(RECONNECT_INTERVAL_SECS);
+    tokio::pin!(sleep);
+    loop {
+        tokio::select! {
+            _ = &mut sleep => return true,
+            d = ui_receiver.recv() => {
+                if let Some(Data::Close) | None = d {
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+// Returns false if the forward is closed.
+async fn run_reverse_forward(
+    mut stream: Stream,
+    target: &str,
+    ui_receiver: &mut mpsc::UnboundedReceiver<Data>,
+) -> bool {
+    let mut mux = forward_mux::Mux::default();
+    loop {
+        tokio::select! {
+            Some(frame) = mux.next() => {
+                if stream.send_bytes(frame.encode()).await.is_err() {
+                    return true;
+                }
+            }
+            res = stream.next() => {
+                let Some(Ok(bytes)) = res else {
+                    return true;
+                };
+                let Some(frame) = forward_mux::Frame::decode(&bytes) else {
+                    log::error!("invalid reverse port forwarding frame");
+                    return true;
+                };
+                if let Some((id, _)) = mux.handle(frame) {
+                    let pending = mux.reserve(id);
+                    let target = target.to_owned();
+                    tokio::spawn(async move {
+                        match timeout(3000, TcpStream::connect(&target)).await {
+                            Ok(Ok(socket)) => return pending.start(socket),
+                            Ok(Err(err)) => log::warn!("failed to connect to {}: {}", target, err),
+                            Err(_) => log::warn!("connecting to {} timed out", target),
+                        }
+                        pending.fail();
+                    });
+                }
+            }
+            d = ui_receiver.recv() => {
+                if let Some(Data::Close) | None = d {
+                    return false;
+                }
+            }
+        }
+    }
+}
+
 async fn connect_and_login(
     id: &str,
     password: &str,
     ui_receiver: &mut mpsc::UnboundedReceiver<Data>,
     interface: impl Interface,
-    forward: &mut Framed<TcpStream, BytesCodec>,
+    // `None` for a UDP forward, whose datagrams keep the framing of the stream.
+    forward: Option<&mut Framed<TcpStream, BytesCodec>>,
     key: &str,
     token: &str,
     is_rdp: bool,
@@ -158,6 +645,7 @@ async fn connect_and_login(
             return Ok(None);
         }
     }
+    let mut forward = forward;
     let mut buffer = Vec::new();
     let mut received = false;
 
@@ -217,7 +705,12 @@ async fn connect_and_login(
                     _ => {}
                 }
             },
-            res = forward.next() => {
+            res = async {
+                match forward.as_mut() {
+                    Some(forward) => forward.next().await,
+                    None => std::future::pending().await,
+                }
+            } => {
                 if let Some(Ok(bytes)) = res {
                     buffer.extend(bytes);
                 } else {
@@ -226,6 +719,9 @@ async fn connect_and_login(
             },
         }
     }
+    if forward.is_none() {
+        return Ok(Some(stream));
+    }
     stream.set_raw();
     if !buffer.is_empty() {
         allow_err!(stream.send_bytes(buffer.into()).await);
@@ -257,3 +753,57 @@ async fn run_forward(forward: Framed<TcpStream, BytesCodec>, stream: Stream) ->
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hbb_common::tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn forwards_are_validated() {
+        assert_eq!(
+            parse_forward_args(&args(&["8080", "10.0.0.1", "80"])),
+            Some((8080, "10.0.0.1".to_owned(), 80))
+        );
+        assert!(parse_forward_args(&args(&["8080", "10.0.0.1", "0"])).is_none());
+        assert!(parse_forward_args(&args(&["0", "10.0.0.1", "80"])).is_none());
+        assert!(parse_forward_args(&args(&["8080", "10.0.0.1"])).is_none());
+        assert!(!is_valid_forward(1080, "socks5", 0));
+        assert!(!is_valid_forward(0, socks5::SOCKS5_HOST, 0));
+    }
+
+    #[tokio::test]
+    async fn socks5_forwards_reach_the_handshake() {
+        let (port, remote_host, remote_port) =
+            parse_forward_args(&args(&["1080", socks5::SOCKS5_HOST, "0"])).unwrap();
+        assert!(is_valid_forward(port, &remote_host, remote_port));
+        assert!(socks5::is_socks5(&remote_host, remote_port));
+        // Each client makes its own connection, with the target it asks for.
+        assert_eq!(mux_target(&remote_host, remote_port), None);
+
+        let listener = tcp::new_listener("127.0.0.1:0", true).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = tokio::spawn(async move {
+            let mut s = TcpStream::connect(addr).await.unwrap();
+            s.write_all(&[5, 1, 0]).await.unwrap();
+            s.write_all(&[5, 1, 0, 3, 8]).await.unwrap();
+            s.write_all(b"intranet").await.unwrap();
+            s.write_all(&22u16.to_be_bytes()).await.unwrap();
+            let mut out = [0u8; 12];
+            s.read_exact(&mut out).await.unwrap();
+            out
+        });
+        let (mut forward, _) = listener.accept().await.unwrap();
+        let target = socks5::accept(&mut forward).await.unwrap();
+        assert_eq!(target, ("intranet".to_owned(), 22));
+        socks5::reply(&mut forward, socks5::REPLY_SUCCEEDED)
+            .await
+            .unwrap();
+        let out = client.await.unwrap();
+        assert_eq!(out[..4], [5, 0, 5, socks5::REPLY_SUCCEEDED]);
+    }
+}
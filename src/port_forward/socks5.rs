@@ -0,0 +1,134 @@
+//! SOCKS5 (RFC 1928) handshake of dynamic port forwarding, without authentication since the
+//! listener is bound to localhost. Only CONNECT is supported, each accepted client opens a
+//! new forward to the destination it requests.
+
+use hbb_common::{
+    bail,
+    tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    ResultType,
+};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// Remote host of a dynamic forward, with remote port 0.
+pub const SOCKS5_HOST: &str = "SOCKS5";
+
+const VERSION: u8 = 5;
+const METHOD_NO_AUTH: u8 = 0;
+const METHOD_NONE_ACCEPTABLE: u8 = 0xff;
+const CMD_CONNECT: u8 = 1;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+pub const REPLY_SUCCEEDED: u8 = 0;
+pub const REPLY_GENERAL_FAILURE: u8 = 1;
+const REPLY_COMMAND_NOT_SUPPORTED: u8 = 7;
+const REPLY_ADDRESS_NOT_SUPPORTED: u8 = 8;
+
+pub fn is_socks5(remote_host: &str, remote_port: i32) -> bool {
+    remote_host == SOCKS5_HOST && remote_port == 0
+}
+
+/// Negotiate with the client and read its request, returns the destination host and port.
+/// IPv6 hosts are in brackets, so `host:port` can be parsed.
+pub async fn accept<S: AsyncRead + AsyncWrite + Unpin>(s: &mut S) -> ResultType<(String, u16)> {
+    let mut head = [0u8; 2];
+    s.read_exact(&mut head).await?;
+    if head[0] != VERSION {
+        bail!("Unsupported SOCKS version {}", head[0]);
+    }
+    let mut methods = vec![0u8; head[1] as usize];
+    s.read_exact(&mut methods).await?;
+    if !methods.contains(&METHOD_NO_AUTH) {
+        s.write_all(&[VERSION, METHOD_NONE_ACCEPTABLE]).await?;
+        bail!("No acceptable SOCKS5 authentication method");
+    }
+    s.write_all(&[VERSION, METHOD_NO_AUTH]).await?;
+
+    let mut request = [0u8; 4];
+    s.read_exact(&mut request).await?;
+    if request[0] != VERSION {
+        bail!("Unsupported SOCKS version {}", request[0]);
+    }
+    let host = match request[3] {
+        ATYP_IPV4 => {
+            let mut ip = [0u8; 4];
+            s.read_exact(&mut ip).await?;
+            Ipv4Addr::from(ip).to_string()
+        }
+        ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            s.read_exact(&mut len).await?;
+            let mut domain = vec![0u8; len[0] as usize];
+            s.read_exact(&mut domain).await?;
+            String::from_utf8(domain)?
+        }
+        ATYP_IPV6 => {
+            let mut ip = [0u8; 16];
+            s.read_exact(&mut ip).await?;
+            format!("[{}]", Ipv6Addr::from(ip))
+        }
+        atyp => {
+            reply(s, REPLY_ADDRESS_NOT_SUPPORTED).await?;
+            bail!("Unsupported SOCKS5 address type {}", atyp);
+        }
+    };
+    let port = s.read_u16().await?;
+    if request[1] != CMD_CONNECT {
+        reply(s, REPLY_COMMAND_NOT_SUPPORTED).await?;
+        bail!("Unsupported SOCKS5 command {}", request[1]);
+    }
+    Ok((host, port))
+}
+
+/// The bound address is not known on this side, it is reported as 0.0.0.0:0.
+pub async fn reply<S: AsyncWrite + Unpin>(s: &mut S, rep: u8) -> ResultType<()> {
+    s.write_all(&[VERSION, rep, 0, ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hbb_common::tokio;
+
+    async fn request(req: &[u8]) -> (ResultType<(String, u16)>, Vec<u8>) {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        client
+            .write_all(&[VERSION, 2, 2, METHOD_NO_AUTH])
+            .await
+            .unwrap();
+        client.write_all(req).await.unwrap();
+        let res = accept(&mut server).await;
+        drop(server);
+        let mut out = vec![];
+        client.read_to_end(&mut out).await.unwrap();
+        (res, out)
+    }
+
+    #[tokio::test]
+    async fn connect_requests_are_parsed() {
+        let (res, out) = request(&[5, 1, 0, 1, 10, 0, 0, 1, 0, 22]).await;
+        assert_eq!(res.unwrap(), ("10.0.0.1".to_owned(), 22));
+        assert_eq!(out, [VERSION, METHOD_NO_AUTH]);
+
+        let mut req = vec![5, 1, 0, 3, 8];
+        req.extend(b"intranet");
+        req.extend(443u16.to_be_bytes());
+        assert_eq!(request(&req).await.0.unwrap(), ("intranet".to_owned(), 443));
+
+        let mut req = vec![5, 1, 0, 4];
+        req.extend(Ipv6Addr::LOCALHOST.octets());
+        req.extend(80u16.to_be_bytes());
+        assert_eq!(request(&req).await.0.unwrap(), ("[::1]".to_owned(), 80));
+    }
+
+    #[tokio::test]
+    async fn other_commands_are_refused() {
+        // UDP ASSOCIATE
+        let (res, out) = request(&[5, 3, 0, 1, 0, 0, 0, 0, 0, 0]).await;
+        assert!(res.is_err());
+        assert_eq!(out[2..4], [VERSION, REPLY_COMMAND_NOT_SUPPORTED]);
+    }
+}
@@ -134,6 +134,10 @@ impl RendezvousMediator {
         tokio::spawn(async move {
             direct_server(server_cloned).await;
         });
+        let server_cloned = server.clone();
+        tokio::spawn(async move {
+            ws_direct_server(server_cloned).await;
+        });
         #[cfg(target_os = "android")]
         let start_lan_listening = true;
         #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -160,7 +164,9 @@ impl RendezvousMediator {
                     let server = server.clone();
                     let timeout = timeout.clone();
                     futs.push(tokio::spawn(async move {
-                        if let Err(err) = Self::start(server, host).await {
+                        let res = Self::start(server, host.clone()).await;
+                        crate::ws_fallback::on_mediator_exit(&host, res.is_ok());
+                        if let Err(err) = res {
                             let err = format!("rendezvous mediator error: {err}");
                             // When user reboot, there might be below error, waiting too long
                             // (CONNECT_TIMEOUT 18s) will make user think there is bug
@@ -300,6 +306,9 @@ impl RendezvousMediator {
                         if timeout {
                             fails += 1;
                             if fails >= MAX_FAILS2 {
+                                if last_register_resp.is_none() && crate::ws_fallback::is_enabled() {
+                                    bail!("No response from {} over UDP", host);
+                                }
                                 Config::update_latency(&host, -1);
                                 old_latency = 0;
                                 if last_dns_check.elapsed().as_millis() as i64 > DNS_INTERVAL {
@@ -416,7 +425,10 @@ impl RendezvousMediator {
     }
 
     pub async fn start_tcp(server: ServerPtr, host: String) -> ResultType<()> {
+        let endpoint = crate::ws_fallback::mediator_endpoint(&host);
         let host = check_port(&host, RENDEZVOUS_PORT);
+        let host_prefix = Self::get_host_prefix(&host);
+        let host = endpoint.unwrap_or(host);
         log::info!("start tcp: {}", hbb_common::websocket::check_ws(&host));
         let mut conn = connect_tcp(host.clone(), CONNECT_TIMEOUT).await?;
         let key = crate::get_key(true).await;
@@ -424,7 +436,7 @@ impl RendezvousMediator {
         let mut rz = Self {
             addr: conn.local_addr().into_target_addr()?,
             host: host.clone(),
-            host_prefix: Self::get_host_prefix(&host),
+            host_prefix,
             keep_alive: crate::DEFAULT_KEEP_ALIVE,
         };
         let mut timer = crate::rustdesk_interval(interval(crate::TIMER_OUT));
@@ -480,6 +492,7 @@ impl RendezvousMediator {
             || Config::is_proxy()
             || use_ws()
             || crate::is_udp_disabled()
+            || crate::ws_fallback::mediator_endpoint(&host).is_some()
         {
             Self::start_tcp(server, host).await
         } else {
@@ -551,7 +564,7 @@ impl RendezvousMediator {
         socket.send(&msg_out).await?;
         crate::create_relay_connection(
             server,
-            relay_server,
+            crate::ws_fallback::relay_endpoint(&self.host, relay_server),
             uuid,
             peer_addr,
             secure,
@@ -572,7 +585,8 @@ impl RendezvousMediator {
         }
         let peer_addr_v6 = hbb_common::AddrMangle::decode(&fla.socket_addr_v6);
         let relay_server = self.get_relay_server(fla.relay_server.clone());
-        let relay = use_ws() || Config::is_proxy();
+        let relay =
+            use_ws() || Config::is_proxy() || crate::ws_fallback::is_ws_endpoint(&self.host);
         let mut socket_addr_v6 = Default::default();
         let meta = connection_meta(
             fla.control_permissions.clone().into_option(),
@@ -651,7 +665,10 @@ impl RendezvousMediator {
             return Ok(());
         }
         let peer_addr_v6 = hbb_common::AddrMangle::decode(&ph.socket_addr_v6);
-        let relay = use_ws() || Config::is_proxy() || ph.force_relay;
+        let relay = use_ws()
+            || Config::is_proxy()
+            || ph.force_relay
+            || crate::ws_fallback::is_ws_endpoint(&self.host);
         let mut socket_addr_v6 = Default::default();
         let meta = connection_meta(
             ph.control_permissions.into_option(),
@@ -907,6 +924,72 @@ async fn direct_server(server: ServerPtr) {
     }
 }
 
+// Direct access over WebSocket on the port after the direct access port, while the
+// websocket fallback is on, see `ws_fallback`.
+async fn ws_direct_server(server: ServerPtr) {
+    let mut listener: Option<(tokio::net::TcpListener, i32)> = None;
+    // Not retried until the port changes.
+    let mut failed_port = None;
+    loop {
+        let enabled = crate::ws_fallback::is_enabled()
+            && option2bool(
+                OPTION_DIRECT_SERVER,
+                &Config::get_option(OPTION_DIRECT_SERVER),
+            )
+            && !option2bool("stop-service", &Config::get_option("stop-service"));
+        let port = get_direct_port() + 1;
+        if !enabled || listener.as_ref().map_or(false, |(_, p)| *p != port) {
+            if listener.take().is_some() {
+                log::info!("Exit websocket direct access listen");
+            }
+        }
+        if enabled && listener.is_none() && failed_port != Some(port) {
+            match hbb_common::tcp::listen_any(port as _).await {
+                Ok(l) => {
+                    log::info!("Websocket direct server listening on port {}", port);
+                    listener = Some((l, port));
+                }
+                Err(err) => {
+                    failed_port = Some(port);
+                    log::error!(
+                        "Failed to start websocket direct server on port: {}, error: {}",
+                        port,
+                        err
+                    );
+                }
+            }
+        }
+        let Some((l, _)) = listener.as_mut() else {
+            sleep(1.).await;
+            continue;
+        };
+        if let Ok(Ok((stream, addr))) = hbb_common::timeout(1000, l.accept()).await {
+            stream.set_nodelay(true).ok();
+            log::info!("websocket direct access from {}", addr);
+            let server = server.clone();
+            tokio::spawn(async move {
+                let stream = match crate::ws_fallback::accept(stream).await {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        log::warn!("Websocket handshake from {} failed: {}", addr, err);
+                        return;
+                    }
+                };
+                allow_err!(
+                    crate::server::create_tcp_connection(
+                        server,
+                        stream,
+                        addr,
+                        false,
+                        ConnectionMeta::default(),
+                    )
+                    .await
+                );
+            });
+        }
+    }
+}
+
 enum Sink<'a> {
     Framed(&'a mut FramedSocket, &'a TargetAddr<'a>),
     Stream(&'a mut Stream),
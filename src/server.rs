@@ -32,10 +32,17 @@ use video_service::VideoSource;
 
 use crate::ipc::Data;
 
+pub mod access_schedule;
 pub mod audio_service;
+pub mod audit_sink;
+pub mod audit_spool;
 #[cfg(target_os = "windows")]
 pub mod terminal_helper;
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub mod terminal_profile;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub mod terminal_record;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
 pub mod terminal_service;
 cfg_if::cfg_if! {
 if #[cfg(not(target_os = "ios"))] {
@@ -73,9 +80,12 @@ mod login_failure_check;
 pub mod display_service;
 #[cfg(windows)]
 pub mod portable_service;
+pub mod permission_profile;
+pub mod recording;
 mod service;
 mod video_qos;
 pub mod video_service;
+pub mod webhook;
 
 #[cfg(all(target_os = "windows", feature = "flutter"))]
 pub mod printer_service;
@@ -322,11 +332,12 @@ async fn create_relay_connection_(
     ipv4: bool,
     meta: ConnectionMeta,
 ) -> ResultType<()> {
-    let mut stream = socket_client::connect_tcp(
-        socket_client::ipv4_to_ipv6(crate::check_port(relay_server, RELAY_PORT), ipv4),
-        CONNECT_TIMEOUT,
-    )
-    .await?;
+    let relay_server = if crate::ws_fallback::is_ws_endpoint(&relay_server) {
+        relay_server
+    } else {
+        socket_client::ipv4_to_ipv6(crate::check_port(relay_server, RELAY_PORT), ipv4)
+    };
+    let mut stream = socket_client::connect_tcp(relay_server, CONNECT_TIMEOUT).await?;
     let mut msg_out = RendezvousMessage::new();
     let licence_key = crate::get_key(true).await;
     msg_out.set_request_relay(RequestRelay {
@@ -601,6 +612,8 @@ pub async fn start_server(is_server: bool, no_server: bool) {
                 std::process::exit(-1);
             }
         });
+        // Deliver the audit records left by the previous run.
+        audit_spool::start();
         // Warm the DRM availability cache before any client connects, so the first connection does
         // not race a cold `_drm` probe and ship an empty display list ("No displays" + retry).
         // X11 is skipped -- probing there makes the root service open DRM readers for a path this
@@ -0,0 +1,301 @@
+//! Time windows of unattended access.
+//!
+//! With `access-schedule` set, the logins that don't wait for the local user to accept (passwords
+//! and reconnections of recent sessions) are only accepted within its windows, e.g.
+//! `mon-fri 08:00-18:00; sat 09:00-12:00`. A window ending at or before its start runs past
+//! midnight. The times are in `access-schedule-timezone`: `local` (the default, which follows
+//! daylight saving time), `UTC`, a fixed offset like `+01:00` or an IANA zone like
+//! `Europe/Berlin`.
+//!
+//! Outside the windows the login is refused, or with `allow-click-outside-access-schedule` left to
+//! the local user to accept. An invalid schedule is logged and ignored rather than locking
+//! everyone out, an invalid timezone is logged and the local time is used.
+
+use chrono_tz::Tz;
+use hbb_common::{
+    chrono::{self, Datelike, FixedOffset, Timelike},
+    config::{self, Config},
+    log,
+};
+
+pub const OPTION_ACCESS_SCHEDULE: &str = "access-schedule";
+pub const OPTION_ACCESS_SCHEDULE_TIMEZONE: &str = "access-schedule-timezone";
+pub const OPTION_ALLOW_CLICK_OUTSIDE_ACCESS_SCHEDULE: &str = "allow-click-outside-access-schedule";
+
+pub const LOGIN_MSG_OUTSIDE_ACCESS_SCHEDULE: &str = "Unattended access is not allowed at this time";
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+#[derive(Debug, PartialEq)]
+struct Window {
+    // From monday.
+    days: [bool; 7],
+    // Minutes of the day.
+    start: u32,
+    end: u32,
+}
+
+/// If unattended access is allowed now, always without a schedule or with an invalid one.
+pub fn is_open_now() -> bool {
+    let schedule = Config::get_option(OPTION_ACCESS_SCHEDULE);
+    if schedule.trim().is_empty() {
+        return true;
+    }
+    let windows = match parse(&schedule) {
+        Ok(windows) => windows,
+        Err(e) => {
+            log::error!("Invalid access schedule {:?}, ignored: {}", schedule, e);
+            return true;
+        }
+    };
+    let timezone = Config::get_option(OPTION_ACCESS_SCHEDULE_TIMEZONE);
+    let (weekday, minute) = now_in(&timezone).unwrap_or_else(|| {
+        log::error!(
+            "Invalid access schedule timezone {:?}, the local time is used",
+            timezone
+        );
+        day_minute(&chrono::Local::now())
+    });
+    is_open(&windows, weekday, minute)
+}
+
+pub fn allow_click_outside() -> bool {
+    config::option2bool(
+        OPTION_ALLOW_CLICK_OUTSIDE_ACCESS_SCHEDULE,
+        &Config::get_option(OPTION_ALLOW_CLICK_OUTSIDE_ACCESS_SCHEDULE),
+    )
+}
+
+fn is_open(windows: &[Window], weekday: usize, minute: u32) -> bool {
+    windows.iter().any(|w| {
+        if w.start < w.end {
+            w.days[weekday] && w.start <= minute && minute < w.end
+        } else {
+            // The part after midnight belongs to the day before.
+            (w.days[weekday] && minute >= w.start) || (w.days[(weekday + 6) % 7] && minute < w.end)
+        }
+    })
+}
+
+/// The weekday from monday and the minute of the day now.
+fn now_in(timezone: &str) -> Option<(usize, u32)> {
+    let timezone = timezone.trim();
+    if timezone.is_empty() || timezone.eq_ignore_ascii_case("local") {
+        return Some(day_minute(&chrono::Local::now()));
+    }
+    if let Some(offset) = parse_offset(timezone) {
+        return Some(day_minute(&chrono::Utc::now().with_timezone(&offset)));
+    }
+    let tz = parse_zone(timezone)?;
+    Some(day_minute(&chrono::Utc::now().with_timezone(&tz)))
+}
+
+// Europe/Berlin, the names are case sensitive.
+fn parse_zone(s: &str) -> Option<Tz> {
+    s.parse().ok()
+}
+
+fn day_minute<T: Datelike + Timelike>(t: &T) -> (usize, u32) {
+    (
+        t.weekday().num_days_from_monday() as usize,
+        t.hour() * 60 + t.minute(),
+    )
+}
+
+// UTC, +01:00, UTC-05:30, +0800 or +8.
+fn parse_offset(s: &str) -> Option<FixedOffset> {
+    let upper = s.to_ascii_uppercase();
+    let s = upper
+        .strip_prefix("UTC")
+        .or_else(|| upper.strip_prefix("GMT"))
+        .unwrap_or(&upper);
+    if s.is_empty() {
+        return FixedOffset::east_opt(0);
+    }
+    let sign = match s.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let s = &s[1..];
+    let (hours, minutes) = match s.split_once(':') {
+        Some((h, m)) => (h, m),
+        None if s.len() == 4 => s.split_at(2),
+        None => (s, "0"),
+    };
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 14 || minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+fn parse(schedule: &str) -> Result<Vec<Window>, String> {
+    schedule
+        .split([';', '\n'])
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(parse_window)
+        .collect()
+}
+
+// mon-fri 08:00-18:00
+fn parse_window(s: &str) -> Result<Window, String> {
+    let Some((days, times)) = s.split_once(char::is_whitespace) else {
+        return Err(format!("no times in {:?}", s));
+    };
+    let Some((start, end)) = times.trim().split_once('-') else {
+        return Err(format!("invalid times {:?}", times));
+    };
+    Ok(Window {
+        days: parse_days(days)?,
+        start: parse_time(start)?,
+        end: parse_time(end)?,
+    })
+}
+
+// mon,wed,fri or sat-sun, ranges may wrap around the week.
+fn parse_days(s: &str) -> Result<[bool; 7], String> {
+    let day = |s: &str| {
+        let s = s.trim().to_ascii_lowercase();
+        DAYS.iter()
+            .position(|d| *d == s)
+            .ok_or_else(|| format!("invalid day {:?}", s))
+    };
+    let mut days = [false; 7];
+    for part in s.split(',') {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (day(first)?, day(last)?),
+            None => (day(part)?, day(part)?),
+        };
+        let mut d = first;
+        loop {
+            days[d] = true;
+            if d == last {
+                break;
+            }
+            d = (d + 1) % 7;
+        }
+    }
+    Ok(days)
+}
+
+// HH:MM, up to 24:00.
+fn parse_time(s: &str) -> Result<u32, String> {
+    let s = s.trim();
+    let minutes = s.split_once(':').and_then(|(h, m)| {
+        let (h, m) = (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?);
+        (m < 60).then_some(h * 60 + m)
+    });
+    match minutes {
+        Some(minutes) if minutes <= MINUTES_PER_DAY => Ok(minutes),
+        _ => Err(format!("invalid time {:?}", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedule_is_parsed() {
+        assert_eq!(
+            parse("mon-fri 08:00-18:00; sat,sun 22:30-06:00\n").unwrap(),
+            vec![
+                Window {
+                    days: [true, true, true, true, true, false, false],
+                    start: 8 * 60,
+                    end: 18 * 60,
+                },
+                Window {
+                    days: [false, false, false, false, false, true, true],
+                    start: 22 * 60 + 30,
+                    end: 6 * 60,
+                },
+            ]
+        );
+        assert_eq!(
+            parse("fri-mon 00:00-24:00").unwrap()[0].days,
+            [true, false, false, false, true, true, true]
+        );
+        assert!(parse("mon-fri").is_err());
+        assert!(parse("weekdays 08:00-18:00").is_err());
+        assert!(parse("mon 08:00-25:00").is_err());
+    }
+
+    #[test]
+    fn windows_are_checked() {
+        let windows = parse("mon-fri 08:00-18:00; sat 22:00-02:00").unwrap();
+        let at = |h: u32, m: u32| h * 60 + m;
+        assert!(is_open(&windows, 0, at(8, 0)));
+        assert!(!is_open(&windows, 0, at(18, 0)));
+        assert!(!is_open(&windows, 0, at(7, 59)));
+        assert!(!is_open(&windows, 5, at(12, 0)));
+        assert!(is_open(&windows, 5, at(23, 0)));
+        // After midnight, on sunday.
+        assert!(is_open(&windows, 6, at(1, 30)));
+        assert!(!is_open(&windows, 6, at(2, 0)));
+        // Not after friday.
+        assert!(!is_open(&windows, 5, at(1, 0)));
+    }
+
+    #[test]
+    fn offsets_are_parsed() {
+        let secs = |s: &str| parse_offset(s).map(|o| o.local_minus_utc());
+        assert_eq!(secs("UTC"), Some(0));
+        assert_eq!(secs("+01:00"), Some(3600));
+        assert_eq!(secs("utc-05:30"), Some(-(5 * 3600 + 30 * 60)));
+        assert_eq!(secs("GMT+0800"), Some(8 * 3600));
+        assert_eq!(secs("+8"), Some(8 * 3600));
+        assert_eq!(secs("+15:00"), None);
+    }
+
+    #[test]
+    fn zones_are_parsed() {
+        use chrono::TimeZone;
+        let at = |tz: Tz, secs: i64| day_minute(&tz.timestamp_opt(secs, 0).unwrap());
+        let berlin = parse_zone("Europe/Berlin").unwrap();
+        // 2024-01-15 12:00 UTC, a monday, and 2024-07-15 12:00 UTC in summer time.
+        assert_eq!(at(berlin, 1705320000), (0, 13 * 60));
+        assert_eq!(at(berlin, 1721044800), (0, 14 * 60));
+        assert_eq!(
+            at(parse_zone("America/New_York").unwrap(), 1705320000),
+            (0, 7 * 60)
+        );
+        assert!(parse_zone("Europe/Nowhere").is_none());
+        assert!(now_in("Asia/Kolkata").is_some());
+        assert!(now_in("+05:30").is_some());
+        assert!(now_in("Mars/Olympus").is_none());
+    }
+
+    #[test]
+    fn invalid_configs_fall_back() {
+        struct Restore(String, String);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                Config::set_option(OPTION_ACCESS_SCHEDULE.to_owned(), self.0.clone());
+                Config::set_option(OPTION_ACCESS_SCHEDULE_TIMEZONE.to_owned(), self.1.clone());
+            }
+        }
+        let _restore = Restore(
+            Config::get_option(OPTION_ACCESS_SCHEDULE),
+            Config::get_option(OPTION_ACCESS_SCHEDULE_TIMEZONE),
+        );
+        Config::set_option(
+            OPTION_ACCESS_SCHEDULE.to_owned(),
+            "weekdays 08:00".to_owned(),
+        );
+        assert!(is_open_now());
+        Config::set_option(
+            OPTION_ACCESS_SCHEDULE.to_owned(),
+            "mon-sun 00:00-24:00".to_owned(),
+        );
+        Config::set_option(
+            OPTION_ACCESS_SCHEDULE_TIMEZONE.to_owned(),
+            "Mars/Olympus".to_owned(),
+        );
+        assert!(is_open_now());
+    }
+}
@@ -0,0 +1,389 @@
+//! Local sinks of the audit records, independent of the api server.
+//!
+//! The records posted to the api server are also written to the sinks selected by the options: a
+//! rotating JSON-lines file, RFC 5424 syslog over UDP, TCP or a unix socket, and journald with
+//! structured fields. They are written in order by a background thread, so a slow sink doesn't
+//! hold up the connection.
+
+use hbb_common::{
+    bail, chrono,
+    config::{self, Config},
+    lazy_static, log, ResultType,
+};
+use serde_json::{json, Value};
+use std::{
+    fs,
+    io::Write,
+    net::{TcpStream, ToSocketAddrs, UdpSocket},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// Path of the JSON-lines file, off if empty.
+pub const OPTION_AUDIT_FILE: &str = "audit-file";
+/// The file is rotated past this, 10 if empty or 0.
+pub const OPTION_AUDIT_FILE_MAX_SIZE_MB: &str = "audit-file-max-size-mb";
+/// `udp://host:port`, `tcp://host:port` or `unix:/dev/log`, off if empty.
+pub const OPTION_AUDIT_SYSLOG: &str = "audit-syslog";
+/// Linux only.
+pub const OPTION_AUDIT_JOURNALD: &str = "audit-journald";
+
+const DEFAULT_MAX_SIZE_MB: u64 = 10;
+// The rotated files are `<path>.1` (the newest) to `<path>.5`.
+const ROTATED_FILES: usize = 5;
+const SYSLOG_TIMEOUT: Duration = Duration::from_secs(3);
+// log audit
+const SYSLOG_FACILITY: u8 = 13;
+const SEVERITY_WARNING: u8 = 4;
+const SEVERITY_NOTICE: u8 = 5;
+const APP_NAME: &str = "rustdesk";
+
+lazy_static::lazy_static! {
+    static ref SENDER: Mutex<Option<Sender<(&'static str, Value)>>> = Default::default();
+}
+
+/// Write a record to the enabled sinks, `kind` is the audit it was made for: conn, file or alarm.
+pub fn emit(kind: &'static str, record: &Value) {
+    if !enabled() {
+        return;
+    }
+    let mut sender = SENDER.lock().unwrap();
+    let sender = sender.get_or_insert_with(|| {
+        let (tx, rx) = channel();
+        std::thread::spawn(move || run(rx));
+        tx
+    });
+    sender.send((kind, record.clone())).ok();
+}
+
+fn enabled() -> bool {
+    !Config::get_option(OPTION_AUDIT_FILE).is_empty()
+        || !Config::get_option(OPTION_AUDIT_SYSLOG).is_empty()
+        || journald_enabled()
+}
+
+fn journald_enabled() -> bool {
+    cfg!(target_os = "linux")
+        && config::option2bool(
+            OPTION_AUDIT_JOURNALD,
+            &Config::get_option(OPTION_AUDIT_JOURNALD),
+        )
+}
+
+// The options are read for every record, so the sinks follow their changes.
+fn run(rx: Receiver<(&'static str, Value)>) {
+    let mut syslog = Syslog::default();
+    while let Ok((kind, record)) = rx.recv() {
+        let severity = if kind == "alarm" {
+            SEVERITY_WARNING
+        } else {
+            SEVERITY_NOTICE
+        };
+        let now = chrono::Utc::now();
+        let path = Config::get_option(OPTION_AUDIT_FILE);
+        if !path.is_empty() {
+            let line = json!({
+                "time": now.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                "audit": kind,
+                "record": record,
+            });
+            if let Err(e) = append_line(Path::new(&path), max_size(), &line.to_string()) {
+                log::error!("Failed to write the audit record to {}: {}", path, e);
+            }
+        }
+        let addr = Config::get_option(OPTION_AUDIT_SYSLOG);
+        if !addr.is_empty() {
+            let msg = syslog_message(severity, now, &crate::common::hostname(), kind, &record);
+            if let Err(e) = syslog.send(&addr, &msg) {
+                log::error!("Failed to send the audit record to {}: {}", addr, e);
+            }
+        }
+        #[cfg(target_os = "linux")]
+        if journald_enabled() {
+            if let Err(e) = journald::send(&journald::encode(severity, kind, &record)) {
+                log::error!("Failed to send the audit record to journald: {}", e);
+            }
+        }
+    }
+}
+
+fn max_size() -> u64 {
+    let mb = Config::get_option(OPTION_AUDIT_FILE_MAX_SIZE_MB)
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_MAX_SIZE_MB);
+    mb.saturating_mul(1 << 20)
+}
+
+/// Append a line to the file, rotated first if the line would take it past `max_size`.
+fn append_line(path: &Path, max_size: u64, line: &str) -> ResultType<()> {
+    let len = fs::metadata(path).map(|m| m.len()).unwrap_or_default();
+    if len > 0 && len + line.len() as u64 + 1 > max_size {
+        rotate(path)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    file.write_all(format!("{}\n", line).as_bytes())?;
+    Ok(())
+}
+
+fn rotate(path: &Path) -> ResultType<()> {
+    for i in (1..ROTATED_FILES).rev() {
+        let from = rotated(path, i);
+        if from.exists() {
+            fs::rename(from, rotated(path, i + 1))?;
+        }
+    }
+    fs::rename(path, rotated(path, 1))?;
+    Ok(())
+}
+
+fn rotated(path: &Path, i: usize) -> PathBuf {
+    PathBuf::from(format!("{}.{}", path.display(), i))
+}
+
+/// An RFC 5424 message with the record as its content, the audit kind is the MSGID.
+fn syslog_message(
+    severity: u8,
+    time: chrono::DateTime<chrono::Utc>,
+    hostname: &str,
+    kind: &str,
+    record: &Value,
+) -> String {
+    // The header fields are printable ASCII without spaces, "-" if empty.
+    let hostname: String = hostname
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(255)
+        .collect();
+    format!(
+        "<{}>1 {} {} {} {} {} - {}",
+        SYSLOG_FACILITY * 8 + severity,
+        time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        if hostname.is_empty() { "-" } else { &hostname },
+        APP_NAME,
+        std::process::id(),
+        kind,
+        record
+    )
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixDatagram),
+}
+
+/// The connection to the syslog server, kept between the records.
+#[derive(Default)]
+struct Syslog {
+    addr: String,
+    conn: Option<Connection>,
+}
+
+impl Syslog {
+    // Connected again once if the kept connection fails, e.g. the server restarted.
+    fn send(&mut self, addr: &str, msg: &str) -> ResultType<()> {
+        if self.addr != addr {
+            self.addr = addr.to_owned();
+            self.conn = None;
+        }
+        if let Some(conn) = self.conn.as_mut() {
+            if Self::write(conn, msg).is_ok() {
+                return Ok(());
+            }
+        }
+        self.conn = None;
+        let mut conn = Self::connect(addr)?;
+        Self::write(&mut conn, msg)?;
+        self.conn = Some(conn);
+        Ok(())
+    }
+
+    fn connect(addr: &str) -> ResultType<Connection> {
+        if let Some(host) = addr.strip_prefix("udp://") {
+            let Some(target) = host.to_socket_addrs()?.next() else {
+                bail!("Failed to resolve {}", host);
+            };
+            let socket = UdpSocket::bind(if target.is_ipv6() {
+                "[::]:0"
+            } else {
+                "0.0.0.0:0"
+            })?;
+            socket.connect(target)?;
+            return Ok(Connection::Udp(socket));
+        }
+        if let Some(host) = addr.strip_prefix("tcp://") {
+            let Some(target) = host.to_socket_addrs()?.next() else {
+                bail!("Failed to resolve {}", host);
+            };
+            let stream = TcpStream::connect_timeout(&target, SYSLOG_TIMEOUT)?;
+            stream.set_write_timeout(Some(SYSLOG_TIMEOUT))?;
+            return Ok(Connection::Tcp(stream));
+        }
+        #[cfg(unix)]
+        if let Some(path) = addr
+            .strip_prefix("unix://")
+            .or_else(|| addr.strip_prefix("unix:"))
+        {
+            let socket = std::os::unix::net::UnixDatagram::unbound()?;
+            socket.connect(path)?;
+            return Ok(Connection::Unix(socket));
+        }
+        bail!("Unsupported syslog address {}", addr);
+    }
+
+    fn write(conn: &mut Connection, msg: &str) -> ResultType<()> {
+        match conn {
+            Connection::Udp(socket) => {
+                socket.send(msg.as_bytes())?;
+            }
+            // Octet counting framing of RFC 6587, the records may contain newlines.
+            Connection::Tcp(stream) => {
+                stream.write_all(format!("{} {}", msg.len(), msg).as_bytes())?;
+            }
+            #[cfg(unix)]
+            Connection::Unix(socket) => {
+                socket.send(msg.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod journald {
+    use hbb_common::ResultType;
+    use serde_json::Value;
+    use std::os::unix::net::UnixDatagram;
+
+    const SOCKET: &str = "/run/systemd/journal/socket";
+
+    /// The native protocol datagram: MESSAGE is the record, its top level fields are also
+    /// `RUSTDESK_<NAME>` fields to match on.
+    pub(super) fn encode(severity: u8, kind: &str, record: &Value) -> Vec<u8> {
+        let mut data = vec![];
+        field(&mut data, "MESSAGE", &record.to_string());
+        field(&mut data, "PRIORITY", &severity.to_string());
+        field(&mut data, "SYSLOG_IDENTIFIER", super::APP_NAME);
+        field(&mut data, "RUSTDESK_AUDIT", kind);
+        if let Some(map) = record.as_object() {
+            for (key, value) in map {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    v => v.to_string(),
+                };
+                field(&mut data, &field_name(key), &value);
+            }
+        }
+        data
+    }
+
+    pub(super) fn send(data: &[u8]) -> ResultType<()> {
+        UnixDatagram::unbound()?.send_to(data, SOCKET)?;
+        Ok(())
+    }
+
+    // Values with newlines are length prefixed.
+    fn field(data: &mut Vec<u8>, name: &str, value: &str) {
+        data.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            data.push(b'\n');
+            data.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            data.push(b'=');
+        }
+        data.extend_from_slice(value.as_bytes());
+        data.push(b'\n');
+    }
+
+    // Field names are uppercase letters, digits and underscores.
+    fn field_name(key: &str) -> String {
+        let name: String = key
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        format!("RUSTDESK_{}", name)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use serde_json::json;
+
+        #[test]
+        fn fields_are_encoded() {
+            let data = encode(
+                5,
+                "file",
+                &json!({"peer-id": "123", "info": "a\nb", "num": 2}),
+            );
+            let text = String::from_utf8_lossy(&data);
+            assert!(text.contains("PRIORITY=5\n"));
+            assert!(text.contains("RUSTDESK_AUDIT=file\n"));
+            assert!(text.contains("RUSTDESK_PEER_ID=123\n"));
+            assert!(text.contains("RUSTDESK_NUM=2\n"));
+            let mut multiline = b"RUSTDESK_INFO\n".to_vec();
+            multiline.extend_from_slice(&3u64.to_le_bytes());
+            multiline.extend_from_slice(b"a\nb\n");
+            assert!(data.windows(multiline.len()).any(|w| w == multiline));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syslog_message_is_rfc5424() {
+        let time = chrono::DateTime::parse_from_rfc3339("2024-05-01T08:30:00.250Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let msg = syslog_message(
+            SEVERITY_WARNING,
+            time,
+            "my host",
+            "alarm",
+            &json!({"typ": 1}),
+        );
+        assert_eq!(
+            msg,
+            format!(
+                "<108>1 2024-05-01T08:30:00.250Z myhost rustdesk {} alarm - {{\"typ\":1}}",
+                std::process::id()
+            )
+        );
+    }
+
+    #[test]
+    fn file_is_rotated_past_the_limit() {
+        let dir = std::env::temp_dir().join(format!("audit_sink_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.jsonl");
+        for i in 0..(ROTATED_FILES + 2) {
+            append_line(&path, 8, &format!("line{}", i)).unwrap();
+        }
+        let read = |p: PathBuf| fs::read_to_string(p).unwrap();
+        assert_eq!(read(path.clone()), format!("line{}\n", ROTATED_FILES + 1));
+        assert_eq!(read(rotated(&path, 1)), format!("line{}\n", ROTATED_FILES));
+        assert_eq!(read(rotated(&path, ROTATED_FILES)), "line1\n");
+        assert!(!rotated(&path, ROTATED_FILES + 1).exists());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,396 @@
+//! Durable spool of the audit records posted to the api server.
+//!
+//! Every record is written to its own file in the spool directory before it is posted, and removed
+//! once the server has stored it, so the records survive restarts and outages of any length and
+//! are delivered in the order they were made. The spool is bounded by size, the oldest records are
+//! dropped past it. The files are written by the delivery thread, not by the callers of [`push`].
+//!
+//! The delivery is at least once: a record retried past the nonce window may be stored twice, its
+//! `record_id` stays the same so the server can drop the repetition.
+
+use hbb_common::{
+    bail, chrono,
+    config::Config,
+    lazy_static, log,
+    tokio::{
+        self,
+        sync::{mpsc, Notify},
+        time,
+    },
+    ResultType,
+};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, Once},
+    time::Duration,
+};
+
+const MAX_SIZE: u64 = 32 << 20;
+const EXTENSION: &str = "json";
+// The api server remembers a record's nonce for five minutes and drops repeated posts of it, so a
+// nonce is only posted again within this of its first post: one attempt is already up to 84s
+// (post_request_ retries the TLS handshake up to four times at 12s each, then the TCP-proxy
+// fallback adds 36s), and a later one could land after the server forgot the nonce. Past it the
+// record gets a fresh nonce, which gives the following retries a full window of their own.
+const NONCE_REUSE_SECS: i64 = 120;
+// One delay per failed pass, the last one repeats until the server is reachable again.
+const RETRY_BACKOFF_SECS: [u64; 5] = [10, 30, 60, 120, 300];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub url: String,
+    pub record: Value,
+    /// Unix time the record was spooled.
+    pub queued: i64,
+    #[serde(default)]
+    pub attempts: u32,
+    /// Unix time of the first post with the current nonce.
+    #[serde(default)]
+    pub nonce_posted: Option<i64>,
+    #[serde(default)]
+    pub last_error: String,
+}
+
+lazy_static::lazy_static! {
+    // Serializes the changes of the spool in this process, holds the next sequence number.
+    static ref NEXT_SEQ: Mutex<u64> = Default::default();
+    static ref PUSHED: Notify = Notify::new();
+    // The records pushed and not written yet, the receiver is taken by the delivery thread.
+    static ref SPOOL: (
+        mpsc::UnboundedSender<Entry>,
+        Mutex<Option<mpsc::UnboundedReceiver<Entry>>>
+    ) = {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (tx, Mutex::new(Some(rx)))
+    };
+}
+
+pub fn dir() -> PathBuf {
+    Config::path("audit_spool")
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// Spool a record for `url`, it is posted after the records spooled before it.
+///
+/// Doesn't block, the record is written by the delivery thread.
+pub fn push(url: String, mut record: Value) {
+    if let Some(record) = record.as_object_mut() {
+        record
+            .entry("record_id")
+            .or_insert_with(|| json!(uuid::Uuid::new_v4().to_string()));
+    }
+    let entry = Entry {
+        url,
+        record,
+        queued: now(),
+        attempts: 0,
+        nonce_posted: None,
+        last_error: "".to_owned(),
+    };
+    start();
+    if SPOOL.0.send(entry).is_err() {
+        log::error!("Failed to spool the audit record, the delivery thread is gone");
+    }
+}
+
+fn append(dir: &Path, entry: &Entry) -> ResultType<()> {
+    let mut next_seq = NEXT_SEQ.lock().unwrap();
+    fs::create_dir_all(dir)?;
+    let files = list(dir)?;
+    let seq = files
+        .last()
+        .map(|(seq, _, _)| seq + 1)
+        .unwrap_or_default()
+        .max(*next_seq);
+    let path = dir.join(format!("{:020}.{}", seq, EXTENSION));
+    write(&path, entry)?;
+    *next_seq = seq + 1;
+    let len = fs::metadata(&path)?.len();
+    let mut sizes: Vec<(PathBuf, u64)> = files.into_iter().map(|(_, p, len)| (p, len)).collect();
+    sizes.push((path, len));
+    let dropped = over_limit(&sizes, MAX_SIZE);
+    for path in &dropped {
+        fs::remove_file(path).ok();
+    }
+    if !dropped.is_empty() {
+        log::error!(
+            "Audit spool is over {} bytes, dropped the {} oldest records",
+            MAX_SIZE,
+            dropped.len()
+        );
+    }
+    Ok(())
+}
+
+/// The oldest files to remove to keep the total below `max`, the newest one is always kept.
+fn over_limit(files: &[(PathBuf, u64)], max: u64) -> Vec<PathBuf> {
+    let mut total: u64 = files.iter().map(|(_, len)| len).sum();
+    let mut dropped = vec![];
+    for (path, len) in files.iter().take(files.len().saturating_sub(1)) {
+        if total <= max {
+            break;
+        }
+        total -= len;
+        dropped.push(path.clone());
+    }
+    dropped
+}
+
+// Written to a temporary file first, a crash leaves either the old or the new content.
+fn write(path: &Path, entry: &Entry) -> ResultType<()> {
+    let tmp = path.with_extension("tmp");
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(&serde_json::to_vec(entry)?)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// The spooled files by sequence number, with their sizes.
+fn list(dir: &Path) -> ResultType<Vec<(u64, PathBuf, u64)>> {
+    let mut files = vec![];
+    if !dir.exists() {
+        return Ok(files);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(EXTENSION) {
+            continue;
+        }
+        let Some(seq) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse::<u64>().ok())
+        else {
+            continue;
+        };
+        let len = fs::metadata(&path).map(|m| m.len()).unwrap_or_default();
+        files.push((seq, path, len));
+    }
+    files.sort_by_key(|(seq, _, _)| *seq);
+    Ok(files)
+}
+
+/// The spooled records by sequence number.
+pub fn entries(dir: &Path) -> ResultType<Vec<(u64, Entry)>> {
+    let mut entries = vec![];
+    for (seq, path, _) in list(dir)? {
+        match read(&path) {
+            Ok(entry) => entries.push((seq, entry)),
+            Err(e) => log::error!("Invalid audit record {}: {}", path.display(), e),
+        }
+    }
+    Ok(entries)
+}
+
+fn read(path: &Path) -> ResultType<Entry> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+/// Start delivering the spooled records in the background, e.g. the ones left by the previous run.
+pub fn start() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        std::thread::spawn(deliver_loop);
+    });
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn deliver_loop() {
+    let dir = dir();
+    if let Some(mut rx) = SPOOL.1.lock().unwrap().take() {
+        let dir = dir.clone();
+        // Runs while the delivery waits for the server, the records are on disk right away.
+        tokio::spawn(async move {
+            while let Some(entry) = rx.recv().await {
+                if let Err(e) = append(&dir, &entry) {
+                    log::error!("Failed to spool the audit record: {}", e);
+                }
+                PUSHED.notify_one();
+            }
+        });
+    }
+    let mut failures = 0;
+    loop {
+        match deliver(&dir).await {
+            Ok(_) => {
+                failures = 0;
+                PUSHED.notified().await;
+            }
+            Err(e) => {
+                let secs = RETRY_BACKOFF_SECS[failures.min(RETRY_BACKOFF_SECS.len() - 1)];
+                failures += 1;
+                log::warn!("Audit spool delivery failed, retry in {}s: {}", secs, e);
+                time::sleep(Duration::from_secs(secs)).await;
+            }
+        }
+    }
+}
+
+/// Deliver the spooled records now, e.g. from the command line.
+#[tokio::main(flavor = "current_thread")]
+pub async fn flush() -> ResultType<usize> {
+    deliver(&dir()).await
+}
+
+/// Post the spooled records in order until the spool is empty, the number of records stored.
+/// Stops at the first record the server may still store later.
+async fn deliver(dir: &Path) -> ResultType<usize> {
+    let mut delivered = 0;
+    loop {
+        let Some((_, path, _)) = list(dir)?.into_iter().next() else {
+            return Ok(delivered);
+        };
+        let mut entry = match read(&path) {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::error!("Invalid audit record {} removed: {}", path.display(), e);
+                remove(&path);
+                continue;
+            }
+        };
+        if renew_nonce(&mut entry, now()) {
+            log::warn!(
+                "Audit record {} gets a new nonce, its earlier posts may have been stored",
+                path.display()
+            );
+        }
+        entry.attempts += 1;
+        // Saved before posting, so the nonce that may reach the server is known after a crash.
+        update(&path, &entry)?;
+        match post(&entry.url, entry.record.to_string()).await {
+            Ok(()) => {
+                remove(&path);
+                delivered += 1;
+            }
+            Err((false, err)) => {
+                log::error!("Audit record {} dropped: {}", path.display(), err);
+                remove(&path);
+            }
+            Err((true, err)) => {
+                entry.last_error = err.clone();
+                update(&path, &entry)?;
+                bail!("{}", err);
+            }
+        }
+    }
+}
+
+// A record dropped past the size limit in the meantime is not written back.
+fn update(path: &Path, entry: &Entry) -> ResultType<()> {
+    let _lock = NEXT_SEQ.lock().unwrap();
+    if path.exists() {
+        write(path, entry)?;
+    }
+    Ok(())
+}
+
+fn remove(path: &Path) {
+    let _lock = NEXT_SEQ.lock().unwrap();
+    fs::remove_file(path).ok();
+}
+
+/// Prepare the nonce of the next post, true if it had to be renewed.
+fn renew_nonce(entry: &mut Entry, now: i64) -> bool {
+    match entry.nonce_posted {
+        Some(posted) if now - posted >= NONCE_REUSE_SECS => {
+            entry.record["nonce"] = json!(uuid::Uuid::new_v4().to_string());
+            entry.nonce_posted = Some(now);
+            true
+        }
+        Some(_) => false,
+        None => {
+            entry.nonce_posted = Some(now);
+            false
+        }
+    }
+}
+
+/// Post a record once, the error tells if the server may store it when trying again.
+async fn post(url: &str, body: String) -> Result<(), (bool, String)> {
+    // Audit records are compliance evidence; retry transport errors and
+    // 5xx (e.g. a reverse proxy answering while the api server restarts)
+    // so transient failures don't silently drop them. A 4xx is a
+    // deterministic rejection and fails immediately.
+    match crate::post_request_with_status(url.to_owned(), body, "").await {
+        Ok((status, text)) => {
+            if (200..300).contains(&status) {
+                // Success is an empty body. hbbs reports handler
+                // failures (e.g. a db write error) as 200 with an
+                // {"error": ...} body - retryable: the server
+                // releases the record's nonce when its write fails,
+                // so trying again is what stores the record. Any
+                // other nonempty body did not come from the audit
+                // handler (a proxy interposing a 2xx maintenance
+                // page, a malformed error) and must not be mistaken
+                // for storage, so it is retried rather than dropped.
+                if text.trim().is_empty() {
+                    return Ok(());
+                }
+                let server_err = serde_json::from_str::<Value>(&text)
+                    .ok()
+                    .and_then(|v| v.get("error")?.as_str().map(|s| s.to_owned()))
+                    .filter(|e| !e.is_empty());
+                let (label, detail) = match &server_err {
+                    Some(e) => ("server error", e.as_str()),
+                    None => ("unexpected response body", text.as_str()),
+                };
+                let brief: String = detail.chars().take(128).collect();
+                Err((true, format!("{}: {}", label, brief)))
+            } else {
+                let brief: String = text.chars().take(128).collect();
+                // 408 and 429 are the transient 4xx: the request timed
+                // out upstream, or a proxy is shedding load. Every other
+                // 4xx is a deterministic rejection and retrying it would
+                // only delay the log line.
+                let transient = status >= 500 || status == 408 || status == 429;
+                Err((transient, format!("status {}: {}", status, brief)))
+            }
+        }
+        Err(e) => Err((true, e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nonce_is_renewed_past_the_reuse_window() {
+        let mut entry = Entry {
+            url: "".to_owned(),
+            record: json!({"nonce": "first"}),
+            queued: 0,
+            attempts: 0,
+            nonce_posted: None,
+            last_error: "".to_owned(),
+        };
+        assert!(!renew_nonce(&mut entry, 1000));
+        assert_eq!(entry.nonce_posted, Some(1000));
+        assert!(!renew_nonce(&mut entry, 1000 + NONCE_REUSE_SECS - 1));
+        assert_eq!(entry.record["nonce"], "first");
+        assert!(renew_nonce(&mut entry, 1000 + NONCE_REUSE_SECS));
+        assert_ne!(entry.record["nonce"], "first");
+        assert_eq!(entry.nonce_posted, Some(1000 + NONCE_REUSE_SECS));
+    }
+
+    #[test]
+    fn oldest_records_are_dropped_over_the_limit() {
+        let files: Vec<(PathBuf, u64)> =
+            (0..4).map(|i| (PathBuf::from(i.to_string()), 10)).collect();
+        assert!(over_limit(&files, 40).is_empty());
+        assert_eq!(
+            over_limit(&files, 25),
+            vec![PathBuf::from("0"), PathBuf::from("1")]
+        );
+        // The new record is kept even alone over the limit.
+        assert_eq!(over_limit(&files, 5).len(), 3);
+    }
+}
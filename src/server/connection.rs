@@ -20,7 +20,9 @@ use crate::{
     client::{
         new_voice_call_request, new_voice_call_response, start_audio_thread, MediaData, MediaSender,
     },
-    display_service, ipc, privacy_mode, video_service, VERSION,
+    display_service, file_delta, ipc, privacy_mode,
+    transfer_limit::{TransferLimits, TransferScheduler},
+    video_service, VERSION,
 };
 #[cfg(any(target_os = "android", target_os = "ios"))]
 use crate::{common::DEVICE_NAME, flutter::connection_manager::start_channel};
@@ -56,7 +58,7 @@ use serde_json::{json, value::Value};
 use std::sync::atomic::Ordering;
 use std::{
     collections::HashSet,
-    net::Ipv6Addr,
+    net::{IpAddr, Ipv6Addr},
     num::NonZeroI64,
     path::PathBuf,
     str::FromStr,
@@ -87,6 +89,10 @@ lazy_static::lazy_static! {
     pub static ref CONTROL_PERMISSIONS_ARRAY: Arc::<Mutex<Vec<(i32, ControlPermissions)>>> = Default::default();
     static ref WAKELOCK_SENDER: Arc::<Mutex<std::sync::mpsc::Sender<(usize, usize)>>> = Arc::new(Mutex::new(start_wakelock_thread()));
     static ref WAKELOCK_KEEP_AWAKE_OPTION: Arc::<Mutex<Option<bool>>> = Default::default();
+    // The reverse port forwards accepted in the connection manager, with when their last
+    // connection closed, `None` while it is open. Reconnections within `SESSION_TIMEOUT` are
+    // not asked again.
+    static ref REVERSE_PORT_FORWARD_APPROVALS: Arc::<Mutex<HashMap<(SessionKey, String), Option<Instant>>>> = Default::default();
 }
 
 #[cfg(feature = "flutter")]
@@ -167,6 +173,8 @@ struct Session {
     last_recv_time: Arc<Mutex<Instant>>,
     random_password: String,
     tfa: bool,
+    // How the session first logged in, kept by the reconnections for the permission profile.
+    primary_auth: ConnAuditPrimaryAuth,
 }
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -210,6 +218,16 @@ impl ConnAuditPrimaryAuth {
     fn as_i64(self) -> i64 {
         self as i64
     }
+
+    fn profile_auth(self) -> Option<permission_profile::Auth> {
+        match self {
+            ConnAuditPrimaryAuth::None => None,
+            ConnAuditPrimaryAuth::Click => Some(permission_profile::Auth::Click),
+            ConnAuditPrimaryAuth::TemporaryPassword => Some(permission_profile::Auth::Temporary),
+            ConnAuditPrimaryAuth::PermanentPassword => Some(permission_profile::Auth::Permanent),
+            ConnAuditPrimaryAuth::SwitchSides => Some(permission_profile::Auth::Switch),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -244,6 +262,15 @@ impl TerminalUserToken {
         }
     }
 }
+
+enum PortForwardSocket {
+    Tcp(Framed<TcpStream, BytesCodec>),
+    Udp(crate::udp_forward::UdpForwarder),
+    Reverse(tokio::net::TcpListener),
+    // The targets declared at login.
+    Mux(Vec<String>),
+}
+
 pub struct Connection {
     inner: ConnInner,
     display_idx: usize,
@@ -251,13 +278,16 @@ pub struct Connection {
     server: super::ServerPtrWeak,
     hash: Hash,
     read_jobs: Vec<fs::TransferJob>,
+    deltas: file_delta::Deltas,
+    transfer_scheduler: TransferScheduler,
     timer: crate::RustDeskInterval,
     file_timer: crate::RustDeskInterval,
     file_transfer: Option<(String, bool)>,
     view_camera: bool,
     terminal: bool,
-    port_forward_socket: Option<Framed<TcpStream, BytesCodec>>,
+    port_forward_socket: Option<PortForwardSocket>,
     port_forward_address: String,
+    reverse_port_forward_approved: bool,
     tx_to_cm: mpsc::UnboundedSender<ipc::Data>,
     authorized: bool,
     require_2fa: Option<totp_rs::TOTP>,
@@ -271,6 +301,7 @@ pub struct Connection {
     block_input: bool,
     privacy_mode: bool,
     control_permissions: Option<ControlPermissions>,
+    permission_profile: Option<permission_profile::Profile>,
     last_test_delay: Option<Instant>,
     network_delay: u32,
     lock_after_session_end: bool,
@@ -332,7 +363,6 @@ pub struct Connection {
     printer_data: Vec<(Instant, String, Vec<u8>)>,
     // For post requests that need to be sent sequentially.
     // eg. post_conn_audit
-    tx_post_seq: mpsc::UnboundedSender<(String, Value)>,
     conn_audit_primary_auth: ConnAuditPrimaryAuth,
     conn_audit_two_factor: ConnAuditTwoFactor,
     // Tracks read job IDs delegated to CM process.
@@ -389,6 +419,12 @@ impl Subscriber for ConnInner {
 const TEST_DELAY_TIMEOUT: Duration = Duration::from_secs(1);
 const SEC30: Duration = Duration::from_secs(30);
 const H1: Duration = Duration::from_secs(3600);
+// Comma separated CIDRs port forwards may connect to, any destination if empty.
+const OPTION_PORT_FORWARD_ALLOWED_NETWORKS: &str = "port-forward-allowed-networks";
+// Reverse port forwards open a listener on this machine, off unless set to "Y".
+const OPTION_ALLOW_REVERSE_TUNNEL: &str = "allow-reverse-tunnel";
+// Reverse port forwards may listen on other addresses than loopback if set to "Y".
+const OPTION_ALLOW_REVERSE_TUNNEL_ANY_ADDRESS: &str = "allow-reverse-tunnel-any-address";
 const MILLI1: Duration = Duration::from_millis(1);
 const SEND_TIMEOUT_VIDEO: u64 = 12_000;
 const SEND_TIMEOUT_OTHER: u64 = SEND_TIMEOUT_VIDEO * 10;
@@ -441,10 +477,6 @@ impl Connection {
         let (tx_input, _rx_input) = std_mpsc::channel();
         let (tx_from_authed, mut rx_from_authed) = mpsc::unbounded_channel::<ipc::Data>();
         let mut hbbs_rx = crate::hbbs_http::sync::signal_receiver();
-        let (tx_post_seq, rx_post_seq) = mpsc::unbounded_channel();
-        tokio::spawn(async move {
-            Self::post_seq_loop(rx_post_seq).await;
-        });
 
         #[cfg(not(any(target_os = "android", target_os = "ios")))]
         let tx_cloned = tx.clone();
@@ -463,6 +495,11 @@ impl Connection {
             server,
             hash,
             read_jobs: Vec::new(),
+            deltas: Default::default(),
+            // The same limits for all the peers, theirs only apply to their uploads.
+            transfer_scheduler: TransferScheduler::new(TransferLimits::from_options(
+                Config::get_option,
+            )),
             timer: crate::rustdesk_interval(time::interval(SEC30)),
             file_timer: crate::rustdesk_interval(time::interval(SEC30)),
             file_transfer: None,
@@ -470,18 +507,20 @@ impl Connection {
             terminal: false,
             port_forward_socket: None,
             port_forward_address: "".to_owned(),
+            reverse_port_forward_approved: false,
             tx_to_cm,
             authorized: false,
-            keyboard: Self::permission(keys::OPTION_ENABLE_KEYBOARD, &control_permissions),
-            clipboard: Self::permission(keys::OPTION_ENABLE_CLIPBOARD, &control_permissions),
-            audio: Self::permission(keys::OPTION_ENABLE_AUDIO, &control_permissions),
+            keyboard: Self::permission(keys::OPTION_ENABLE_KEYBOARD, &control_permissions, None),
+            clipboard: Self::permission(keys::OPTION_ENABLE_CLIPBOARD, &control_permissions, None),
+            audio: Self::permission(keys::OPTION_ENABLE_AUDIO, &control_permissions, None),
             // to-do: make sure is the option correct here
-            file: Self::permission(keys::OPTION_ENABLE_FILE_TRANSFER, &control_permissions),
-            restart: Self::permission(keys::OPTION_ENABLE_REMOTE_RESTART, &control_permissions),
-            recording: Self::permission(keys::OPTION_ENABLE_RECORD_SESSION, &control_permissions),
-            block_input: Self::permission(keys::OPTION_ENABLE_BLOCK_INPUT, &control_permissions),
-            privacy_mode: Self::permission(keys::OPTION_ENABLE_PRIVACY_MODE, &control_permissions),
+            file: Self::permission(keys::OPTION_ENABLE_FILE_TRANSFER, &control_permissions, None),
+            restart: Self::permission(keys::OPTION_ENABLE_REMOTE_RESTART, &control_permissions, None),
+            recording: Self::permission(keys::OPTION_ENABLE_RECORD_SESSION, &control_permissions, None),
+            block_input: Self::permission(keys::OPTION_ENABLE_BLOCK_INPUT, &control_permissions, None),
+            privacy_mode: Self::permission(keys::OPTION_ENABLE_PRIVACY_MODE, &control_permissions, None),
             control_permissions,
+            permission_profile: None,
             last_test_delay: None,
             network_delay: 0,
             lock_after_session_end: false,
@@ -533,7 +572,6 @@ impl Connection {
             retina: Retina::default(),
             tx_from_authed,
             printer_data: Vec::new(),
-            tx_post_seq,
             cm_read_job_ids: HashSet::new(),
             terminal_service_id: "".to_owned(),
             terminal_persistent: false,
@@ -626,6 +664,13 @@ impl Connection {
                 Some(data) = rx_from_cm.recv() => {
                     match data {
                         ipc::Data::Authorize => {
+                            conn.reverse_port_forward_approved = true;
+                            if conn.is_reverse_port_forward() {
+                                REVERSE_PORT_FORWARD_APPROVALS.lock().unwrap().insert(
+                                    (conn.session_key(), conn.port_forward_address.clone()),
+                                    None,
+                                );
+                            }
                             conn.set_conn_audit_primary_auth(ConnAuditPrimaryAuth::Click);
                             conn.require_2fa.take();
                             if !conn.send_logon_response_and_keep_alive().await {
@@ -650,6 +695,7 @@ impl Connection {
                             }
                         }
                         ipc::Data::ChatMessage{text} => {
+                            super::recording::event(conn.inner.id(), "chat", json!({"from": "local", "text": text}));
                             let mut misc = Misc::new();
                             misc.set_chat_message(ChatMessage {
                                 text,
@@ -662,6 +708,7 @@ impl Connection {
                         }
                         ipc::Data::SwitchPermission{name, enabled} => {
                             log::info!("Change permission {} -> {}", name, enabled);
+                            super::recording::event(conn.inner.id(), "permission", json!({"name": name, "enabled": enabled}));
                             if &name == "keyboard" {
                                 conn.keyboard = enabled;
                                 conn.send_permission(Permission::Keyboard, enabled).await;
@@ -729,6 +776,10 @@ impl Connection {
                             } else if &name == "block_input" {
                                 conn.block_input = enabled;
                                 conn.send_permission(Permission::BlockInput, enabled).await;
+                            } else if &name == "terminal_input" {
+                                #[cfg(not(any(target_os = "android", target_os = "ios")))]
+                                terminal_service::set_can_input(
+                                    &conn.terminal_service_id, conn.inner.id(), enabled);
                             } else if &name == "privacy_mode" {
                                 // Keep permission state and runtime state consistent:
                                 // when revoking the permission, try to leave privacy mode first.
@@ -909,7 +960,14 @@ impl Connection {
                 _ = conn.file_timer.tick() => {
                     if !conn.read_jobs.is_empty() {
                         conn.send_to_cm(ipc::Data::FileTransferLog(("transfer".to_string(), fs::serialize_transfer_jobs(&conn.read_jobs))));
-                        match fs::handle_read_jobs(&mut conn.read_jobs, &mut conn.stream).await {
+                        for msg in conn.deltas.next_messages(&mut conn.read_jobs).await {
+                            conn.send(msg).await;
+                        }
+                        let video_delay = video_service::VIDEO_QOS.lock().unwrap().max_user_delay();
+                        let mut ready = conn.transfer_scheduler.take_ready(&mut conn.read_jobs, video_delay);
+                        let res = fs::handle_read_jobs(&mut ready, &mut conn.stream).await;
+                        conn.transfer_scheduler.give_back(ready, &mut conn.read_jobs);
+                        match res {
                             Ok(log) => {
                                 if !log.is_empty() {
                                     conn.send_to_cm(ipc::Data::FileTransferLog(("transfer".to_string(), log)));
@@ -985,6 +1043,7 @@ impl Connection {
                             }
                         }
                         Some(message::Union::MultiClipboards(_multi_clipboards)) => {
+                            super::recording::event(conn.inner.id(), "clipboard", json!({"direction": "out"}));
                             #[cfg(not(target_os = "ios"))]
                             if let Some(msg_out) = crate::clipboard::get_msg_if_not_support_multi_clip(&conn.lr.version, &conn.lr.my_platform, _multi_clipboards) {
                                 if let Err(err) = conn.stream.send(&msg_out).await {
@@ -1007,18 +1066,25 @@ impl Connection {
                     match data {
                         #[cfg(all(target_os = "windows", feature = "flutter"))]
                         ipc::Data::PrinterData(data) => {
-                            if Self::permission(keys::OPTION_ENABLE_REMOTE_PRINTER, &conn.control_permissions) {
+                            if Self::permission(keys::OPTION_ENABLE_REMOTE_PRINTER, &conn.control_permissions, conn.permission_profile.as_ref()) {
                                 conn.send_printer_request(data).await;
                             } else {
                                 conn.send_remote_printing_disallowed().await;
                             }
                         }
+                        ipc::Data::RecordingFailed(error) => {
+                            conn.post_alarm_audit(AlarmAuditType::RecordingFailure, json!({ "error": error }));
+                            conn.send_close_reason_no_retry("Session recording failed").await;
+                            conn.on_close("session recording failed", false).await;
+                            break;
+                        }
                         _ => {}
                     }
                 }
                 _ = second_timer.tick() => {
                     #[cfg(windows)]
                     conn.portable_check();
+                    conn.transfer_scheduler.set_limits(TransferLimits::from_options(Config::get_option));
                     raii::AuthedConnID::check_wake_lock_on_setting_changed();
                     if let Some((instant, minute)) = conn.auto_disconnect_timer.as_ref() {
                         if instant.elapsed().as_secs() > minute * 60 {
@@ -1090,6 +1156,13 @@ impl Connection {
         conn.post_conn_audit(json!({
             "action": "close",
         }));
+        conn.fire_webhook(
+            webhook::Event::Disconnect,
+            json!({
+                "peer_id": conn.lr.my_id,
+                "authorized": conn.authorized,
+            }),
+        );
         if let Some(s) = conn.server.upgrade() {
             let mut s = s.write().unwrap();
             s.remove_connection(&conn.inner);
@@ -1178,19 +1251,27 @@ impl Connection {
         log::debug!("Input thread exited");
     }
 
-    async fn post_seq_loop(mut rx: mpsc::UnboundedReceiver<(String, Value)>) {
-        while let Some((url, v)) = rx.recv().await {
-            allow_err!(Self::post_audit_async(url, v).await);
-        }
-        log::debug!("post_seq_loop exited");
-    }
-
     async fn try_port_forward_loop(
         &mut self,
         rx_from_cm: &mut mpsc::UnboundedReceiver<Data>,
     ) -> ResultType<()> {
         let mut last_recv_time = Instant::now();
-        if let Some(mut forward) = self.port_forward_socket.take() {
+        let tcp_forward = match self.port_forward_socket.take() {
+            Some(PortForwardSocket::Tcp(forward)) => Some(forward),
+            Some(PortForwardSocket::Udp(forward)) => {
+                return self.udp_port_forward_loop(forward, rx_from_cm).await;
+            }
+            Some(PortForwardSocket::Reverse(listener)) => {
+                return self
+                    .mux_port_forward_loop(Some(listener), vec![], rx_from_cm)
+                    .await;
+            }
+            Some(PortForwardSocket::Mux(targets)) => {
+                return self.mux_port_forward_loop(None, targets, rx_from_cm).await;
+            }
+            None => None,
+        };
+        if let Some(mut forward) = tcp_forward {
             log::info!("Running port forwarding loop");
             self.stream.set_raw();
             let mut hbbs_rx = crate::hbbs_http::sync::signal_receiver();
@@ -1241,6 +1322,143 @@ impl Connection {
         Ok(())
     }
 
+    // The stream keeps its framing, one datagram per message, see `udp_forward`.
+    async fn udp_port_forward_loop(
+        &mut self,
+        mut forward: crate::udp_forward::UdpForwarder,
+        rx_from_cm: &mut mpsc::UnboundedReceiver<Data>,
+    ) -> ResultType<()> {
+        log::info!("Running UDP port forwarding loop");
+        let mut last_recv_time = Instant::now();
+        let mut hbbs_rx = crate::hbbs_http::sync::signal_receiver();
+        loop {
+            tokio::select! {
+                Some(data) = rx_from_cm.recv() => {
+                    match data {
+                        ipc::Data::Close => {
+                            bail!("Close requested from connection manager");
+                        }
+                        ipc::Data::CmErr(e) => {
+                            log::error!("Connection manager error: {e}");
+                            bail!("{e}");
+                        }
+                        _ => {}
+                    }
+                }
+                Some(frame) = forward.recv() => {
+                    last_recv_time = Instant::now();
+                    self.stream.send_bytes(frame).await?;
+                },
+                res = self.stream.next() => {
+                    if let Some(res) = res {
+                        last_recv_time = Instant::now();
+                        forward.send(&res?).await?;
+                    } else {
+                        bail!("Stream reset by the peer");
+                    }
+                },
+                _ = self.timer.tick() => {
+                    forward.remove_idle();
+                    if last_recv_time.elapsed() >= H1 {
+                        bail!("Timeout");
+                    }
+                }
+                Ok(conns) = hbbs_rx.recv() => {
+                    if conns.contains(&self.inner.id) {
+                        bail!("Closed manually by the web console");
+                    }
+                }
+            }
+        }
+    }
+
+    // See `forward_mux`. With a listener, for reverse forwarding, the connections accepted
+    // here are opened on the controlling side. Otherwise the controlling side opens them, to
+    // one of the `targets` declared at login.
+    async fn mux_port_forward_loop(
+        &mut self,
+        listener: Option<tokio::net::TcpListener>,
+        targets: Vec<String>,
+        rx_from_cm: &mut mpsc::UnboundedReceiver<Data>,
+    ) -> ResultType<()> {
+        log::info!("Running multiplexed port forwarding loop");
+        let mut mux = crate::forward_mux::Mux::default();
+        let mut last_recv_time = Instant::now();
+        let mut hbbs_rx = crate::hbbs_http::sync::signal_receiver();
+        loop {
+            tokio::select! {
+                Some(data) = rx_from_cm.recv() => {
+                    match data {
+                        ipc::Data::Close => {
+                            bail!("Close requested from connection manager");
+                        }
+                        ipc::Data::CmErr(e) => {
+                            log::error!("Connection manager error: {e}");
+                            bail!("{e}");
+                        }
+                        _ => {}
+                    }
+                }
+                res = async {
+                    match listener.as_ref() {
+                        Some(listener) => listener.accept().await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    let (socket, addr) = res?;
+                    log::info!("Reverse port forward connection from {:?}", addr);
+                    let frame = mux.accept(socket, "".to_owned());
+                    self.stream.send_bytes(frame.encode()).await?;
+                }
+                Some(frame) = mux.next() => {
+                    last_recv_time = Instant::now();
+                    self.stream.send_bytes(frame.encode()).await?;
+                },
+                res = self.stream.next() => {
+                    if let Some(res) = res {
+                        last_recv_time = Instant::now();
+                        let Some(frame) = crate::forward_mux::Frame::decode(&res?) else {
+                            bail!("Invalid port forward frame");
+                        };
+                        if let Some((id, target)) = mux.handle(frame) {
+                            let pending = mux.reserve(id);
+                            if listener.is_some() {
+                                // Connections are only opened from this side.
+                                pending.fail();
+                            } else if !targets.contains(&target) {
+                                log::warn!("Port forward to {} was not declared at login", target);
+                                pending.fail();
+                            } else {
+                                self.post_conn_audit(json!({
+                                    "action": "port_forward_open",
+                                    "target": target,
+                                }));
+                                tokio::spawn(async move {
+                                    match connect_mux_port_forward_target(target).await {
+                                        Some(socket) => pending.start(socket),
+                                        None => pending.fail(),
+                                    }
+                                });
+                            }
+                        }
+                    } else {
+                        bail!("Stream reset by the peer");
+                    }
+                },
+                _ = self.timer.tick() => {
+                    if last_recv_time.elapsed() >= H1 {
+                        bail!("Timeout");
+                    }
+                }
+                Ok(conns) = hbbs_rx.recv() => {
+                    if conns.contains(&self.inner.id) {
+                        bail!("Closed manually by the web console");
+                    }
+                }
+            }
+        }
+    }
+
     async fn send_permission(&mut self, permission: Permission, enabled: bool) {
         let mut misc = Misc::new();
         misc.set_permission_info(PermissionInfo {
@@ -1375,6 +1593,7 @@ impl Connection {
             audit["conn_audit_ref"] = json!(audit_ref);
         }
         self.post_conn_audit(audit);
+        self.fire_webhook(webhook::Event::Connect, json!({ "ip": self.ip }));
         true
     }
 
@@ -1404,10 +1623,6 @@ impl Connection {
     }
 
     fn post_conn_audit(&self, v: Value) {
-        if self.server_audit_conn.is_empty() {
-            return;
-        }
-        let url = self.server_audit_conn.clone();
         let mut v = v;
         v["id"] = json!(Config::get_id());
         v["uuid"] = json!(crate::encode64(hbb_common::get_uuid()));
@@ -1415,7 +1630,14 @@ impl Connection {
         v["session_id"] = json!(self.lr.session_id);
         // Unique per record; the api server dedups retried posts by it.
         v["nonce"] = json!(uuid::Uuid::new_v4().to_string());
-        allow_err!(self.tx_post_seq.send((url, v)));
+        super::audit_sink::emit("conn", &v);
+        if !self.server_audit_conn.is_empty() {
+            super::audit_spool::push(self.server_audit_conn.clone(), v);
+        }
+    }
+
+    fn fire_webhook(&self, event: webhook::Event, data: Value) {
+        webhook::fire(event, self.inner.id(), data);
     }
 
     fn get_files_for_audit(job_type: fs::JobType, mut files: Vec<FileEntry>) -> Vec<(String, i64)> {
@@ -1441,15 +1663,24 @@ impl Connection {
         files: Vec<(String, i64)>,
         info: Value,
     ) {
-        if self.server_audit_file.is_empty() {
-            return;
-        }
-        let url = self.server_audit_file.clone();
         let file_num = files.len();
         let mut files = files;
         files.sort_by(|a, b| b.1.cmp(&a.1));
         files.truncate(10);
         let is_file = files.len() == 1 && files[0].0.is_empty();
+        self.fire_webhook(
+            webhook::Event::FileTransfer,
+            json!({
+                "peer_id": self.lr.my_id,
+                "direction": match r#type {
+                    FileAuditType::RemoteSend => "send",
+                    FileAuditType::RemoteReceive => "receive",
+                },
+                "path": path,
+                "num": file_num,
+                "files": files,
+            }),
+        );
         let mut info = info;
         info["ip"] = json!(self.ip.clone());
         info["name"] = json!(self.lr.my_name.clone());
@@ -1466,9 +1697,10 @@ impl Connection {
             "info":json!(info).to_string(),
             "nonce": uuid::Uuid::new_v4().to_string(),
         });
-        tokio::spawn(async move {
-            allow_err!(Self::post_audit_async(url, v).await);
-        });
+        super::audit_sink::emit("file", &v);
+        if !self.server_audit_file.is_empty() {
+            super::audit_spool::push(self.server_audit_file.clone(), v);
+        }
     }
 
     fn post_alarm_audit(&self, typ: AlarmAuditType, info: Value) {
@@ -1477,9 +1709,6 @@ impl Connection {
             Config::get_option("custom-rendezvous-server"),
             "alarm".to_owned(),
         );
-        if url.is_empty() {
-            return;
-        }
         let mut v = Value::default();
         v["id"] = json!(Config::get_id());
         v["uuid"] = json!(crate::encode64(hbb_common::get_uuid()));
@@ -1492,9 +1721,10 @@ impl Connection {
                 v["conn_audit_ref"] = json!(audit_ref);
             }
         }
-        tokio::spawn(async move {
-            allow_err!(Self::post_audit_async(url, v).await);
-        });
+        super::audit_sink::emit("alarm", &v);
+        if !url.is_empty() {
+            super::audit_spool::push(url, v);
+        }
     }
 
     fn post_session_scope_violation_alarm(&self, message: &'static str) {
@@ -1514,105 +1744,6 @@ impl Connection {
         );
     }
 
-    async fn post_audit_async(url: String, v: Value) -> ResultType<String> {
-        // Audit records are compliance evidence; retry transport errors and
-        // 5xx (e.g. a reverse proxy answering while the api server restarts)
-        // so transient failures don't silently drop them. A 4xx is a
-        // deterministic rejection and fails immediately.
-        //
-        // The delays, not the attempt count, are what cover the case this exists
-        // for: a proxy answering 502 during a restart fails fast, so without them
-        // every attempt lands within a few seconds and none outlives the restart.
-        //
-        // The window is bounded on the other side: the api server only remembers a
-        // record's nonce for five minutes, so a retry arriving after that expired
-        // would be stored a second time. Counting attempts cannot bound it - one
-        // attempt is already up to 84s (post_request_ retries the TLS handshake up
-        // to four times at 12s each, then the TCP-proxy fallback adds 36s), and a
-        // suspend between attempts stretches the wall clock without limit. So stop
-        // by elapsed time instead, early enough that the last attempt still lands
-        // inside the server's window.
-        const RETRY_DEADLINE: Duration = Duration::from_secs(120);
-        // One delay per retry, so the attempt count follows from the table and the
-        // two cannot drift apart.
-        const RETRY_BACKOFF_SECS: [u64; 2] = [10, 30];
-        const ATTEMPTS: usize = RETRY_BACKOFF_SECS.len() + 1;
-        let body = v.to_string();
-        let started = Instant::now();
-        let mut attempt = 0usize;
-        loop {
-            attempt += 1;
-            let (retryable, err) =
-                match crate::post_request_with_status(url.clone(), body.clone(), "").await {
-                    Ok((status, text)) => {
-                        if (200..300).contains(&status) {
-                            // Success is an empty body. hbbs reports handler
-                            // failures (e.g. a db write error) as 200 with an
-                            // {"error": ...} body - retryable: the server
-                            // releases the record's nonce when its write fails,
-                            // so trying again is what stores the record. Any
-                            // other nonempty body did not come from the audit
-                            // handler (a proxy interposing a 2xx maintenance
-                            // page, a malformed error) and must not be mistaken
-                            // for storage, so it is retried rather than dropped.
-                            if text.trim().is_empty() {
-                                return Ok(text);
-                            }
-                            let server_err = serde_json::from_str::<Value>(&text)
-                                .ok()
-                                .and_then(|v| v.get("error")?.as_str().map(|s| s.to_owned()))
-                                .filter(|e| !e.is_empty());
-                            let (label, detail) = match &server_err {
-                                Some(e) => ("server error", e.as_str()),
-                                None => ("unexpected response body", text.as_str()),
-                            };
-                            let brief: String = detail.chars().take(128).collect();
-                            (true, format!("{}: {}", label, brief))
-                        } else {
-                            let brief: String = text.chars().take(128).collect();
-                            // 408 and 429 are the transient 4xx: the request timed
-                            // out upstream, or a proxy is shedding load. Every other
-                            // 4xx is a deterministic rejection and retrying it would
-                            // only delay the log line.
-                            let transient = status >= 500 || status == 408 || status == 429;
-                            (transient, format!("status {}: {}", status, brief))
-                        }
-                    }
-                    Err(e) => (true, e.to_string()),
-                };
-            let elapsed = started.elapsed();
-            if !retryable || attempt >= ATTEMPTS || elapsed >= RETRY_DEADLINE {
-                log::error!(
-                    "Audit post dropped (attempt {}/{}, {:?} elapsed): {}",
-                    attempt,
-                    ATTEMPTS,
-                    elapsed,
-                    err
-                );
-                bail!("{}", err);
-            }
-            log::warn!(
-                "Audit post failed (attempt {}/{}): {}",
-                attempt,
-                ATTEMPTS,
-                err
-            );
-            // In range by construction: the guard above returns at ATTEMPTS.
-            time::sleep(Duration::from_secs(RETRY_BACKOFF_SECS[attempt - 1])).await;
-            // Re-checked after the delay so no attempt starts past the deadline;
-            // the check above alone would let one begin up to a backoff later.
-            if started.elapsed() >= RETRY_DEADLINE {
-                log::error!(
-                    "Audit post dropped (attempt {}/{}, deadline passed during backoff): {}",
-                    attempt,
-                    ATTEMPTS,
-                    err
-                );
-                bail!("{}", err);
-            }
-        }
-    }
-
     fn set_conn_audit_primary_auth(&mut self, method: ConnAuditPrimaryAuth) {
         self.conn_audit_primary_auth = method;
     }
@@ -1631,7 +1762,19 @@ impl Connection {
     }
 
     fn normalize_port_forward_target(pf: &mut PortForward) -> (String, bool) {
+        if crate::forward_mux::parse_mux_host(&pf.host).is_some() {
+            return (pf.host.clone(), false);
+        }
         let mut is_rdp = false;
+        let prefix = [
+            crate::udp_forward::UDP_PREFIX,
+            crate::forward_mux::REVERSE_PREFIX,
+        ]
+        .into_iter()
+        .find(|prefix| pf.host.starts_with(prefix));
+        if let Some(prefix) = prefix {
+            pf.host = pf.host[prefix.len()..].to_owned();
+        }
         if pf.host == "RDP" && pf.port == 0 {
             pf.host = "localhost".to_owned();
             pf.port = 3389;
@@ -1640,7 +1783,33 @@ impl Connection {
         if pf.host.is_empty() {
             pf.host = "localhost".to_owned();
         }
-        (format!("{}:{}", pf.host, pf.port), is_rdp)
+        let addr = format!("{}:{}", pf.host, pf.port);
+        if let Some(prefix) = prefix {
+            return (format!("{}{}", prefix, addr), false);
+        }
+        (addr, is_rdp)
+    }
+
+    // The same reverse port forward of the same session was accepted before.
+    fn is_reverse_port_forward_reconnection(&self) -> bool {
+        let mut lock = REVERSE_PORT_FORWARD_APPROVALS.lock().unwrap();
+        lock.retain(|_, closed| closed.map_or(true, |t| t.elapsed() < SESSION_TIMEOUT));
+        match lock.get_mut(&(self.session_key(), self.port_forward_address.clone())) {
+            Some(closed) => {
+                *closed = None;
+                log::info!("reverse port forward reconnection");
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn is_reverse_port_forward(&self) -> bool {
+        matches!(
+            self.lr.union.as_ref(),
+            Some(login_request::Union::PortForward(pf))
+                if crate::forward_mux::strip_reverse_prefix(&pf.host).is_some()
+        )
     }
 
     async fn connect_port_forward_if_needed(&mut self) -> bool {
@@ -1653,9 +1822,63 @@ impl Connection {
         let mut pf = pf.clone();
         let (mut addr, is_rdp) = Self::normalize_port_forward_target(&mut pf);
         self.port_forward_address = addr.clone();
-        match timeout(3000, TcpStream::connect(&addr)).await {
+        if let Some(targets) = crate::forward_mux::parse_mux_host(&addr) {
+            // Each connection is checked and connected when it is opened.
+            self.port_forward_socket = Some(PortForwardSocket::Mux(targets));
+            return true;
+        }
+        if let Some(bind_addr) = crate::forward_mux::strip_reverse_prefix(&addr) {
+            return match tokio::net::TcpListener::bind(bind_addr).await {
+                Ok(listener) => {
+                    self.port_forward_socket = Some(PortForwardSocket::Reverse(listener));
+                    true
+                }
+                Err(e) => {
+                    log::warn!(
+                        "Reverse port forward failed to listen on {}: {}",
+                        bind_addr,
+                        e
+                    );
+                    self.send_login_error(format!("Failed to listen on {}: {}", bind_addr, e))
+                        .await;
+                    false
+                }
+            };
+        }
+        let udp_target = crate::udp_forward::strip_prefix(&addr).map(|x| x.to_owned());
+        let Some(target) =
+            allowed_port_forward_target(udp_target.as_deref().unwrap_or(&addr)).await
+        else {
+            log::warn!("Port forward to {} is not in the allowed networks", addr);
+            self.send_login_error(format!(
+                "Port forwarding to {} is not allowed by the peer",
+                addr
+            ))
+            .await;
+            return false;
+        };
+        if udp_target.is_some() {
+            // Nothing to connect to, only check the target resolves.
+            return match crate::udp_forward::UdpForwarder::new(&target).await {
+                Ok(forward) => {
+                    self.port_forward_socket = Some(PortForwardSocket::Udp(forward));
+                    true
+                }
+                Err(e) => {
+                    log::warn!("UDP port forward failed for {}: {}", addr, e);
+                    self.send_login_error(format!(
+                        "Failed to access remote {}. Please make sure it is reachable/open.",
+                        addr
+                    ))
+                    .await;
+                    false
+                }
+            };
+        }
+        match timeout(3000, TcpStream::connect(&target)).await {
             Ok(Ok(sock)) => {
-                self.port_forward_socket = Some(Framed::new(sock, BytesCodec::new()));
+                self.port_forward_socket =
+                    Some(PortForwardSocket::Tcp(Framed::new(sock, BytesCodec::new())));
                 true
             }
             Ok(Err(e)) => {
@@ -1728,9 +1951,30 @@ impl Connection {
         if let Some(keep_alive) = self.prepare_terminal_login_for_authorization().await {
             return keep_alive;
         }
+        if self.is_reverse_port_forward() && !self.reverse_port_forward_approved {
+            self.reverse_port_forward_approved = self.is_reverse_port_forward_reconnection();
+        }
+        if self.is_reverse_port_forward() && !self.reverse_port_forward_approved {
+            // Listening on this machine always needs to be accepted in the connection manager,
+            // even with the password.
+            self.try_start_cm(self.lr.my_id.clone(), self.lr.my_name.clone(), false);
+            self.send_login_error(crate::client::LOGIN_MSG_NO_PASSWORD_ACCESS)
+                .await;
+            return true;
+        }
+        if !self.apply_permission_profile().await {
+            return false;
+        }
         if !self.connect_port_forward_if_needed().await {
             return false;
         }
+        if (self.is_remote() || self.terminal) && super::recording::is_enforced() {
+            if let Err(e) = super::recording::prepare() {
+                log::error!("Session recording is required but unavailable: {}", e);
+                self.send_login_error("Session recording is required but unavailable").await;
+                return false;
+            }
+        }
         self.authorized = true;
         // Releases the budget `check_id_whitelist` charges against this address: only a peer
         // that got this far proved more than a self-reported id.
@@ -1767,6 +2011,33 @@ impl Connection {
             audit["two_factor"] = json!(self.conn_audit_two_factor.as_i64());
         }
         self.post_conn_audit(audit);
+        self.fire_webhook(
+            webhook::Event::Authorize,
+            json!({
+                "peer_id": self.lr.my_id,
+                "peer_name": self.lr.my_name,
+                "ip": self.ip,
+                "type": auth_conn_type.as_str(),
+            }),
+        );
+        super::recording::session_started(
+            self.inner.id(),
+            json!({
+                "id": self.lr.my_id,
+                "name": self.lr.my_name,
+                "ip": self.ip,
+                "type": conn_type,
+                "permissions": {
+                    "keyboard": self.keyboard,
+                    "clipboard": self.clipboard,
+                    "audio": self.audio,
+                    "file": self.file,
+                    "restart": self.restart,
+                    "recording": self.recording,
+                    "block_input": self.block_input,
+                },
+            }),
+        );
         #[allow(unused_mut)]
         let mut username = crate::platform::get_active_username();
         let mut res = LoginResponse::new();
@@ -2000,6 +2271,9 @@ impl Connection {
         msg_out.set_login_response(res);
         self.send(msg_out).await;
         self.update_scoped_login_options().await;
+        if (self.is_remote() || self.terminal) && super::recording::record_incoming() {
+            self.send_recording_notice().await;
+        }
         if let Some((dir, show_hidden)) = self.file_transfer.clone() {
             self.keyboard = false;
             let dir = if !dir.is_empty() && std::path::Path::new(&dir).is_dir() {
@@ -2187,6 +2461,27 @@ impl Connection {
 
     #[inline]
     fn send_to_cm(&mut self, data: ipc::Data) {
+        match &data {
+            ipc::Data::ChatMessage { text } => {
+                super::recording::event(
+                    self.inner.id(),
+                    "chat",
+                    json!({"from": "peer", "text": text}),
+                );
+            }
+            ipc::Data::FileTransferLog((kind, log)) => {
+                let files = serde_json::from_str::<Value>(log).unwrap_or(json!(log));
+                // The periodic progress of the read jobs is an array, only the outcomes are kept.
+                if !files.is_array() {
+                    super::recording::event(
+                        self.inner.id(),
+                        "file_transfer",
+                        json!({"kind": kind, "files": files}),
+                    );
+                }
+            }
+            _ => {}
+        }
         self.tx_to_cm.send(data).ok();
     }
 
@@ -2364,49 +2659,50 @@ impl Connection {
     }
 
     fn validate_password(&mut self, allow_permanent_password: bool) -> bool {
-        if password::temporary_enabled() {
-            let password = password::temporary_password();
-            if self.validate_password_plain(&password) {
-                self.set_conn_audit_primary_auth(ConnAuditPrimaryAuth::TemporaryPassword);
-                raii::AuthedConnID::update_or_insert_session(
-                    self.session_key(),
-                    Some(password),
-                    Some(false),
-                );
-                self.check_update_temporary_password(true);
-                return true;
-            }
+        let Some(primary_auth) = self.check_password(allow_permanent_password) else {
+            return false;
+        };
+        self.set_conn_audit_primary_auth(primary_auth);
+        if primary_auth == ConnAuditPrimaryAuth::TemporaryPassword {
+            raii::AuthedConnID::update_or_insert_session(
+                self.session_key(),
+                Some(password::temporary_password()),
+                Some(false),
+                ConnAuditPrimaryAuth::TemporaryPassword,
+            );
+            self.check_update_temporary_password(true);
+        } else if allow_permanent_password && !password::permanent_enabled() {
+            log::info!("Permanent password accepted via logon-screen fallback");
+        }
+        true
+    }
+
+    // The kind of the password of the login if it is valid, without the side effects of
+    // `validate_password`.
+    fn check_password(&self, allow_permanent_password: bool) -> Option<ConnAuditPrimaryAuth> {
+        if password::temporary_enabled()
+            && self.validate_password_plain(&password::temporary_password())
+        {
+            return Some(ConnAuditPrimaryAuth::TemporaryPassword);
         }
         if password::permanent_enabled() || allow_permanent_password {
-            let print_fallback = || {
-                if allow_permanent_password && !password::permanent_enabled() {
-                    log::info!("Permanent password accepted via logon-screen fallback");
-                }
-            };
             // Strictly check storage usability before auth so malformed encrypted/hash storage
             // cannot fall back to being accepted as legacy plaintext.
             let (local_storage, local_salt) =
                 Config::get_local_permanent_password_storage_and_salt();
-            if !local_storage.is_empty() {
-                if local_permanent_password_storage_is_usable_for_auth(&local_storage, &local_salt)
+            let valid = if !local_storage.is_empty() {
+                local_permanent_password_storage_is_usable_for_auth(&local_storage, &local_salt)
                     && self.validate_password_storage(&local_storage)
-                {
-                    self.set_conn_audit_primary_auth(ConnAuditPrimaryAuth::PermanentPassword);
-                    print_fallback();
-                    return true;
-                }
             } else {
                 let (hard, salt) = Config::get_preset_password_storage_and_salt();
-                if preset_permanent_password_storage_is_usable_for_auth(&hard, &salt)
+                preset_permanent_password_storage_is_usable_for_auth(&hard, &salt)
                     && self.validate_preset_password_storage(&hard, &salt)
-                {
-                    self.set_conn_audit_primary_auth(ConnAuditPrimaryAuth::PermanentPassword);
-                    print_fallback();
-                    return true;
-                }
+            };
+            if valid {
+                return Some(ConnAuditPrimaryAuth::PermanentPassword);
             }
         }
-        false
+        None
     }
 
     fn is_recent_session(&mut self, tfa: bool) -> bool {
@@ -2427,8 +2723,13 @@ impl Connection {
             {
                 if tfa {
                     self.set_conn_audit_two_factor(ConnAuditTwoFactor::Totp);
+                    if self.conn_audit_primary_auth == ConnAuditPrimaryAuth::None {
+                        self.set_conn_audit_primary_auth(session.primary_auth);
+                    }
                 } else {
-                    self.set_conn_audit_primary_auth(ConnAuditPrimaryAuth::TemporaryPassword);
+                    // Not necessarily the current temporary password, the kind of the first login
+                    // is kept, `None` if unknown.
+                    self.set_conn_audit_primary_auth(session.primary_auth);
                 }
                 log::info!("is recent session");
                 return true;
@@ -2458,8 +2759,13 @@ impl Connection {
     fn permission(
         enable_prefix_option: &str,
         control_permissions: &Option<ControlPermissions>,
+        profile: Option<&permission_profile::Profile>,
     ) -> bool {
         use hbb_common::rendezvous_proto::control_permissions::Permission;
+        // The profile of the peer only takes permissions away.
+        if profile.is_some_and(|p| !p.allows(enable_prefix_option)) {
+            return false;
+        }
         if let Some(control_permissions) = control_permissions {
             let permission = match enable_prefix_option {
                 keys::OPTION_ENABLE_KEYBOARD => Some(Permission::keyboard),
@@ -2487,6 +2793,77 @@ impl Connection {
         Self::is_permission_enabled_locally(enable_prefix_option)
     }
 
+    // Until the peer is authenticated, its profile has the permissions of all the ways it may
+    // log in, the login is refused early only if none of them allows what it asks for.
+    fn select_login_permission_profile(&mut self, lr: &LoginRequest) {
+        use permission_profile::Auth;
+        self.permission_profile = match self.conn_audit_primary_auth.profile_auth() {
+            // A recent session keeps the way it first logged in.
+            Some(auth) => permission_profile::select(&lr.my_id, Some(auth)),
+            None if lr.password.is_empty() => {
+                permission_profile::select_any(&lr.my_id, &[Auth::Click, Auth::Switch])
+            }
+            None => permission_profile::select_any(
+                &lr.my_id,
+                &[Auth::Click, Auth::Temporary, Auth::Permanent, Auth::Switch],
+            ),
+        };
+    }
+
+    // The profile is picked once the peer and the way it logged in are known, before the
+    // permissions are used by the services and shown in the connection manager.
+    async fn apply_permission_profile(&mut self) -> bool {
+        self.permission_profile = permission_profile::select(
+            &self.lr.my_id,
+            self.conn_audit_primary_auth.profile_auth(),
+        );
+        let Some(profile) = self.permission_profile.clone() else {
+            return true;
+        };
+        log::info!("#{} permission profile {}", self.inner.id(), profile.name);
+        let denied = if self.file_transfer.is_some()
+            && !profile.allows(keys::OPTION_ENABLE_FILE_TRANSFER)
+        {
+            Some("No permission of file transfer")
+        } else if self.view_camera && !profile.allows(keys::OPTION_ENABLE_CAMERA) {
+            Some("No permission of viewing camera")
+        } else if self.terminal && !profile.allows(keys::OPTION_ENABLE_TERMINAL) {
+            Some("No permission of terminal")
+        } else if matches!(self.lr.union, Some(login_request::Union::PortForward(_)))
+            && !profile.allows(keys::OPTION_ENABLE_TUNNEL)
+        {
+            Some("No permission of IP tunneling")
+        } else {
+            None
+        };
+        if let Some(err) = denied {
+            self.send_login_error(err).await;
+            sleep(1.).await;
+            return false;
+        }
+        let mut narrowed = vec![];
+        for (option, permission, enabled) in [
+            (keys::OPTION_ENABLE_KEYBOARD, Permission::Keyboard, &mut self.keyboard),
+            (keys::OPTION_ENABLE_CLIPBOARD, Permission::Clipboard, &mut self.clipboard),
+            (keys::OPTION_ENABLE_AUDIO, Permission::Audio, &mut self.audio),
+            (keys::OPTION_ENABLE_FILE_TRANSFER, Permission::File, &mut self.file),
+            (keys::OPTION_ENABLE_REMOTE_RESTART, Permission::Restart, &mut self.restart),
+            (keys::OPTION_ENABLE_RECORD_SESSION, Permission::Recording, &mut self.recording),
+            (keys::OPTION_ENABLE_BLOCK_INPUT, Permission::BlockInput, &mut self.block_input),
+            (keys::OPTION_ENABLE_PRIVACY_MODE, Permission::PrivacyMode, &mut self.privacy_mode),
+        ] {
+            if *enabled && !profile.allows(option) {
+                *enabled = false;
+                narrowed.push(permission);
+            }
+        }
+        // The peer was told the permissions when the connection started.
+        for permission in narrowed {
+            self.send_permission(permission, false).await;
+        }
+        true
+    }
+
     fn update_codec_on_login(&self) {
         use scrap::codec::{Encoder, EncodingUpdate::*};
         if let Some(o) = self.lr.clone().option.as_ref() {
@@ -2682,11 +3059,13 @@ impl Connection {
             if !self.check_id_whitelist().await {
                 return false;
             }
+            self.select_login_permission_profile(&lr);
             match lr.union {
                 Some(login_request::Union::FileTransfer(ft)) => {
                     if !Self::permission(
                         keys::OPTION_ENABLE_FILE_TRANSFER,
                         &self.control_permissions,
+                        self.permission_profile.as_ref(),
                     ) {
                         self.send_login_error("No permission of file transfer")
                             .await;
@@ -2696,7 +3075,11 @@ impl Connection {
                     self.file_transfer = Some((ft.dir, ft.show_hidden));
                 }
                 Some(login_request::Union::ViewCamera(_vc)) => {
-                    if !Self::permission(keys::OPTION_ENABLE_CAMERA, &self.control_permissions) {
+                    if !Self::permission(
+                        keys::OPTION_ENABLE_CAMERA,
+                        &self.control_permissions,
+                        self.permission_profile.as_ref(),
+                    ) {
                         self.send_login_error("No permission of viewing camera")
                             .await;
                         sleep(1.).await;
@@ -2705,7 +3088,11 @@ impl Connection {
                     self.view_camera = true;
                 }
                 Some(login_request::Union::Terminal(terminal)) => {
-                    if !Self::permission(keys::OPTION_ENABLE_TERMINAL, &self.control_permissions) {
+                    if !Self::permission(
+                        keys::OPTION_ENABLE_TERMINAL,
+                        &self.control_permissions,
+                        self.permission_profile.as_ref(),
+                    ) {
                         self.send_login_error("No permission of terminal").await;
                         sleep(1.).await;
                         return false;
@@ -2726,11 +3113,43 @@ impl Connection {
                     self.terminal_service_id = terminal.service_id;
                 }
                 Some(login_request::Union::PortForward(mut pf)) => {
-                    if !Self::permission(keys::OPTION_ENABLE_TUNNEL, &self.control_permissions) {
+                    if !Self::permission(
+                        keys::OPTION_ENABLE_TUNNEL,
+                        &self.control_permissions,
+                        self.permission_profile.as_ref(),
+                    ) {
                         self.send_login_error("No permission of IP tunneling").await;
                         sleep(1.).await;
                         return false;
                     }
+                    if let Some(bind_host) = crate::forward_mux::strip_reverse_prefix(&pf.host) {
+                        if Config::get_option(OPTION_ALLOW_REVERSE_TUNNEL) != "Y" {
+                            self.send_login_error("No permission of reverse IP tunneling")
+                                .await;
+                            sleep(1.).await;
+                            return false;
+                        }
+                        if !is_reverse_bind_allowed(
+                            bind_host,
+                            Config::get_option(OPTION_ALLOW_REVERSE_TUNNEL_ANY_ADDRESS) == "Y",
+                        ) {
+                            self.send_login_error(format!(
+                                "Reverse port forwarding may only listen on localhost, not {}",
+                                bind_host
+                            ))
+                            .await;
+                            sleep(1.).await;
+                            return false;
+                        }
+                    }
+                    if crate::forward_mux::parse_mux_host(&pf.host)
+                        .map_or(false, |targets| targets.is_empty())
+                    {
+                        self.send_login_error("No port forward targets declared")
+                            .await;
+                        sleep(1.).await;
+                        return false;
+                    }
                     let (addr, _is_rdp) = Self::normalize_port_forward_target(&mut pf);
                     self.port_forward_address = addr;
                 }
@@ -2790,9 +3209,56 @@ impl Connection {
                 crate::get_builtin_option(keys::OPTION_ALLOW_LOGON_SCREEN_PASSWORD) == "Y"
                     && is_logon();
 
-            if (password::approve_mode() == ApproveMode::Click && !allow_logon_screen_password)
-                || password::approve_mode() == ApproveMode::Both && !password::has_valid_password()
-            {
+            let click_only = (password::approve_mode() == ApproveMode::Click
+                && !allow_logon_screen_password)
+                || password::approve_mode() == ApproveMode::Both && !password::has_valid_password();
+            let outside_schedule = !click_only && !access_schedule::is_open_now();
+            if outside_schedule {
+                let fallback = access_schedule::allow_click_outside();
+                // Only the logins that would be accepted within the windows are reported, a peer
+                // without the password can't flood the alarms.
+                let unattended = if self.is_recent_session(false) {
+                    true
+                } else if lr.password.is_empty() {
+                    false
+                } else {
+                    let (failure, res) = self.check_failure(0).await;
+                    if !res {
+                        return true;
+                    }
+                    let valid = self.check_password(allow_logon_screen_password).is_some();
+                    self.update_failure_with_scope(failure, valid, 0, FailureScope::Default);
+                    if !valid {
+                        self.check_update_temporary_password(false);
+                        self.send_login_error(crate::client::LOGIN_MSG_PASSWORD_WRONG)
+                            .await;
+                        if fallback {
+                            self.try_start_cm(lr.my_id, lr.my_name, false);
+                        }
+                        return true;
+                    }
+                    true
+                };
+                if unattended {
+                    self.post_alarm_audit(
+                        AlarmAuditType::AccessSchedule,
+                        json!({
+                            "ip": self.ip,
+                            "id": lr.my_id.clone(),
+                            "name": lr.my_name.clone(),
+                            "click": fallback,
+                        }),
+                    );
+                }
+                if !fallback {
+                    self.send_login_error(access_schedule::LOGIN_MSG_OUTSIDE_ACCESS_SCHEDULE)
+                        .await;
+                    sleep(1.).await;
+                    return false;
+                }
+            }
+
+            if click_only || outside_schedule {
                 #[cfg(not(any(target_os = "android", target_os = "ios")))]
                 if should_use_terminal_os_login_scope(self.terminal, &lr.os_login.username) {
                     if let Some(keep_alive) = self.prepare_terminal_login_for_authorization().await
@@ -2857,7 +3323,10 @@ impl Connection {
                         self.update_failure(failure, true, 1);
                         self.require_2fa.take();
                         self.set_conn_audit_two_factor(ConnAuditTwoFactor::Totp);
-                        raii::AuthedConnID::set_session_2fa(self.session_key());
+                        raii::AuthedConnID::set_session_2fa(
+                            self.session_key(),
+                            self.conn_audit_primary_auth,
+                        );
                         if !self.send_logon_response_and_keep_alive().await {
                             return false;
                         }
@@ -3144,6 +3613,11 @@ impl Connection {
                 }
                 Some(message::Union::Clipboard(cb)) => {
                     if self.should_handle_text_clipboard_message() && self.clipboard_enabled() {
+                        super::recording::event(
+                            self.inner.id(),
+                            "clipboard",
+                            json!({"direction": "in"}),
+                        );
                         #[cfg(not(any(target_os = "android", target_os = "ios")))]
                         update_clipboard(vec![cb], ClipboardSide::Host);
                         // ios as the controlled side is actually not supported for now.
@@ -3172,6 +3646,11 @@ impl Connection {
                 }
                 Some(message::Union::MultiClipboards(_mcb)) => {
                     if self.should_handle_text_clipboard_message() && self.clipboard_enabled() {
+                        super::recording::event(
+                            self.inner.id(),
+                            "clipboard",
+                            json!({"direction": "in"}),
+                        );
                         #[cfg(not(any(target_os = "android", target_os = "ios")))]
                         update_clipboard(_mcb.clipboards, ClipboardSide::Host);
                         #[cfg(target_os = "android")]
@@ -3463,6 +3942,7 @@ impl Connection {
                                 )));
                             }
                             Some(file_action::Union::Cancel(c)) => {
+                                self.deltas.remove_job(c.id);
                                 self.send_fs(ipc::FS::CancelWrite { id: c.id });
                                 let _ = self.cm_read_job_ids.remove(&c.id);
                                 self.send_fs(ipc::FS::CancelRead {
@@ -3523,6 +4003,22 @@ impl Connection {
                             compressed: block.compressed,
                         });
                     }
+                    Some(file_response::Union::Signature(s)) => {
+                        if self.read_jobs.iter().any(|j| j.id() == s.id) {
+                            if let Err(err) = self.deltas.start_read(&mut self.read_jobs, &s) {
+                                self.send(fs::new_error(s.id, err, s.file_num)).await;
+                            }
+                        } else if self.cm_read_job_ids.contains(&s.id) {
+                            if let Ok(data) = s.write_to_bytes() {
+                                self.send_fs(ipc::FS::ReadDelta { data: data.into() });
+                            }
+                        }
+                    }
+                    Some(file_response::Union::Delta(d)) => {
+                        if let Ok(data) = d.write_to_bytes() {
+                            self.send_fs(ipc::FS::WriteDelta { data: data.into() });
+                        }
+                    }
                     Some(file_response::Union::Done(d)) => {
                         self.send_fs(ipc::FS::WriteDone {
                             id: d.id,
@@ -4000,6 +4496,20 @@ impl Connection {
                 }
             }
         }
+        // The service may be running and shared, only the user it was created for may attach.
+        if let Some(owner) = terminal_service::service_owner(&self.terminal_service_id) {
+            if owner != self.terminal_owner() {
+                log::error!(
+                    "Terminal service owner mismatch: ip={} conn_id={}",
+                    self.ip,
+                    self.inner.id()
+                );
+                self.send_login_error("Terminal service user mismatch detected.")
+                    .await;
+                sleep(1.).await;
+                return Some(false);
+            }
+        }
         if is_terminal_os_login {
             self.try_start_cm_ipc();
         }
@@ -4456,11 +4966,16 @@ impl Connection {
     }
 
     async fn toggle_privacy_mode(&mut self, t: TogglePrivacyMode) {
+        let was_on = privacy_mode::is_in_privacy_mode();
         if t.on {
             self.turn_on_privacy(t.impl_key).await;
         } else {
             self.turn_off_privacy(t.impl_key).await;
         }
+        let on = privacy_mode::is_in_privacy_mode();
+        if on != was_on {
+            self.fire_webhook(webhook::Event::PrivacyMode, json!({ "on": on }));
+        }
     }
 
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -5865,6 +6380,19 @@ impl Connection {
         self.printer_data.push((Instant::now(), path, data));
     }
 
+    async fn send_recording_notice(&mut self) {
+        let mut msg_out = Message::new();
+        let res = MessageBox {
+            msgtype: "custom-nook-nocancel-hasclose".to_owned(),
+            title: "Recording".to_owned(),
+            text: "incoming-session-recorded-tip".to_owned(),
+            link: "".to_owned(),
+            ..Default::default()
+        };
+        msg_out.set_message_box(res);
+        self.send(msg_out).await;
+    }
+
     #[cfg(all(target_os = "windows", feature = "flutter"))]
     async fn send_remote_printing_disallowed(&mut self) {
         let mut msg_out = Message::new();
@@ -5885,6 +6413,23 @@ impl Connection {
         terminal_service::set_persistent(&self.terminal_service_id, persistent).ok();
     }
 
+    // The user the terminal runs for: the OS login user if any, else the user of the session
+    // or of this process.
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    fn terminal_owner(&self) -> String {
+        let username = self.lr.os_login.username.trim();
+        if should_use_terminal_os_login_scope(self.terminal, username) {
+            return format!("os-login:{}", username.to_lowercase());
+        }
+        match &self.terminal_user_token {
+            #[cfg(target_os = "windows")]
+            Some(TerminalUserToken::CurrentLogonUser(_)) => {
+                format!("session:{}", crate::platform::get_active_username())
+            }
+            _ => "self".to_owned(),
+        }
+    }
+
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
     async fn init_terminal_service(&mut self) {
         debug_assert!(self.terminal_user_token.is_some());
@@ -5896,13 +6441,27 @@ impl Connection {
         if self.terminal_service_id.is_empty() {
             self.terminal_service_id = terminal_service::generate_service_id();
         }
-        let s = Box::new(terminal_service::new(
+        let (s, shared) = match terminal_service::new(
             self.terminal_service_id.clone(),
             self.terminal_persistent,
             user_token.to_terminal_service_token(),
-        ));
-        s.on_subscribe(self.inner.clone());
-        self.terminal_generic_service = Some(s);
+            &self.terminal_owner(),
+            self.inner.clone(),
+        ) {
+            Ok(res) => res,
+            Err(e) => {
+                log::error!("Failed to attach to terminal service: {}", e);
+                return;
+            }
+        };
+        self.terminal_generic_service = Some(Box::new(s));
+        if shared {
+            // Let the connection manager show the input permission of the attachment.
+            self.send_to_cm(ipc::Data::SwitchPermission {
+                name: "terminal_input".to_owned(),
+                enabled: terminal_service::can_input(&self.terminal_service_id, self.inner.id()),
+            });
+        }
     }
 
     #[cfg(not(any(target_os = "android", target_os = "ios")))]
@@ -5912,6 +6471,19 @@ impl Connection {
             // unreacheable, but keep it for safety
             bail!("Terminal user token is not set.");
         };
+        // Observers of a shared terminal can only attach to running terminals.
+        if !terminal_service::can_input(&self.terminal_service_id, self.inner.id()) {
+            let allowed = match &action.union {
+                Some(terminal_action::Union::Open(open)) => {
+                    terminal_service::has_terminal(&self.terminal_service_id, open.terminal_id)
+                }
+                _ => false,
+            };
+            if !allowed {
+                log::debug!("Ignore terminal action of read-only attachment");
+                return Ok(());
+            }
+        }
         let mut proxy = terminal_service::TerminalServiceProxy::new(
             self.terminal_service_id.clone(),
             Some(self.terminal_persistent),
@@ -5923,6 +6495,11 @@ impl Connection {
                 let mut msg_out = Message::new();
                 msg_out.set_terminal_response(response);
                 self.send(msg_out).await;
+                if let Some(replay) = proxy.take_replay_response() {
+                    let mut msg_out = Message::new();
+                    msg_out.set_terminal_response(replay);
+                    self.send(msg_out).await;
+                }
             }
             Ok(None) => {
                 // No response needed
@@ -6105,6 +6682,12 @@ async fn start_ipc(
                             compressed}) = data {
                                 stream.send(&Data::FS(ipc::FS::WriteBlock{id, file_num, data: Bytes::new(), compressed})).await?;
                                 stream.send_raw(data).await?;
+                        } else if let Data::FS(ipc::FS::ReadDelta { data }) = data {
+                            stream.send(&Data::FS(ipc::FS::ReadDelta { data: Bytes::new() })).await?;
+                            stream.send_raw(data).await?;
+                        } else if let Data::FS(ipc::FS::WriteDelta { data }) = data {
+                            stream.send(&Data::FS(ipc::FS::WriteDelta { data: Bytes::new() })).await?;
+                            stream.send_raw(data).await?;
                         } else {
                             stream.send(&data).await?;
                         }
@@ -6141,6 +6724,8 @@ pub enum AlarmAuditType {
     TerminalOsLoginConcurrency = 8,
     SessionScopeViolation = 9,
     IdWhitelist = 10,
+    AccessSchedule = 11,
+    RecordingFailure = 12,
 }
 
 pub enum FileAuditType {
@@ -6329,6 +6914,20 @@ pub fn on_printer_data(data: Vec<u8>) {
         });
 }
 
+/// Close the sessions covered by the enforced recording of incoming sessions.
+pub fn on_recording_failed(error: &str) {
+    for c in crate::server::AUTHED_CONNS.lock().unwrap().iter() {
+        if matches!(
+            c.conn_type,
+            AuthConnType::Remote | AuthConnType::ViewCamera | AuthConnType::Terminal
+        ) {
+            c.sender
+                .send(Data::RecordingFailed(error.to_owned()))
+                .ok();
+        }
+    }
+}
+
 #[cfg(windows)]
 pub struct PortableState {
     pub last_uac: bool,
@@ -6351,11 +6950,25 @@ impl Default for PortableState {
 
 impl Drop for Connection {
     fn drop(&mut self) {
+        super::recording::session_ended(self.inner.id());
+
+        if self.reverse_port_forward_approved && self.is_reverse_port_forward() {
+            if let Some(closed) = REVERSE_PORT_FORWARD_APPROVALS
+                .lock()
+                .unwrap()
+                .get_mut(&(self.session_key(), self.port_forward_address.clone()))
+            {
+                *closed = Some(Instant::now());
+            }
+        }
+
         #[cfg(not(any(target_os = "android", target_os = "ios")))]
         self.release_pressed_modifiers();
 
+        // The service may be shared with other connections attached to the same terminal.
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
         if let Some(s) = self.terminal_generic_service.as_ref() {
-            s.join();
+            terminal_service::release(&self.terminal_service_id, s, self.inner.id());
         }
 
         #[cfg(target_os = "windows")]
@@ -6601,6 +7214,7 @@ mod raii {
             key: SessionKey,
             password: Option<String>,
             tfa: Option<bool>,
+            primary_auth: ConnAuditPrimaryAuth,
         ) {
             let mut lock = SESSIONS.lock().unwrap();
             let session = lock.get_mut(&key);
@@ -6611,6 +7225,7 @@ mod raii {
                 if let Some(tfa) = tfa {
                     session.tfa = tfa;
                 }
+                session.primary_auth = primary_auth;
             } else {
                 lock.insert(
                     key,
@@ -6618,16 +7233,20 @@ mod raii {
                         random_password: password.unwrap_or_default(),
                         tfa: tfa.unwrap_or_default(),
                         last_recv_time: Arc::new(Mutex::new(Instant::now())),
+                        primary_auth,
                     },
                 );
             }
         }
 
-        pub fn set_session_2fa(key: SessionKey) {
+        pub fn set_session_2fa(key: SessionKey, primary_auth: ConnAuditPrimaryAuth) {
             let mut lock = SESSIONS.lock().unwrap();
             let session = lock.get_mut(&key);
             if let Some(session) = session {
                 session.tfa = true;
+                if primary_auth != ConnAuditPrimaryAuth::None {
+                    session.primary_auth = primary_auth;
+                }
             } else {
                 lock.insert(
                     key,
@@ -6635,6 +7254,7 @@ mod raii {
                         last_recv_time: Arc::new(Mutex::new(Instant::now())),
                         random_password: "".to_owned(),
                         tfa: true,
+                        primary_auth,
                     },
                 );
             }
@@ -6720,7 +7340,7 @@ mod raii {
 // `create_login_msg`), so the bare id is matched as well. That suffix is self-asserted and
 // unsigned, so matching only the full form would reject the honest cross-server peer while
 // an attacker just reports the bare id: it can produce false rejects but no true ones.
-fn id_whitelist_allows(id_whitelist: &[String], my_id: &str) -> bool {
+pub(super) fn id_whitelist_allows(id_whitelist: &[String], my_id: &str) -> bool {
     if id_whitelist.is_empty() {
         return true;
     }
@@ -6784,6 +7404,71 @@ fn wildcard_match(pattern: &str, text: &str) -> bool {
     pi == p.len()
 }
 
+// Whether a reverse port forward may listen on `host`, loopback only unless `any_address`.
+fn is_reverse_bind_allowed(host: &str, any_address: bool) -> bool {
+    if any_address || host.is_empty() || host.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .map_or(false, |ip| ip.is_loopback())
+}
+
+// The address to connect to for the port forward `target`, resolved to an address in the
+// allowed networks if they are set, so the check can't be bypassed by a second lookup.
+async fn allowed_port_forward_target(target: &str) -> Option<String> {
+    let allowed_networks: Vec<String> = Config::get_option(OPTION_PORT_FORWARD_ALLOWED_NETWORKS)
+        .split(',')
+        .map(|x| x.trim().to_owned())
+        .filter(|x| !x.is_empty())
+        .collect();
+    if allowed_networks.is_empty() {
+        return Some(target.to_owned());
+    }
+    let addrs = match tokio::net::lookup_host(target).await {
+        Ok(addrs) => addrs,
+        Err(e) => {
+            log::warn!("Failed to resolve port forward target {}: {}", target, e);
+            return None;
+        }
+    };
+    addrs
+        .into_iter()
+        .find(|addr| port_forward_destination_allowed(&allowed_networks, addr.ip()))
+        .map(|addr| addr.to_string())
+}
+
+async fn connect_mux_port_forward_target(target: String) -> Option<TcpStream> {
+    let target = if target.starts_with(':') {
+        format!("localhost{}", target)
+    } else {
+        target
+    };
+    let Some(addr) = allowed_port_forward_target(&target).await else {
+        log::warn!("Port forward to {} is not in the allowed networks", target);
+        return None;
+    };
+    log::info!("Multiplexed port forward to {}", target);
+    match timeout(3000, TcpStream::connect(&addr)).await {
+        Ok(Ok(socket)) => Some(socket),
+        Ok(Err(e)) => {
+            log::warn!("Port forward connect failed for {}: {}", target, e);
+            None
+        }
+        Err(e) => {
+            log::warn!("Port forward connect timed out for {}: {}", target, e);
+            None
+        }
+    }
+}
+
+fn port_forward_destination_allowed(allowed_networks: &[String], ip: IpAddr) -> bool {
+    allowed_networks
+        .iter()
+        .any(|x| IpCidr::from_str(x).map_or(false, |y| y.contains(ip)))
+}
+
 #[cfg(test)]
 mod test {
     #[allow(unused)]
@@ -6809,6 +7494,51 @@ mod test {
         assert!(!insert_pending_switch_sides_uuid(id, uuid));
     }
 
+    #[test]
+    fn port_forward_destinations_are_checked_against_networks() {
+        let allowed_networks = vec!["10.1.0.0/16".to_owned(), "192.168.1.5".to_owned()];
+        let allowed =
+            |ip: &str| port_forward_destination_allowed(&allowed_networks, ip.parse().unwrap());
+        assert!(allowed("10.1.2.3"));
+        assert!(allowed("192.168.1.5"));
+        assert!(!allowed("10.2.0.1"));
+        assert!(!allowed("192.168.1.6"));
+        assert!(!allowed("::1"));
+    }
+
+    #[test]
+    fn profiles_get_the_kind_of_the_login() {
+        use permission_profile::Auth;
+        assert_eq!(ConnAuditPrimaryAuth::None.profile_auth(), None);
+        assert_eq!(
+            ConnAuditPrimaryAuth::Click.profile_auth(),
+            Some(Auth::Click)
+        );
+        assert_eq!(
+            ConnAuditPrimaryAuth::TemporaryPassword.profile_auth(),
+            Some(Auth::Temporary)
+        );
+        assert_eq!(
+            ConnAuditPrimaryAuth::PermanentPassword.profile_auth(),
+            Some(Auth::Permanent)
+        );
+        assert_eq!(
+            ConnAuditPrimaryAuth::SwitchSides.profile_auth(),
+            Some(Auth::Switch)
+        );
+    }
+
+    #[test]
+    fn reverse_port_forwards_listen_on_loopback() {
+        for host in ["", "localhost", "127.0.0.1", "127.0.0.2", "[::1]"] {
+            assert!(is_reverse_bind_allowed(host, false), "{}", host);
+        }
+        for host in ["0.0.0.0", "192.168.1.5", "[::]", "example.com"] {
+            assert!(!is_reverse_bind_allowed(host, false), "{}", host);
+            assert!(is_reverse_bind_allowed(host, true), "{}", host);
+        }
+    }
+
     #[test]
     fn login_scope_latches_session_scope_across_login_retries() {
         let port_forward = |host: &str| {
@@ -0,0 +1,294 @@
+//! Per-peer permission profiles of incoming connections.
+//!
+//! Profiles are assigned by the controlled side in the `permission-profiles` option as a JSON
+//! array of rules, the first one matching the peer ID and the way it logged in applies, e.g.
+//!
+//! ```json
+//! [
+//!   {"profile": "admin", "ids": ["123456789"], "auth": ["permanent"]},
+//!   {"profile": "helpdesk", "ids": ["8*"], "permissions": ["keyboard", "clipboard", "audio"]},
+//!   {"profile": "view-only"}
+//! ]
+//! ```
+//!
+//! `ids` are matched like the ID whitelist and `auth` is one of `click`, `temporary`, `permanent`
+//! or `switch`, all match if empty. The built-in profiles are `view-only`, `support` (keyboard and
+//! mouse, camera, no files) and `admin`, `permissions` defines the profile of a rule instead. The
+//! permissions are `keyboard`, `clipboard`, `file`, `audio`, `restart`, `terminal`,
+//! `port_forward`, `recording`, `privacy_mode`, `block_input` and `camera`.
+//!
+//! `temporary` and `permanent` are the kind of password the peer logged in with, not a particular
+//! password. A reconnection of a recent session keeps the kind of its first login, and if it is
+//! unknown the peer is view-only.
+//!
+//! A profile narrows the permissions enabled by the options and the server, the ones not in it are
+//! denied. Without a matching rule nothing changes, an invalid option makes the peer view-only.
+//! Before the peer is authenticated, the login is only refused for what none of the ways it may
+//! still log in allows, see [`select_any`].
+
+use hbb_common::{
+    config::{keys, Config},
+    log,
+};
+use serde_derive::Deserialize;
+
+const OPTION_PERMISSION_PROFILES: &str = "permission-profiles";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    Keyboard,
+    Clipboard,
+    File,
+    Audio,
+    Restart,
+    Terminal,
+    PortForward,
+    Recording,
+    PrivacyMode,
+    BlockInput,
+    Camera,
+}
+
+impl Permission {
+    fn of_option(enable_prefix_option: &str) -> Option<Self> {
+        match enable_prefix_option {
+            keys::OPTION_ENABLE_KEYBOARD => Some(Permission::Keyboard),
+            keys::OPTION_ENABLE_CLIPBOARD => Some(Permission::Clipboard),
+            // Printing sends a file to this side.
+            keys::OPTION_ENABLE_FILE_TRANSFER | keys::OPTION_ENABLE_REMOTE_PRINTER => {
+                Some(Permission::File)
+            }
+            keys::OPTION_ENABLE_AUDIO => Some(Permission::Audio),
+            keys::OPTION_ENABLE_REMOTE_RESTART => Some(Permission::Restart),
+            keys::OPTION_ENABLE_TERMINAL => Some(Permission::Terminal),
+            keys::OPTION_ENABLE_TUNNEL => Some(Permission::PortForward),
+            keys::OPTION_ENABLE_RECORD_SESSION => Some(Permission::Recording),
+            keys::OPTION_ENABLE_PRIVACY_MODE => Some(Permission::PrivacyMode),
+            keys::OPTION_ENABLE_BLOCK_INPUT => Some(Permission::BlockInput),
+            keys::OPTION_ENABLE_CAMERA => Some(Permission::Camera),
+            _ => None,
+        }
+    }
+}
+
+/// How the peer logged in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Auth {
+    Click,
+    Temporary,
+    Permanent,
+    Switch,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct Rule {
+    profile: String,
+    #[serde(default)]
+    permissions: Option<Vec<Permission>>,
+    #[serde(default)]
+    ids: Vec<String>,
+    #[serde(default)]
+    auth: Vec<Auth>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    permissions: Vec<Permission>,
+}
+
+impl Profile {
+    /// If the permission of an `enable-*` option is in the profile, the others are not affected.
+    pub fn allows(&self, enable_prefix_option: &str) -> bool {
+        match Permission::of_option(enable_prefix_option) {
+            Some(permission) => self.permissions.contains(&permission),
+            None => true,
+        }
+    }
+}
+
+fn builtin(name: &str) -> Option<Vec<Permission>> {
+    use Permission::*;
+    match name {
+        "view-only" => Some(vec![]),
+        "support" => Some(vec![Keyboard, Clipboard, Audio, Restart, Camera]),
+        "admin" => Some(vec![
+            Keyboard,
+            Clipboard,
+            File,
+            Audio,
+            Restart,
+            Terminal,
+            PortForward,
+            Recording,
+            PrivacyMode,
+            BlockInput,
+            Camera,
+        ]),
+        _ => None,
+    }
+}
+
+fn view_only() -> Profile {
+    Profile {
+        name: "view-only".to_owned(),
+        permissions: vec![],
+    }
+}
+
+/// The profile of the peer, `None` if no rule matches.
+pub fn select(peer_id: &str, auth: Option<Auth>) -> Option<Profile> {
+    let rules = Config::get_option(OPTION_PERMISSION_PROFILES);
+    if rules.trim().is_empty() {
+        return None;
+    }
+    if auth.is_none() {
+        log::error!("Unknown login of the peer, it is view-only");
+        return Some(view_only());
+    }
+    match serde_json::from_str::<Vec<Rule>>(&rules) {
+        Ok(rules) => find(&rules, peer_id, auth),
+        Err(e) => {
+            log::error!("Invalid permission profiles, the peer is view-only: {}", e);
+            Some(view_only())
+        }
+    }
+}
+
+/// The profile of the peer before it is authenticated, with the permissions of the profiles of all
+/// the ways it may log in. `None` if no rule matches one of them.
+pub fn select_any(peer_id: &str, auths: &[Auth]) -> Option<Profile> {
+    let mut profiles = auths.iter().map(|auth| select(peer_id, Some(*auth)));
+    let first = profiles.next()??;
+    profiles.try_fold(first, |mut any, profile| {
+        for permission in profile?.permissions {
+            if !any.permissions.contains(&permission) {
+                any.permissions.push(permission);
+            }
+        }
+        Some(any)
+    })
+}
+
+fn find(rules: &[Rule], peer_id: &str, auth: Option<Auth>) -> Option<Profile> {
+    let rule = rules.iter().find(|rule| {
+        super::connection::id_whitelist_allows(&rule.ids, peer_id)
+            && (rule.auth.is_empty() || auth.is_some_and(|auth| rule.auth.contains(&auth)))
+    })?;
+    let Some(permissions) = rule.permissions.clone().or_else(|| builtin(&rule.profile)) else {
+        log::error!(
+            "Unknown permission profile {}, the peer is view-only",
+            rule.profile
+        );
+        return Some(view_only());
+    };
+    Some(Profile {
+        name: rule.profile.clone(),
+        permissions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_matching_rule_applies() {
+        let rules: Vec<Rule> = serde_json::from_str(
+            r#"[
+                {"profile": "admin", "ids": ["123456789"], "auth": ["permanent"]},
+                {"profile": "helpdesk", "ids": ["8*"], "permissions": ["keyboard", "file"]},
+                {"profile": "support", "auth": ["click", "temporary"]}
+            ]"#,
+        )
+        .unwrap();
+        let name = |id: &str, auth: Option<Auth>| find(&rules, id, auth).map(|p| p.name);
+        assert_eq!(name("123456789", Some(Auth::Permanent)).unwrap(), "admin");
+        assert_eq!(name("123456789", Some(Auth::Click)).unwrap(), "support");
+        assert_eq!(
+            name("812345678", Some(Auth::Permanent)).unwrap(),
+            "helpdesk"
+        );
+        // The server suffix of a cross-server peer is ignored.
+        assert_eq!(
+            name("123456789@example.com", Some(Auth::Permanent)).unwrap(),
+            "admin"
+        );
+        assert_eq!(name("555", Some(Auth::Permanent)), None);
+        assert_eq!(name("555", None), None);
+    }
+
+    #[test]
+    fn profiles_narrow_the_permissions() {
+        let rules: Vec<Rule> = serde_json::from_str(
+            r#"[
+                {"profile": "support", "ids": ["1"]},
+                {"profile": "helpdesk", "ids": ["2"], "permissions": ["file"]},
+                {"profile": "missing"}
+            ]"#,
+        )
+        .unwrap();
+        let support = find(&rules, "1", None).unwrap();
+        assert!(support.allows(keys::OPTION_ENABLE_KEYBOARD));
+        assert!(!support.allows(keys::OPTION_ENABLE_FILE_TRANSFER));
+        assert!(!support.allows(keys::OPTION_ENABLE_TUNNEL));
+        assert!(support.allows(keys::OPTION_ENABLE_CAMERA));
+        assert!(!support.allows(keys::OPTION_ENABLE_PRIVACY_MODE));
+        assert!(!support.allows(keys::OPTION_ENABLE_BLOCK_INPUT));
+        // Not covered by the profiles.
+        assert!(support.allows("enable-unknown"));
+        let helpdesk = find(&rules, "2", None).unwrap();
+        assert!(helpdesk.allows(keys::OPTION_ENABLE_REMOTE_PRINTER));
+        assert!(!helpdesk.allows(keys::OPTION_ENABLE_KEYBOARD));
+        assert_eq!(find(&rules, "3", None), Some(view_only()));
+        assert!(serde_json::from_str::<Vec<Rule>>(r#"[{"profile": "a", "auth": ["x"]}]"#).is_err());
+    }
+
+    struct RestoreProfiles(String);
+
+    impl Drop for RestoreProfiles {
+        fn drop(&mut self) {
+            Config::set_option(OPTION_PERMISSION_PROFILES.to_owned(), self.0.clone());
+        }
+    }
+
+    #[test]
+    fn profiles_are_selected_by_the_login() {
+        let _restore = RestoreProfiles(Config::get_option(OPTION_PERMISSION_PROFILES));
+        let name = |id: &str, auth: Option<Auth>| select(id, auth).map(|p| p.name);
+
+        Config::set_option(OPTION_PERMISSION_PROFILES.to_owned(), "".to_owned());
+        assert_eq!(name("1", None), None);
+        assert_eq!(select_any("1", &[Auth::Click]), None);
+
+        Config::set_option(
+            OPTION_PERMISSION_PROFILES.to_owned(),
+            r#"[
+                {"profile": "admin", "auth": ["permanent"]},
+                {"profile": "support", "auth": ["temporary", "switch"]},
+                {"profile": "view-only", "ids": ["1"]}
+            ]"#
+            .to_owned(),
+        );
+        assert_eq!(name("1", Some(Auth::Permanent)).unwrap(), "admin");
+        assert_eq!(name("1", Some(Auth::Temporary)).unwrap(), "support");
+        assert_eq!(name("1", Some(Auth::Switch)).unwrap(), "support");
+        assert_eq!(name("1", Some(Auth::Click)).unwrap(), "view-only");
+        assert_eq!(name("2", Some(Auth::Click)), None);
+        // A login whose kind is unknown gets nothing.
+        assert_eq!(name("2", None).unwrap(), "view-only");
+
+        // Refused before the login only if no way to log in allows it.
+        let any = select_any("1", &[Auth::Temporary, Auth::Permanent]).unwrap();
+        assert!(any.allows(keys::OPTION_ENABLE_FILE_TRANSFER));
+        let any = select_any("1", &[Auth::Click, Auth::Switch]).unwrap();
+        assert!(any.allows(keys::OPTION_ENABLE_CAMERA));
+        assert!(!any.allows(keys::OPTION_ENABLE_FILE_TRANSFER));
+        assert_eq!(select_any("2", &[Auth::Click, Auth::Permanent]), None);
+
+        Config::set_option(OPTION_PERMISSION_PROFILES.to_owned(), "{".to_owned());
+        assert_eq!(name("1", Some(Auth::Permanent)).unwrap(), "view-only");
+    }
+}
@@ -0,0 +1,173 @@
+//! Recording of incoming sessions on the controlled side.
+//!
+//! With `allow-auto-record-incoming` the sessions are recorded when possible. With
+//! `enforce-record-incoming` the recording is required: remote control and terminal sessions
+//! are refused if the recording directory is not usable, and closed with an alarm if the
+//! recording fails later. The incoming recordings are pruned by age and total size before new
+//! ones are started.
+
+mod sidecar;
+pub use sidecar::{event, search, session_ended, session_started, state_sender};
+
+use hbb_common::{
+    config::{self, Config},
+    log, ResultType,
+};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+pub const OPTION_ALLOW_AUTO_RECORD_INCOMING: &str = "allow-auto-record-incoming";
+pub const OPTION_ENFORCE_RECORD_INCOMING: &str = "enforce-record-incoming";
+/// Incoming recordings older than this are removed, no limit if empty or 0.
+pub const OPTION_RECORDING_RETENTION_DAYS: &str = "recording-retention-days";
+/// The oldest incoming recordings are removed to keep the total below this, no limit if empty
+/// or 0.
+pub const OPTION_RECORDING_MAX_SIZE_MB: &str = "recording-max-size-mb";
+
+const INCOMING_PREFIX: &str = "incoming_";
+// Files modified recently may still be written by another display or terminal.
+const IN_USE: Duration = Duration::from_secs(60);
+
+pub fn is_enforced() -> bool {
+    config::option2bool(
+        OPTION_ENFORCE_RECORD_INCOMING,
+        &Config::get_option(OPTION_ENFORCE_RECORD_INCOMING),
+    )
+}
+
+pub fn record_incoming() -> bool {
+    is_enforced()
+        || config::option2bool(
+            OPTION_ALLOW_AUTO_RECORD_INCOMING,
+            &Config::get_option(OPTION_ALLOW_AUTO_RECORD_INCOMING),
+        )
+}
+
+/// Report a recording that can't be created or written. If the recording is enforced, the
+/// sessions are closed, they must not go on unrecorded.
+pub fn failed(error: &str) {
+    log::error!("Session recording failed: {}", error);
+    if is_enforced() {
+        super::on_recording_failed(error);
+    }
+}
+
+pub fn dir() -> String {
+    #[cfg(windows)]
+    let root = crate::platform::is_root();
+    #[cfg(not(windows))]
+    let root = false;
+    crate::ui_interface::video_save_directory(root)
+}
+
+/// Create the recording directory and apply the retention limits, before a new recording.
+pub fn prepare() -> ResultType<()> {
+    let dir = PathBuf::from(dir());
+    std::fs::create_dir_all(&dir)?;
+    let max_age = option_u64(OPTION_RECORDING_RETENTION_DAYS)
+        .map(|days| Duration::from_secs(days.saturating_mul(24 * 3600)));
+    let max_size = option_u64(OPTION_RECORDING_MAX_SIZE_MB).map(|mb| mb.saturating_mul(1 << 20));
+    if max_age.is_none() && max_size.is_none() {
+        return Ok(());
+    }
+    for path in expired(incoming_files(&dir)?, SystemTime::now(), max_age, max_size) {
+        match std::fs::remove_file(&path) {
+            Ok(_) => log::info!("Removed recording {}", path.display()),
+            Err(e) => log::error!("Failed to remove recording {}: {}", path.display(), e),
+        }
+    }
+    Ok(())
+}
+
+fn option_u64(name: &str) -> Option<u64> {
+    Config::get_option(name)
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|v| *v > 0)
+}
+
+fn incoming_files(dir: &Path) -> ResultType<Vec<(PathBuf, SystemTime, u64)>> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(INCOMING_PREFIX)
+        {
+            continue;
+        }
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        if meta.is_file() {
+            files.push((entry.path(), meta.modified()?, meta.len()));
+        }
+    }
+    Ok(files)
+}
+
+/// The files to remove, older than `max_age`, then the oldest ones over `max_size` in total.
+/// Files in use are kept but count in the total.
+fn expired(
+    mut files: Vec<(PathBuf, SystemTime, u64)>,
+    now: SystemTime,
+    max_age: Option<Duration>,
+    max_size: Option<u64>,
+) -> Vec<PathBuf> {
+    let age = |modified: SystemTime| now.duration_since(modified).unwrap_or_default();
+    files.sort_by_key(|(_, modified, _)| *modified);
+    let mut total: u64 = files.iter().map(|(_, _, len)| len).sum();
+    let mut removed = vec![];
+    for (path, modified, len) in files {
+        if age(modified) < IN_USE {
+            continue;
+        }
+        let too_old = max_age.is_some_and(|max| age(modified) > max);
+        let too_big = max_size.is_some_and(|max| total > max);
+        if too_old || too_big {
+            total -= len;
+            removed.push(path);
+        }
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_recordings_are_removed_first() {
+        let now = SystemTime::now();
+        let hours = |h: u64| now - Duration::from_secs(h * 3600);
+        let files = vec![
+            (PathBuf::from("new"), hours(1), 40),
+            (PathBuf::from("old"), hours(30), 10),
+            (PathBuf::from("older"), hours(50), 10),
+            (PathBuf::from("writing"), now, 50),
+        ];
+        let day = Some(Duration::from_secs(24 * 3600));
+        assert_eq!(
+            expired(files.clone(), now, day, None),
+            vec![PathBuf::from("older"), PathBuf::from("old")]
+        );
+        assert_eq!(
+            expired(files.clone(), now, None, Some(100)),
+            vec![PathBuf::from("older")]
+        );
+        // The file being written is kept even over the quota.
+        assert_eq!(
+            expired(files.clone(), now, None, Some(10)),
+            vec![
+                PathBuf::from("older"),
+                PathBuf::from("old"),
+                PathBuf::from("new")
+            ]
+        );
+        assert!(expired(files, now, None, None).is_empty());
+    }
+}
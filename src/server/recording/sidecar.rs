@@ -0,0 +1,234 @@
+//! JSON sidecar of the incoming recordings, written next to each of them as `<recording>.json`:
+//! the recorded source, the start and the end, the peers connected meanwhile and the events of
+//! their sessions, i.e. permission changes, chat, file transfers and clipboard updates. The chat
+//! text and the transferred files are left out of the sidecars of encrypted recordings.
+
+use crate::hbbs_http::record_upload;
+use hbb_common::{
+    chrono::{self, DateTime, NaiveDate},
+    lazy_static, log, ResultType,
+};
+use scrap::record::{RecordState, ENCRYPTED_EXTENSION};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{mpsc::Sender, Mutex},
+};
+
+pub const EXTENSION: &str = ".json";
+const MAX_EVENTS: usize = 10_000;
+// Left out of the sidecars of encrypted recordings.
+const PRIVATE_FIELDS: [&str; 2] = ["text", "files"];
+
+lazy_static::lazy_static! {
+    // The peers of the authorized connections, by connection id.
+    static ref SESSIONS: Mutex<HashMap<i32, Value>> = Default::default();
+    // The sidecars of the recordings being written, by recording path.
+    static ref SIDECARS: Mutex<HashMap<String, Value>> = Default::default();
+}
+
+fn now() -> String {
+    chrono::Local::now().to_rfc3339()
+}
+
+/// A connection is authorized, `peer` describes it in the sidecars.
+pub fn session_started(conn_id: i32, peer: Value) {
+    SESSIONS.lock().unwrap().insert(conn_id, peer.clone());
+    for sidecar in SIDECARS.lock().unwrap().values_mut() {
+        if let Some(peers) = sidecar["peers"].as_array_mut() {
+            peers.push(peer.clone());
+        }
+    }
+    event(conn_id, "connect", json!({}));
+}
+
+pub fn session_ended(conn_id: i32) {
+    if SESSIONS.lock().unwrap().contains_key(&conn_id) {
+        event(conn_id, "disconnect", json!({}));
+        SESSIONS.lock().unwrap().remove(&conn_id);
+    }
+}
+
+/// Add an event of a connection to the sidecars of the recordings being written.
+pub fn event(conn_id: i32, kind: &str, data: Value) {
+    let mut sidecars = SIDECARS.lock().unwrap();
+    if sidecars.is_empty() {
+        return;
+    }
+    let Some(peer) = SESSIONS.lock().unwrap().get(&conn_id).cloned() else {
+        return;
+    };
+    let mut event = json!({
+        "time": now(),
+        "conn_id": conn_id,
+        "peer_id": peer["id"],
+        "type": kind,
+    });
+    if let (Some(event), Some(data)) = (event.as_object_mut(), data.as_object()) {
+        event.extend(data.clone());
+    }
+    for (path, sidecar) in sidecars.iter_mut() {
+        let Some(events) = sidecar["events"].as_array_mut() else {
+            continue;
+        };
+        if events.len() >= MAX_EVENTS {
+            sidecar["events_dropped"] = json!(sidecar["events_dropped"].as_u64().unwrap_or(0) + 1);
+            continue;
+        }
+        let mut event = event.clone();
+        if path.ends_with(ENCRYPTED_EXTENSION) {
+            if let Some(event) = event.as_object_mut() {
+                PRIVATE_FIELDS.iter().for_each(|f| {
+                    event.remove(*f);
+                });
+            }
+        }
+        events.push(event);
+    }
+}
+
+/// The sender of the states of a recorder, to keep its sidecar and upload it if enabled.
+/// `source` is what is recorded, e.g. `display0`.
+pub fn state_sender(source: String) -> Sender<RecordState> {
+    let upload = if record_upload::is_enable() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        record_upload::run(rx);
+        Some(tx)
+    } else {
+        None
+    };
+    let (tx, rx) = std::sync::mpsc::channel::<RecordState>();
+    std::thread::spawn(move || {
+        let mut current: Option<String> = None;
+        while let Ok(state) = rx.recv() {
+            match &state {
+                RecordState::NewFile(path) => {
+                    if let Some(path) = current.take() {
+                        finish(&path);
+                    }
+                    let peers: Vec<Value> = SESSIONS.lock().unwrap().values().cloned().collect();
+                    let sidecar = json!({
+                        "recording": Path::new(path).file_name().map(|f| f.to_string_lossy()),
+                        "source": source,
+                        "start": now(),
+                        "peers": peers,
+                        "events": [],
+                    });
+                    SIDECARS.lock().unwrap().insert(path.clone(), sidecar);
+                    current = Some(path.clone());
+                }
+                RecordState::WriteTail => {
+                    if let Some(path) = current.take() {
+                        finish(&path);
+                    }
+                }
+                RecordState::RemoveFile => {
+                    if let Some(path) = current.take() {
+                        SIDECARS.lock().unwrap().remove(&path);
+                    }
+                }
+                RecordState::NewFrame => {}
+            }
+            if let Some(upload) = &upload {
+                upload.send(state).ok();
+            }
+        }
+        if let Some(path) = current.take() {
+            finish(&path);
+        }
+    });
+    tx
+}
+
+fn finish(path: &str) {
+    let Some(mut sidecar) = SIDECARS.lock().unwrap().remove(path) else {
+        return;
+    };
+    sidecar["end"] = json!(now());
+    let file = format!("{}{}", path, EXTENSION);
+    let res = serde_json::to_vec_pretty(&sidecar)
+        .map_err(std::io::Error::from)
+        .and_then(|data| std::fs::write(&file, data));
+    if let Err(e) = res {
+        log::error!("Failed to write {}: {}", file, e);
+    }
+}
+
+/// The sidecars in `dir` of the recordings with the peer `peer_id`, started between `from` and
+/// `to` inclusive, sorted by start.
+pub fn search(
+    dir: &Path,
+    peer_id: Option<&str>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> ResultType<Vec<Value>> {
+    let mut found = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.to_string_lossy().ends_with(EXTENSION) {
+            continue;
+        }
+        let Some(sidecar) = std::fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<Value>(&data).ok())
+        else {
+            continue;
+        };
+        if matches(&sidecar, peer_id, from, to) {
+            found.push(sidecar);
+        }
+    }
+    found.sort_by(|a, b| a["start"].as_str().cmp(&b["start"].as_str()));
+    Ok(found)
+}
+
+fn matches(
+    sidecar: &Value,
+    peer_id: Option<&str>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> bool {
+    let Some(start) = sidecar["start"]
+        .as_str()
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+    else {
+        return false;
+    };
+    let date = start.date_naive();
+    if from.is_some_and(|from| date < from) || to.is_some_and(|to| date > to) {
+        return false;
+    }
+    match peer_id {
+        Some(peer_id) => sidecar["peers"]
+            .as_array()
+            .is_some_and(|peers| peers.iter().any(|p| p["id"] == peer_id)),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecars_are_matched_by_peer_and_date() {
+        let sidecar = json!({
+            "start": "2024-05-02T10:00:00+02:00",
+            "peers": [{"id": "123456789"}, {"id": "987654321"}],
+        });
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok();
+        assert!(matches(&sidecar, None, None, None));
+        assert!(matches(&sidecar, Some("987654321"), None, None));
+        assert!(!matches(&sidecar, Some("111111111"), None, None));
+        assert!(matches(
+            &sidecar,
+            Some("123456789"),
+            date("2024-05-02"),
+            date("2024-05-02")
+        ));
+        assert!(!matches(&sidecar, None, date("2024-05-03"), None));
+        assert!(!matches(&sidecar, None, None, date("2024-05-01")));
+        assert!(!matches(&json!({"peers": []}), None, None, None));
+    }
+}
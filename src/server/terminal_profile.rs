@@ -0,0 +1,157 @@
+//! Launch profiles for terminal sessions.
+//!
+//! Profiles are defined by the controlled side in the `terminal-profiles` option as a JSON array,
+//! the client can only pick one by name, e.g.
+//!
+//! ```json
+//! [
+//!   {"name": "tmux", "type": "tmux", "session": "support"},
+//!   {"name": "scope", "type": "systemd-run"},
+//!   {"name": "logs", "type": "command", "command": "/usr/bin/journalctl", "args": ["-f"]}
+//! ]
+//! ```
+
+use hbb_common::{config::Config, log};
+use portable_pty::CommandBuilder;
+use serde_derive::Deserialize;
+
+const OPTION_TERMINAL_PROFILES: &str = "terminal-profiles";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TerminalProfile {
+    pub name: String,
+    #[serde(flatten)]
+    kind: ProfileKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum ProfileKind {
+    /// Attach to the tmux session, create it if it does not exist.
+    Tmux {
+        #[serde(default)]
+        session: String,
+    },
+    /// Run the default shell in a transient systemd user scope.
+    SystemdRun,
+    /// Run an allowlisted command.
+    Command {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+impl TerminalProfile {
+    /// Program and arguments to spawn, `shell` is the default shell of the session.
+    fn program(&self, shell: &str) -> (String, Vec<String>) {
+        match &self.kind {
+            ProfileKind::Tmux { session } => {
+                let session = if session.is_empty() {
+                    &self.name
+                } else {
+                    session
+                };
+                (
+                    "tmux".to_owned(),
+                    vec![
+                        "new-session".to_owned(),
+                        "-A".to_owned(),
+                        "-s".to_owned(),
+                        session.clone(),
+                    ],
+                )
+            }
+            ProfileKind::SystemdRun => (
+                "systemd-run".to_owned(),
+                vec![
+                    "--user".to_owned(),
+                    "--scope".to_owned(),
+                    "--quiet".to_owned(),
+                    shell.to_owned(),
+                ],
+            ),
+            ProfileKind::Command { command, args } => (command.clone(), args.clone()),
+        }
+    }
+
+    pub fn command_builder(&self, shell: &str) -> CommandBuilder {
+        let (program, args) = self.program(shell);
+        let mut cmd = CommandBuilder::new(program);
+        cmd.args(args);
+        cmd
+    }
+
+    fn is_supported(&self) -> bool {
+        match self.kind {
+            ProfileKind::Tmux { .. } => cfg!(not(windows)),
+            ProfileKind::SystemdRun => cfg!(target_os = "linux"),
+            ProfileKind::Command { .. } => true,
+        }
+    }
+}
+
+fn parse_profiles(value: &str) -> Vec<TerminalProfile> {
+    if value.trim().is_empty() {
+        return vec![];
+    }
+    match serde_json::from_str::<Vec<TerminalProfile>>(value) {
+        Ok(profiles) => profiles
+            .into_iter()
+            .filter(|p| !p.name.is_empty() && p.is_supported())
+            .collect(),
+        Err(e) => {
+            log::error!("Invalid {}: {}", OPTION_TERMINAL_PROFILES, e);
+            vec![]
+        }
+    }
+}
+
+/// Profiles supported on this platform
+pub fn get_profiles() -> Vec<TerminalProfile> {
+    parse_profiles(&Config::get_option(OPTION_TERMINAL_PROFILES))
+}
+
+pub fn find_profile(name: &str) -> Option<TerminalProfile> {
+    get_profiles().into_iter().find(|p| p.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_profiles_reads_all_kinds() {
+        let profiles = parse_profiles(
+            r#"[
+                {"name": "tmux", "type": "tmux"},
+                {"name": "logs", "type": "command", "command": "/usr/bin/journalctl", "args": ["-f"]}
+            ]"#,
+        );
+        #[cfg(not(windows))]
+        assert_eq!(
+            profiles[0].program("/bin/bash"),
+            (
+                "tmux".to_owned(),
+                vec![
+                    "new-session".to_owned(),
+                    "-A".to_owned(),
+                    "-s".to_owned(),
+                    "tmux".to_owned()
+                ]
+            )
+        );
+        assert_eq!(
+            profiles.last().unwrap().program("/bin/bash"),
+            ("/usr/bin/journalctl".to_owned(), vec!["-f".to_owned()])
+        );
+    }
+
+    #[test]
+    fn parse_profiles_ignores_invalid_config() {
+        assert!(parse_profiles("").is_empty());
+        assert!(parse_profiles("not json").is_empty());
+        assert!(parse_profiles(r#"[{"name": "x", "type": "unknown"}]"#).is_empty());
+        assert!(parse_profiles(r#"[{"name": "", "type": "systemd-run"}]"#).is_empty());
+    }
+}
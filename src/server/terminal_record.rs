@@ -0,0 +1,364 @@
+//! Terminal session recording in asciicast v2 format.
+//!
+//! https://docs.asciinema.org/manual/asciicast/v2/
+
+use hbb_common::{
+    anyhow::{anyhow, Context, Result},
+    chrono,
+    config::{self, Config},
+    log,
+    sodiumoxide::crypto::box_,
+};
+use scrap::record::{self, EncryptedWriter, RecordState};
+use serde_json::{json, Value};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+    time::{Duration, Instant},
+};
+
+const OPTION_RECORD_TERMINAL_INPUT: &str = "record-terminal-input";
+const DEFAULT_MAX_IDLE: Duration = Duration::from_secs(2);
+
+enum CastFile {
+    Plain(File),
+    Encrypted(EncryptedWriter<File>),
+}
+
+impl CastFile {
+    // The name of the file is `path`, with the extension of encrypted recordings if encrypted.
+    fn create(path: &str, public_key: Option<&box_::PublicKey>) -> io::Result<(Self, String)> {
+        match public_key {
+            Some(public_key) => {
+                let path = format!("{}{}", path, record::ENCRYPTED_EXTENSION);
+                let file = record::create_encrypted(&path, public_key)?;
+                Ok((CastFile::Encrypted(file), path))
+            }
+            None => Ok((CastFile::Plain(File::create(path)?), path.to_owned())),
+        }
+    }
+}
+
+impl Write for CastFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            CastFile::Plain(f) => f.write(buf),
+            CastFile::Encrypted(f) => f.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            CastFile::Plain(f) => f.flush(),
+            CastFile::Encrypted(f) => f.flush(),
+        }
+    }
+}
+
+pub struct TerminalRecorder {
+    writer: BufWriter<CastFile>,
+    filepath: String,
+    start: Instant,
+    record_input: bool,
+    // Nothing more is written after a failure, it is reported once.
+    failed: bool,
+    output: Utf8Stream,
+    input: Utf8Stream,
+    tx: Sender<RecordState>,
+}
+
+impl TerminalRecorder {
+    /// Returns `None` if recording of incoming sessions is disabled or the file can't be created.
+    pub fn new(terminal_id: i32, rows: u16, cols: u16) -> Option<Self> {
+        if !super::recording::record_incoming() {
+            return None;
+        }
+        match Self::create(terminal_id, rows, cols) {
+            Ok(recorder) => Some(recorder),
+            Err(e) => {
+                super::recording::failed(&format!("create terminal recorder: {}", e));
+                None
+            }
+        }
+    }
+
+    fn create(terminal_id: i32, rows: u16, cols: u16) -> Result<Self> {
+        super::recording::prepare()?;
+        let dir = PathBuf::from(super::recording::dir());
+        let filename = format!(
+            "incoming_{}{}terminal{}.cast",
+            Config::get_id(),
+            chrono::Local::now().format("_%Y%m%d%H%M%S%3f_"),
+            terminal_id
+        );
+        let filepath = dir.join(filename).to_string_lossy().to_string();
+        let (file, filepath) =
+            CastFile::create(&filepath, crate::common::recording_public_key().as_ref())
+                .with_context(|| format!("create {}", filepath))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(
+            writer,
+            "{}",
+            header(rows, cols, chrono::Utc::now().timestamp())
+        )?;
+        writer.flush()?;
+        let tx = super::recording::state_sender(format!("terminal{}", terminal_id));
+        tx.send(RecordState::NewFile(filepath.clone())).ok();
+        log::info!("Terminal {} recording to {}", terminal_id, filepath);
+        Ok(Self {
+            writer,
+            filepath,
+            start: Instant::now(),
+            record_input: config::option2bool(
+                OPTION_RECORD_TERMINAL_INPUT,
+                &Config::get_option(OPTION_RECORD_TERMINAL_INPUT),
+            ),
+            failed: false,
+            output: Utf8Stream::default(),
+            input: Utf8Stream::default(),
+            tx,
+        })
+    }
+
+    pub fn write_output(&mut self, data: &[u8]) {
+        let text = self.output.decode(data);
+        if !text.is_empty() {
+            self.write_event("o", &text);
+        }
+    }
+
+    /// Input is only recorded if `record-terminal-input` is enabled, it may contain passwords.
+    pub fn write_input(&mut self, data: &[u8]) {
+        if self.record_input {
+            let text = self.input.decode(data);
+            if !text.is_empty() {
+                self.write_event("i", &text);
+            }
+        }
+    }
+
+    pub fn write_resize(&mut self, rows: u16, cols: u16) {
+        self.write_event("r", &format!("{}x{}", cols, rows));
+    }
+
+    fn write_event(&mut self, code: &str, data: &str) {
+        if self.failed {
+            return;
+        }
+        let line = event(self.start.elapsed(), code, data);
+        if let Err(e) = writeln!(self.writer, "{}", line).and_then(|_| self.writer.flush()) {
+            self.failed = true;
+            super::recording::failed(&format!("write {}: {}", self.filepath, e));
+            return;
+        }
+        // Like the video recordings, an encrypted file is uploaded when it is finished.
+        if let CastFile::Plain(_) = self.writer.get_ref() {
+            self.tx.send(RecordState::NewFrame).ok();
+        }
+    }
+}
+
+impl Drop for TerminalRecorder {
+    fn drop(&mut self) {
+        let output = self.output.flush();
+        if !output.is_empty() {
+            self.write_event("o", &output);
+        }
+        let input = self.input.flush();
+        if !input.is_empty() {
+            self.write_event("i", &input);
+        }
+        self.writer.flush().ok();
+        if let CastFile::Encrypted(w) = self.writer.get_mut() {
+            if let Err(e) = w.finish() {
+                super::recording::failed(&format!("write {}: {}", self.filepath, e));
+            }
+        }
+        self.tx.send(RecordState::WriteTail).ok();
+    }
+}
+
+/// Decodes the bytes of the PTY as UTF-8, keeping a character split across two reads
+/// until the rest of it arrives.
+#[derive(Default)]
+struct Utf8Stream {
+    pending: Vec<u8>,
+}
+
+impl Utf8Stream {
+    fn decode(&mut self, data: &[u8]) -> String {
+        self.pending.extend_from_slice(data);
+        let mut text = String::new();
+        let mut start = 0;
+        while start < self.pending.len() {
+            match std::str::from_utf8(&self.pending[start..]) {
+                Ok(s) => {
+                    text.push_str(s);
+                    start = self.pending.len();
+                }
+                Err(e) => {
+                    let valid_end = start + e.valid_up_to();
+                    text.push_str(&String::from_utf8_lossy(&self.pending[start..valid_end]));
+                    match e.error_len() {
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            start = valid_end + len;
+                        }
+                        // Incomplete character at the end, wait for the next read.
+                        None => {
+                            start = valid_end;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.pending.drain(..start);
+        text
+    }
+
+    fn flush(&mut self) -> String {
+        let text = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending.clear();
+        text
+    }
+}
+
+fn header(rows: u16, cols: u16, timestamp: i64) -> Value {
+    json!({
+        "version": 2,
+        "width": cols,
+        "height": rows,
+        "timestamp": timestamp,
+        "env": { "TERM": "xterm-256color" },
+    })
+}
+
+fn event(time: Duration, code: &str, data: &str) -> Value {
+    // Millisecond precision is enough, and keeps the file small.
+    let time = (time.as_millis() as f64) / 1000.0;
+    json!([time, code, data])
+}
+
+fn parse_event(line: &str) -> Option<(f64, String, String)> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    let array = value.as_array()?;
+    match array.as_slice() {
+        [time, code, data] => Some((
+            time.as_f64()?,
+            code.as_str()?.to_owned(),
+            data.as_str()?.to_owned(),
+        )),
+        _ => None,
+    }
+}
+
+/// Replay a recording to stdout. Pauses are divided by `speed` and capped by the
+/// `idle_time_limit` of the header, or 2 seconds if the header has none.
+pub fn replay(path: &Path, speed: f64) -> Result<()> {
+    if !speed.is_finite() || speed <= 0.0 {
+        return Err(anyhow!("Invalid speed: {}", speed));
+    }
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header: Value =
+        serde_json::from_str(&lines.next().ok_or_else(|| anyhow!("Empty recording"))??)?;
+    if header["version"].as_i64() != Some(2) {
+        return Err(anyhow!(
+            "Unsupported asciicast version: {}",
+            header["version"]
+        ));
+    }
+    let max_idle = header["idle_time_limit"]
+        .as_f64()
+        .map(Duration::from_secs_f64)
+        .unwrap_or(DEFAULT_MAX_IDLE);
+    let mut stdout = std::io::stdout();
+    let mut last = 0.0;
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((time, code, data)) = parse_event(&line) else {
+            return Err(anyhow!("Invalid event: {}", line));
+        };
+        if code != "o" {
+            continue;
+        }
+        let delay = Duration::from_secs_f64(((time - last) / speed).max(0.0));
+        std::thread::sleep(delay.min(max_idle));
+        last = time;
+        stdout.write_all(data.as_bytes())?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_is_asciicast_v2() {
+        let header = header(24, 80, 1700000000);
+        assert_eq!(header["version"], 2);
+        assert_eq!(header["width"], 80);
+        assert_eq!(header["height"], 24);
+    }
+
+    #[test]
+    fn event_round_trips_control_characters() {
+        let data = "\x1b[1mbold\x1b[0m\r\n\"quoted\"";
+        let line = event(Duration::from_millis(1500), "o", data).to_string();
+        assert_eq!(
+            parse_event(&line),
+            Some((1.5, "o".to_owned(), data.to_owned()))
+        );
+        assert_eq!(
+            parse_event(&event(Duration::ZERO, "r", "80x24").to_string()),
+            Some((0.0, "r".to_owned(), "80x24".to_owned()))
+        );
+        assert_eq!(parse_event("{\"version\": 2}"), None);
+    }
+
+    #[test]
+    fn utf8_stream_keeps_split_characters() {
+        let mut stream = Utf8Stream::default();
+        let bytes = "a\u{e9}\u{1f600}b".as_bytes();
+        assert_eq!(stream.decode(&bytes[..2]), "a");
+        assert_eq!(stream.decode(&bytes[2..5]), "\u{e9}");
+        assert_eq!(stream.decode(&bytes[5..]), "\u{1f600}b");
+        assert_eq!(stream.decode(b"x\xffy\xe2\x82"), "x\u{fffd}y");
+        assert_eq!(stream.flush(), "\u{fffd}");
+        assert_eq!(stream.flush(), "");
+    }
+
+    #[test]
+    fn encrypted_recordings_decrypt_to_asciicast() {
+        let (pk, sk) = box_::gen_keypair();
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("terminal_record_test_{}.cast", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let (file, encrypted) = CastFile::create(&path, Some(&pk)).unwrap();
+        assert_eq!(
+            encrypted,
+            format!("{}{}", path, record::ENCRYPTED_EXTENSION)
+        );
+        let mut writer = BufWriter::new(file);
+        let line = event(Duration::from_millis(10), "o", "secret");
+        writeln!(writer, "{}", line).unwrap();
+        writer.flush().unwrap();
+        let content = std::fs::read(&encrypted).unwrap();
+        assert!(!content.windows(6).any(|x| x == b"secret"));
+        drop(writer);
+        assert!(record::decrypt(Path::new(&encrypted), Path::new(&path), &sk).unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{}\n", line)
+        );
+        std::fs::remove_file(&encrypted).ok();
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -1,3 +1,5 @@
+use super::terminal_profile;
+use super::terminal_record::TerminalRecorder;
 use super::*;
 use hbb_common::{
     anyhow::{anyhow, Context, Result},
@@ -99,6 +101,8 @@ pub struct ServiceMetadata {
     pub created_at: Instant,
     pub terminal_count: usize,
     pub is_persistent: bool,
+    /// Launch profiles that can be selected in `OpenTerminal`
+    pub profiles: Vec<String>,
 }
 
 /// Generate a new persistent service ID
@@ -158,11 +162,17 @@ pub fn is_service_specified_user(service_id: &str) -> Option<bool> {
     get_service(service_id).map(|s| s.lock().unwrap().is_specified_user)
 }
 
+/// The user the service was created for, only the same user may attach to it.
+pub fn service_owner(service_id: &str) -> Option<String> {
+    get_service(service_id).map(|s| s.lock().unwrap().owner.clone())
+}
+
 /// Get or create a persistent terminal service
 fn get_or_create_service(
     service_id: String,
     is_persistent: bool,
     is_specified_user: bool,
+    owner: &str,
 ) -> Result<Arc<Mutex<PersistentTerminalService>>> {
     let mut services = TERMINAL_SERVICES.lock().unwrap();
 
@@ -186,6 +196,7 @@ fn get_or_create_service(
                 service_id.clone(),
                 is_persistent,
                 is_specified_user,
+                owner.to_owned(),
             )))
         })
         .clone();
@@ -193,7 +204,13 @@ fn get_or_create_service(
     // Ensure cleanup task is running
     ensure_cleanup_task();
 
-    service.lock().unwrap().reset_status(is_persistent);
+    {
+        // A connection attaching to a shared service must not interrupt the others.
+        let mut service = service.lock().unwrap();
+        if !service.is_shared() {
+            service.reset_status(is_persistent);
+        }
+    }
 
     Ok(service)
 }
@@ -214,6 +231,10 @@ fn remove_service(service_id: &str) {
 
 /// List all active terminal services
 pub fn list_services() -> Vec<ServiceMetadata> {
+    let profiles: Vec<String> = terminal_profile::get_profiles()
+        .into_iter()
+        .map(|p| p.name)
+        .collect();
     let services = TERMINAL_SERVICES.lock().unwrap();
     services
         .iter()
@@ -223,6 +244,7 @@ pub fn list_services() -> Vec<ServiceMetadata> {
                 created_at: svc.created_at,
                 terminal_count: svc.sessions.len(),
                 is_persistent: svc.is_persistent,
+                profiles: profiles.clone(),
             })
         })
         .collect()
@@ -385,23 +407,110 @@ pub fn get_service_name(source: VideoSource, idx: usize) -> String {
     format!("{}{}", source.service_name_prefix(), idx)
 }
 
+/// Attach a connection to the terminal service.
+///
+/// If another connection is already attached, the running service is shared and its output
+/// fans out to all subscribers. The returned flag is true in that case. The attachment is
+/// made under the lock of the terminal service, so it doesn't race [`release`].
 pub fn new(
     service_id: String,
     is_persistent: bool,
     user_token: Option<UserToken>,
-) -> GenericService {
+    owner: &str,
+    conn: ConnInner,
+) -> Result<(GenericService, bool)> {
     // Create the service with initial persistence setting
-    allow_err!(get_or_create_service(
-        service_id.clone(),
-        is_persistent,
-        user_token.is_some()
-    ));
+    let service =
+        get_or_create_service(service_id.clone(), is_persistent, user_token.is_some(), owner)?;
+    let mut service = service.lock().unwrap();
+    if service.owner != owner {
+        return Err(anyhow!(
+            "Terminal service {} belongs to another user",
+            service_id
+        ));
+    }
+    let conn_id = conn.id();
+    let shared = service.generic_service.clone().filter(|sp| sp.ok());
+    if shared.is_none() {
+        service.attachments.clear();
+    }
+    // Observers start read-only, unless nobody else is able to type.
+    let can_input = !service.attachments.values().any(|a| a.can_input);
+    service
+        .attachments
+        .insert(conn_id, TerminalAttachment { can_input });
+    if let Some(sp) = shared {
+        sp.on_subscribe(conn);
+        log::info!(
+            "Connection {} attached to shared terminal service {}, input: {}",
+            conn_id,
+            service_id,
+            can_input
+        );
+        return Ok((sp, true));
+    }
     let svc = TerminalService {
         sp: GenericService::new(service_id.clone(), false),
         user_token,
     };
-    GenericService::run(&svc.clone(), move |sp| run(sp, service_id.clone()));
-    svc.sp
+    let sp = svc.sp.clone();
+    sp.on_subscribe(conn);
+    GenericService::run(&svc, move |sp| run(sp, service_id.clone()));
+    service.generic_service = Some(sp.clone());
+    Ok((sp, false))
+}
+
+/// Detach a connection, the service keeps running for the other attachments and is stopped
+/// with the last one.
+pub fn release(service_id: &str, sp: &GenericService, conn_id: i32) {
+    let last = match get_service(service_id) {
+        Some(service) => {
+            let mut service = service.lock().unwrap();
+            sp.on_unsubscribe(conn_id);
+            service.attachments.remove(&conn_id);
+            let last = !sp.has_subscribes();
+            if last {
+                // The next connection starts a new one instead of attaching to this one.
+                service.generic_service = None;
+            }
+            last
+        }
+        None => {
+            sp.on_unsubscribe(conn_id);
+            !sp.has_subscribes()
+        }
+    };
+    // Outside the lock, the thread of the service locks it to read the outputs.
+    if last {
+        sp.join();
+    }
+}
+
+/// Whether the connection may send input, resize or close terminals.
+pub fn can_input(service_id: &str, conn_id: i32) -> bool {
+    get_service(service_id).map_or(false, |service| {
+        service
+            .lock()
+            .unwrap()
+            .attachments
+            .get(&conn_id)
+            .map_or(false, |a| a.can_input)
+    })
+}
+
+/// Grant or revoke input of an attachment, used by the connection manager.
+pub fn set_can_input(service_id: &str, conn_id: i32, can_input: bool) {
+    if let Some(service) = get_service(service_id) {
+        if let Some(attachment) = service.lock().unwrap().attachments.get_mut(&conn_id) {
+            attachment.can_input = can_input;
+        }
+    }
+}
+
+pub fn has_terminal(service_id: &str, terminal_id: i32) -> bool {
+    get_service(service_id).map_or(false, |service| {
+        service.lock().unwrap().sessions.contains_key(&terminal_id)
+    })
 }
 
 fn run(sp: TerminalService, service_id: String) -> ResultType<()> {
@@ -736,6 +845,8 @@ pub struct TerminalSession {
     closed_message_sent: bool,
     // Session state machine for reconnection handling
     state: SessionState,
+    // Recording of the session, if auto recording of incoming sessions is enabled
+    recorder: Option<TerminalRecorder>,
     // Helper mode: PTY is managed by helper process, communication via message protocol
     #[cfg(target_os = "windows")]
     is_helper_mode: bool,
@@ -763,6 +874,7 @@ impl TerminalSession {
             cols,
             closed_message_sent: false,
             state: SessionState::Closed,
+            recorder: TerminalRecorder::new(terminal_id, rows, cols),
             #[cfg(target_os = "windows")]
             is_helper_mode: false,
             #[cfg(target_os = "windows")]
@@ -880,6 +992,11 @@ impl Drop for TerminalSession {
     }
 }
 
+/// A connection attached to a terminal service
+struct TerminalAttachment {
+    can_input: bool,
+}
+
 /// Persistent terminal service that can survive connection drops
 pub struct PersistentTerminalService {
     service_id: String,
@@ -889,10 +1006,21 @@ pub struct PersistentTerminalService {
     pub is_persistent: bool,
     needs_session_sync: bool,
     is_specified_user: bool,
+    // The user the service was created for, see `service_owner()`
+    owner: String,
+    // Connections attached to this service, keyed by connection id
+    attachments: HashMap<i32, TerminalAttachment>,
+    // The service publishing the output to all attached connections
+    generic_service: Option<GenericService>,
 }
 
 impl PersistentTerminalService {
-    pub fn new(service_id: String, is_persistent: bool, is_specified_user: bool) -> Self {
+    pub fn new(
+        service_id: String,
+        is_persistent: bool,
+        is_specified_user: bool,
+        owner: String,
+    ) -> Self {
         Self {
             service_id,
             sessions: HashMap::new(),
@@ -901,9 +1029,17 @@ impl PersistentTerminalService {
             is_persistent,
             needs_session_sync: false,
             is_specified_user,
+            owner,
+            attachments: HashMap::new(),
+            generic_service: None,
         }
     }
 
+    /// Whether other connections are attached and receiving the output
+    fn is_shared(&self) -> bool {
+        self.generic_service.as_ref().map_or(false, |sp| sp.ok())
+    }
+
     fn update_activity(&mut self) {
         self.last_activity = Instant::now();
     }
@@ -959,6 +1095,8 @@ impl PersistentTerminalService {
 pub struct TerminalServiceProxy {
     service_id: String,
     is_persistent: bool,
+    // Terminal attached as shared by `handle_open`, its replay is sent to this connection only
+    shared_replay: Option<i32>,
     #[cfg(target_os = "windows")]
     user_token: Option<UserToken>,
 }
@@ -988,6 +1126,7 @@ impl TerminalServiceProxy {
         TerminalServiceProxy {
             service_id,
             is_persistent,
+            shared_replay: None,
             #[cfg(target_os = "windows")]
             user_token: _user_token,
         }
@@ -997,6 +1136,19 @@ impl TerminalServiceProxy {
         &self.service_id
     }
 
+    /// Recent output of the terminal opened by the last action, if it was attached as shared.
+    pub fn take_replay_response(&mut self) -> Option<TerminalResponse> {
+        let terminal_id = self.shared_replay.take()?;
+        let buffer = get_service(&self.service_id)?
+            .lock()
+            .unwrap()
+            .get_terminal_buffer(terminal_id, DEFAULT_RECONNECT_BUFFER_BYTES)?;
+        if buffer.is_empty() {
+            return None;
+        }
+        Some(Self::create_terminal_data_response(terminal_id, buffer))
+    }
+
     pub fn handle_action(&mut self, action: &TerminalAction) -> Result<Option<TerminalResponse>> {
         let service = match get_service(&self.service_id) {
             Some(s) => s,
@@ -1039,7 +1191,7 @@ impl TerminalServiceProxy {
     }
 
     fn handle_open(
-        &self,
+        &mut self,
         service: &mut PersistentTerminalService,
         open: &OpenTerminal,
     ) -> Result<Option<TerminalResponse>> {
@@ -1075,6 +1227,23 @@ impl TerminalServiceProxy {
         if let Some(session_arc) = service.sessions.get(&open.terminal_id) {
             // Reconnect to existing terminal
             let mut session = session_arc.lock().unwrap();
+            // The terminal is active for another attachment. Keep its state, the replay is
+            // sent to the new attachment only, see `take_replay_response()`.
+            if matches!(session.state, SessionState::Active { .. }) && service.attachments.len() > 1
+            {
+                let mut opened = TerminalOpened::new();
+                opened.terminal_id = open.terminal_id;
+                opened.success = true;
+                opened.message = "Attached to shared terminal".to_string();
+                opened.pid = session.pid;
+                opened.service_id = self.service_id.clone();
+                opened.replay_terminal_output = !session.output_buffer.lines.is_empty();
+                if opened.replay_terminal_output {
+                    self.shared_replay = Some(open.terminal_id);
+                }
+                response.set_opened(opened);
+                return Ok(Some(response));
+            }
             // Directly enter Active state with pending replay for immediate streaming.
             // The replay combines output_buffer history and the channel backlog that was
             // already pending at reconnect time so the client can suppress stale xterm
@@ -1143,12 +1312,24 @@ impl TerminalServiceProxy {
             return Ok(Some(response));
         }
 
+        let profile = if open.profile.is_empty() {
+            None
+        } else {
+            Some(
+                terminal_profile::find_profile(&open.profile)
+                    .ok_or_else(|| anyhow!("Unknown terminal profile: {}", open.profile))?,
+            )
+        };
+
         // Windows with user_token: use helper process to run shell as the logged-in user
         // This solves the ConPTY + CreateProcessAsUserW incompatibility issue where
         // vim, Claude Code, and other TUI applications hang when ConPTY is created
         // by SYSTEM service but shell runs as user via CreateProcessAsUserW.
         #[cfg(target_os = "windows")]
         if self.user_token.is_some() {
+            if profile.is_some() {
+                return Err(anyhow!("Terminal profiles are not supported for this user"));
+            }
             return self.handle_open_with_helper(service, open);
         }
 
@@ -1160,6 +1341,9 @@ impl TerminalServiceProxy {
         );
         let mut session =
             TerminalSession::new(open.terminal_id, open.rows as u16, open.cols as u16);
+        if let Some(profile) = profile.as_ref() {
+            session.title = profile.name.clone();
+        }
 
         let pty_size = PtySize {
             rows: open.rows as u16,
@@ -1177,10 +1361,18 @@ impl TerminalServiceProxy {
         log::debug!("Using shell: {}", shell);
 
         #[allow(unused_mut)]
-        let mut cmd = CommandBuilder::new(&shell);
+        let mut cmd = match profile.as_ref() {
+            Some(profile) => {
+                log::debug!("Using terminal profile: {}", profile.name);
+                profile.command_builder(&shell)
+            }
+            None => CommandBuilder::new(&shell),
+        };
 
         #[cfg(target_os = "windows")]
-        configure_utf8_shell_command(&shell, &mut cmd);
+        if profile.is_none() {
+            configure_utf8_shell_command(&shell, &mut cmd);
+        }
 
         // macOS-specific terminal configuration
         // 1. Use login shell (-l) to load user's shell profile (~/.zprofile, ~/.bash_profile)
@@ -1192,8 +1384,10 @@ impl TerminalServiceProxy {
         #[cfg(target_os = "macos")]
         {
             // Start as login shell to load user environment (PATH, etc.)
-            cmd.arg("-l");
-            log::debug!("Added -l flag for macOS login shell");
+            if profile.is_none() {
+                cmd.arg("-l");
+                log::debug!("Added -l flag for macOS login shell");
+            }
 
             let term = if std::path::Path::new("/usr/share/terminfo/78/xterm-256color").exists() {
                 "xterm-256color"
@@ -1602,8 +1796,14 @@ impl TerminalServiceProxy {
         if let Some(session_arc) = session {
             let mut session = session_arc.lock().unwrap();
             session.update_activity();
-            session.rows = resize.rows as u16;
-            session.cols = resize.cols as u16;
+            let (rows, cols) = (resize.rows as u16, resize.cols as u16);
+            if (rows, cols) != (session.rows, session.cols) {
+                if let Some(recorder) = session.recorder.as_mut() {
+                    recorder.write_resize(rows, cols);
+                }
+            }
+            session.rows = rows;
+            session.cols = cols;
 
             // Note: we do NOT clear the sigwinch phase here. The server-side two-phase
             // SIGWINCH mechanism in read_outputs() is self-contained (temp resize → restore
@@ -1663,6 +1863,9 @@ impl TerminalServiceProxy {
             let input = {
                 let mut session = session_arc.lock().unwrap();
                 session.update_activity();
+                if let Some(recorder) = session.recorder.as_mut() {
+                    recorder.write_input(&data.data);
+                }
                 if let Some(input_tx) = session.input_tx.clone() {
                     // Encode data for helper mode or send raw for direct PTY mode
                     #[cfg(target_os = "windows")]
@@ -1910,6 +2113,9 @@ impl TerminalServiceProxy {
                 // Update buffer (always buffer for reconnection support)
                 for data in &received_data {
                     session.output_buffer.append(data);
+                    if let Some(recorder) = session.recorder.as_mut() {
+                        recorder.write_output(data);
+                    }
                 }
 
                 // Skip sending responses if session is not Active.
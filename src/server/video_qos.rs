@@ -1,5 +1,5 @@
 use super::*;
-use scrap::codec::{Quality, BR_BALANCED, BR_BEST, BR_SPEED};
+use scrap::codec::{Quality, BR_BALANCED, BR_BEST, BR_SPEED, SCREEN_CONTENT_FLAG};
 use std::{
     collections::VecDeque,
     time::{Duration, Instant},
@@ -99,6 +99,15 @@ struct UserData {
 struct DisplayData {
     send_counter: usize, // Number of times encode during period
     support_changing_quality: bool,
+    damage: DamageStats,
+}
+
+// Changed regions reported by the capturer, e.g. XDamage on X11
+#[derive(Default, Debug, Clone, Copy)]
+pub struct DamageStats {
+    pub frames: u64,       // Frames captured with their changed regions
+    pub unchanged: u64,    // Captures skipped because nothing changed
+    pub changed_area: f32, // Average changed share of the display in the captured frames, 0 to 1
 }
 
 // Main QoS controller structure
@@ -224,8 +233,12 @@ impl VideoQoS {
             } else if q == ImageQuality::Best.value() {
                 Quality::Best
             } else {
-                let b = ((q >> 8 & 0xFFF) * 2) as f32 / 100.0;
-                Quality::Custom(b.clamp(BR_MIN, BR_MAX))
+                let b = (((q >> 8 & 0xFFF) * 2) as f32 / 100.0).clamp(BR_MIN, BR_MAX);
+                if q & SCREEN_CONTENT_FLAG != 0 {
+                    Quality::ScreenContent(b)
+                } else {
+                    Quality::Custom(b)
+                }
             }
         };
 
@@ -342,6 +355,15 @@ impl VideoQoS {
             }
         }
     }
+
+    // Highest network delay of the video users, file transfers share the uplink with them
+    pub fn max_user_delay(&self) -> Option<u32> {
+        self.users
+            .values()
+            .filter(|user| !user.delay.delay_history.is_empty())
+            .map(|user| user.delay.avg_delay())
+            .max()
+    }
 }
 
 // Common adjust functions
@@ -377,6 +399,25 @@ impl VideoQoS {
         }
     }
 
+    // `changed_area` is None if the capture was skipped because nothing changed
+    pub fn update_damage(&mut self, video_service_name: &str, changed_area: Option<f32>) {
+        if let Some(display) = self.displays.get_mut(video_service_name) {
+            let d = &mut display.damage;
+            match changed_area {
+                Some(area) => {
+                    d.changed_area =
+                        (d.changed_area * d.frames as f32 + area) / (d.frames + 1) as f32;
+                    d.frames += 1;
+                }
+                None => d.unchanged += 1,
+            }
+        }
+    }
+
+    pub fn damage_stats(&self, video_service_name: &str) -> Option<DamageStats> {
+        self.displays.get(video_service_name).map(|d| d.damage)
+    }
+
     #[inline]
     fn highest_fps(&self) -> u32 {
         let user_fps = |u: &UserData| {
@@ -444,7 +485,7 @@ impl VideoQoS {
 
         // Set minimum ratio based on quality mode
         let min = match target_quality {
-            Quality::Best => {
+            Quality::Best | Quality::ScreenContent(_) => {
                 // For Best quality, ensure minimum 1Mbps for high resolution
                 let mut min = BR_BEST / 2.5;
                 if let Some(ratio_1mbps) = ratio_1mbps {
@@ -32,7 +32,6 @@ use crate::{
 };
 use hbb_common::{
     anyhow::anyhow,
-    config,
     tokio::sync::{
         mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
         Mutex as TokioMutex,
@@ -40,6 +39,8 @@ use hbb_common::{
 };
 #[cfg(feature = "hwcodec")]
 use scrap::hwcodec::{HwRamEncoder, HwRamEncoderConfig};
+#[cfg(feature = "openh264")]
+use scrap::openh264codec::OpenH264EncoderConfig;
 #[cfg(feature = "vram")]
 use scrap::vram::{VRamEncoder, VRamEncoderConfig};
 #[cfg(not(windows))]
@@ -575,16 +576,15 @@ fn run(vs: VideoService) -> ResultType<()> {
     let mut video_qos = VIDEO_QOS.lock().unwrap();
     let mut spf = video_qos.spf();
     let mut quality = video_qos.ratio();
-    let record_incoming = config::option2bool(
-        "allow-auto-record-incoming",
-        &Config::get_option("allow-auto-record-incoming"),
-    );
+    let screen_content = video_qos.latest_quality().is_screen_content();
+    let record_incoming = super::recording::record_incoming();
     let client_record = video_qos.record();
     drop(video_qos);
     let (mut encoder, encoder_cfg, codec_format, use_i444, recorder) = match setup_encoder(
         &c,
         sp.name(),
         quality,
+        screen_content,
         client_record,
         record_incoming,
         last_portable_service_running,
@@ -600,11 +600,13 @@ fn run(vs: VideoService) -> ResultType<()> {
                 quality,
                 codec: VpxVideoCodecId::VP9,
                 keyframe_interval: None,
+                screen_content,
             }));
             setup_encoder(
                 &c,
                 sp.name(),
                 quality,
+                screen_content,
                 client_record,
                 record_incoming,
                 last_portable_service_running,
@@ -655,6 +657,8 @@ fn run(vs: VideoService) -> ResultType<()> {
     let capture_width = c.width;
     let capture_height = c.height;
     let (mut second_instant, mut send_counter) = (Instant::now(), 0);
+    // The capturer reports the changed regions, e.g. XDamage on X11.
+    let mut damage_tracking = false;
 
     while sp.ok() {
         #[cfg(windows)]
@@ -664,6 +668,7 @@ fn run(vs: VideoService) -> ResultType<()> {
             &mut quality,
             &mut spf,
             client_record,
+            screen_content,
             &mut send_counter,
             &mut second_instant,
             &sp.name(),
@@ -724,6 +729,15 @@ fn run(vs: VideoService) -> ResultType<()> {
         let res = match c.frame(spf) {
             Ok(frame) => {
                 repeat_encode_counter = 0;
+                if let Some(rects) = frame.dirty_rects() {
+                    let area: usize = rects.iter().map(|r| r.w * r.h).sum();
+                    let ratio = area as f32 / (capture_width * capture_height).max(1) as f32;
+                    VIDEO_QOS
+                        .lock()
+                        .unwrap()
+                        .update_damage(&sp.name(), Some(ratio.min(1.0)));
+                    damage_tracking = true;
+                }
                 if frame.valid() {
                     let screenshot_key = (vs.source, display_idx);
                     let screenshot = SCREENSHOTS.lock().unwrap().remove(&screenshot_key);
@@ -803,6 +817,9 @@ fn run(vs: VideoService) -> ResultType<()> {
 
         match res {
             Err(ref e) if e.kind() == WouldBlock => {
+                if damage_tracking {
+                    VIDEO_QOS.lock().unwrap().update_damage(&sp.name(), None);
+                }
                 #[cfg(windows)]
                 if try_gdi > 0 && !c.is_gdi() {
                     if try_gdi > 3 {
@@ -925,7 +942,18 @@ impl Drop for Raii {
         }
         #[cfg(feature = "vram")]
         Encoder::update(scrap::codec::EncodingUpdate::Check);
-        VIDEO_QOS.lock().unwrap().remove_display(&self.name);
+        let mut video_qos = VIDEO_QOS.lock().unwrap();
+        if let Some(stats) = video_qos.damage_stats(&self.name).filter(|s| s.frames > 0) {
+            log::info!(
+                "{} damage: {} frames, {} unchanged, {:.1}% changed on average",
+                self.name,
+                stats.frames,
+                stats.unchanged,
+                stats.changed_area * 100.0
+            );
+        }
+        video_qos.remove_display(&self.name);
+        drop(video_qos);
         DISPLAY_CONN_IDS.lock().unwrap().remove(&self.display_idx);
     }
 }
@@ -934,6 +962,7 @@ fn setup_encoder(
     c: &CapturerInfo,
     name: String,
     quality: f32,
+    screen_content: bool,
     client_record: bool,
     record_incoming: bool,
     last_portable_service_running: bool,
@@ -950,6 +979,7 @@ fn setup_encoder(
         &c,
         name.to_string(),
         quality,
+        screen_content,
         client_record || record_incoming,
         last_portable_service_running,
         source,
@@ -966,6 +996,7 @@ fn get_encoder_config(
     c: &CapturerInfo,
     _name: String,
     quality: f32,
+    screen_content: bool,
     record: bool,
     _portable_service: bool,
     _source: VideoSource,
@@ -1004,12 +1035,22 @@ fn get_encoder_config(
                     keyframe_interval,
                 });
             }
+            #[cfg(feature = "openh264")]
+            if negotiated_codec == CodecFormat::H264 {
+                return EncoderCfg::OpenH264(OpenH264EncoderConfig {
+                    width: c.width,
+                    height: c.height,
+                    quality,
+                    keyframe_interval,
+                });
+            }
             EncoderCfg::VPX(VpxEncoderConfig {
                 width: c.width as _,
                 height: c.height as _,
                 quality,
                 codec: VpxVideoCodecId::VP9,
                 keyframe_interval,
+                screen_content,
             })
         }
         format @ (CodecFormat::VP8 | CodecFormat::VP9) => EncoderCfg::VPX(VpxEncoderConfig {
@@ -1022,12 +1063,14 @@ fn get_encoder_config(
                 VpxVideoCodecId::VP9
             },
             keyframe_interval,
+            screen_content,
         }),
         CodecFormat::AV1 => EncoderCfg::AOM(AomEncoderConfig {
             width: c.width as _,
             height: c.height as _,
             quality,
             keyframe_interval,
+            screen_content,
         }),
         _ => EncoderCfg::VPX(VpxEncoderConfig {
             width: c.width as _,
@@ -1035,6 +1078,7 @@ fn get_encoder_config(
             quality,
             codec: VpxVideoCodecId::VP9,
             keyframe_interval,
+            screen_content,
         }),
     }
 }
@@ -1044,29 +1088,32 @@ fn get_recorder(
     display_idx: usize,
     camera: bool,
 ) -> Arc<Mutex<Option<Recorder>>> {
-    #[cfg(windows)]
-    let root = crate::platform::is_root();
-    #[cfg(not(windows))]
-    let root = false;
     let recorder = if record_incoming {
-        use crate::hbbs_http::record_upload;
+        if let Err(e) = super::recording::prepare() {
+            super::recording::failed(&format!("prepare the recording directory: {}", e));
+        }
 
-        let tx = if record_upload::is_enable() {
-            let (tx, rx) = std::sync::mpsc::channel();
-            record_upload::run(rx);
-            Some(tx)
-        } else {
-            None
-        };
-        Recorder::new(RecorderContext {
+        let source = format!(
+            "{}{}",
+            if camera { "camera" } else { "display" },
+            display_idx
+        );
+        let tx = Some(super::recording::state_sender(source));
+        match Recorder::new(RecorderContext {
             server: true,
             id: Config::get_id(),
-            dir: crate::ui_interface::video_save_directory(root),
+            dir: super::recording::dir(),
             display_idx,
             camera,
             tx,
-        })
-        .map_or(Default::default(), |r| Arc::new(Mutex::new(Some(r))))
+            public_key: crate::common::recording_public_key(),
+        }) {
+            Ok(r) => Arc::new(Mutex::new(Some(r))),
+            Err(e) => {
+                super::recording::failed(&e.to_string());
+                Default::default()
+            }
+        }
     } else {
         Default::default()
     };
@@ -1164,11 +1211,17 @@ fn handle_one_frame(
             vf.display = display as _;
             let mut msg = Message::new();
             msg.set_video_frame(vf);
-            recorder
-                .lock()
-                .unwrap()
-                .as_mut()
-                .map(|r| r.write_message(&msg, width, height));
+            // An enforced recording must not be skipped, the frame is not sent either.
+            let res = match recorder.lock().unwrap().as_mut() {
+                Some(r) => r.write_message(&msg, width, height),
+                None => Ok(()),
+            };
+            if let Err(e) = res {
+                if super::recording::is_enforced() {
+                    super::recording::failed(&e.to_string());
+                    bail!("Session recording failed: {}", e);
+                }
+            }
             send_conn_ids = sp.send_video_frame(msg);
         }
         Err(e) => {
@@ -1320,6 +1373,7 @@ fn check_qos(
     ratio: &mut f32,
     spf: &mut Duration,
     client_record: bool,
+    screen_content: bool,
     send_counter: &mut usize,
     second_instant: &mut Instant,
     name: &str,
@@ -1343,6 +1397,10 @@ fn check_qos(
         log::info!("switch due to record changed");
         bail!("SWITCH");
     }
+    if screen_content != video_qos.latest_quality().is_screen_content() {
+        log::info!("switch due to screen content changed");
+        bail!("SWITCH");
+    }
     if second_instant.elapsed() > Duration::from_secs(1) {
         *second_instant = Instant::now();
         video_qos.update_display_data(&name, *send_counter);
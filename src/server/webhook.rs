@@ -0,0 +1,287 @@
+//! Outgoing webhooks for the connection lifecycle on the controlled side.
+//!
+//! The events selected by `webhook-events` are posted as JSON to `webhook-url`. With a
+//! `webhook-secret` they are signed in the `X-RustDesk-Signature` header as
+//! `t=<unix time>,v1=<hex HMAC-SHA256 of "<t>.<body>">`, so receivers can check the sender and
+//! reject replays. The events are delivered in order by a background thread, retried with backoff
+//! while the receiver may still take them, then dropped.
+
+use hbb_common::{
+    chrono,
+    config::Config,
+    lazy_static, log,
+    tokio::{self, sync::mpsc, time},
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{sync::Mutex, time::Duration};
+
+pub const OPTION_WEBHOOK_URL: &str = "webhook-url";
+pub const OPTION_WEBHOOK_SECRET: &str = "webhook-secret";
+/// Comma separated event names, all if empty.
+pub const OPTION_WEBHOOK_EVENTS: &str = "webhook-events";
+
+pub const SIGNATURE_HEADER: &str = "X-RustDesk-Signature";
+
+// Events over this while the receiver is down are dropped.
+const QUEUE_SIZE: usize = 1000;
+// One delay per failed attempt, the event is dropped after the last one.
+const RETRY_BACKOFF: [Duration; 4] = [
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+    Duration::from_secs(30),
+    Duration::from_secs(120),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Connect,
+    Authorize,
+    PrivacyMode,
+    FileTransfer,
+    Disconnect,
+}
+
+impl Event {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Event::Connect => "connect",
+            Event::Authorize => "authorize",
+            Event::PrivacyMode => "privacy_mode",
+            Event::FileTransfer => "file_transfer",
+            Event::Disconnect => "disconnect",
+        }
+    }
+}
+
+struct Hook {
+    url: String,
+    secret: String,
+}
+
+lazy_static::lazy_static! {
+    static ref SENDER: Mutex<Option<mpsc::Sender<(Event, Hook, String)>>> = Default::default();
+}
+
+/// Post `event` of connection `conn_id` to the webhook if it is selected.
+pub fn fire(event: Event, conn_id: i32, data: Value) {
+    let url = Config::get_option(OPTION_WEBHOOK_URL);
+    if url.is_empty() || !selected(&Config::get_option(OPTION_WEBHOOK_EVENTS), event) {
+        return;
+    }
+    let body = json!({
+        "event": event.as_str(),
+        "id": Config::get_id(),
+        "conn_id": conn_id,
+        "time": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        // The same in the retries, receivers dedup by it.
+        "delivery": uuid::Uuid::new_v4().to_string(),
+        "data": data,
+    });
+    let hook = Hook {
+        url,
+        secret: Config::get_option(OPTION_WEBHOOK_SECRET),
+    };
+    let mut sender = SENDER.lock().unwrap();
+    let sender = sender.get_or_insert_with(|| {
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        std::thread::spawn(move || deliver_loop(rx));
+        tx
+    });
+    if sender.try_send((event, hook, body.to_string())).is_err() {
+        log::error!("Webhook queue is full, {} event dropped", event.as_str());
+    }
+}
+
+fn selected(events: &str, event: Event) -> bool {
+    let names: Vec<&str> = events
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect();
+    names.is_empty() || names.iter().any(|n| n.eq_ignore_ascii_case(event.as_str()))
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn deliver_loop(mut rx: mpsc::Receiver<(Event, Hook, String)>) {
+    while let Some((event, hook, body)) = rx.recv().await {
+        if let Err(e) = deliver(&hook, &body, &RETRY_BACKOFF).await {
+            log::error!("Webhook {} event dropped: {}", event.as_str(), e);
+        }
+    }
+}
+
+async fn deliver(hook: &Hook, body: &str, backoff: &[Duration]) -> Result<(), String> {
+    let mut attempt = 0;
+    loop {
+        match post(hook, body).await {
+            Ok(()) => return Ok(()),
+            Err((true, e)) if attempt < backoff.len() => {
+                log::warn!("Webhook failed, retry in {:?}: {}", backoff[attempt], e);
+                time::sleep(backoff[attempt]).await;
+                attempt += 1;
+            }
+            Err((_, e)) => return Err(e),
+        }
+    }
+}
+
+/// Post an event once, the error tells if the receiver may take it when trying again.
+async fn post(hook: &Hook, body: &str) -> Result<(), (bool, String)> {
+    // Signed for every attempt, the time tells the receiver how fresh it is.
+    let header = if hook.secret.is_empty() {
+        "".to_owned()
+    } else {
+        format!(
+            "{}: {}",
+            SIGNATURE_HEADER,
+            signature(&hook.secret, chrono::Utc::now().timestamp(), body)
+        )
+    };
+    match crate::post_request_with_status(hook.url.clone(), body.to_owned(), &header).await {
+        Ok((status, _)) if (200..300).contains(&status) => Ok(()),
+        Ok((status, text)) => {
+            let brief: String = text.chars().take(128).collect();
+            // Like the audit records, only 408 and 429 of the 4xx are transient.
+            let transient = status >= 500 || status == 408 || status == 429;
+            Err((transient, format!("status {}: {}", status, brief)))
+        }
+        Err(e) => Err((true, e.to_string())),
+    }
+}
+
+fn signature(secret: &str, time: i64, body: &str) -> String {
+    let mac = hmac_sha256(secret.as_bytes(), format!("{}.{}", time, body).as_bytes());
+    format!("t={},v1={}", time, hex::encode(mac))
+}
+
+// RFC 2104
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(data);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    #[test]
+    fn hmac_matches_rfc4231() {
+        assert_eq!(
+            hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hex::encode(hmac_sha256(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn events_are_filtered() {
+        assert!(selected("", Event::Connect));
+        assert!(selected(" , ", Event::Disconnect));
+        assert!(selected("connect, Disconnect", Event::Disconnect));
+        assert!(!selected("connect,disconnect", Event::FileTransfer));
+    }
+
+    // A local HTTP stand-in answering the requests with `statuses`, returns the raw requests.
+    async fn stand_in(statuses: Vec<u16>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let mut requests = vec![];
+            for status in statuses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+                let mut buf = [0u8; 4096];
+                loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request).to_lowercase();
+                    let Some(end) = text.find("\r\n\r\n") else {
+                        continue;
+                    };
+                    let len = text
+                        .lines()
+                        .find_map(|l| l.strip_prefix("content-length:"))
+                        .and_then(|v| v.trim().parse::<usize>().ok())
+                        .unwrap_or_default();
+                    if n == 0 || request.len() >= end + 4 + len {
+                        break;
+                    }
+                }
+                let response = format!(
+                    "HTTP/1.1 {} Stand-in\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+                requests.push(String::from_utf8_lossy(&request).to_string());
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn events_are_signed_and_retried() {
+        let (url, handle) = stand_in(vec![503, 200]).await;
+        let hook = Hook {
+            url,
+            secret: "secret".to_owned(),
+        };
+        let body = r#"{"event":"connect"}"#;
+        deliver(&hook, body, &[Duration::from_millis(10)])
+            .await
+            .unwrap();
+        let requests = handle.await.unwrap();
+        assert_eq!(requests.len(), 2);
+        let request = &requests[1];
+        assert!(request.ends_with(body));
+        let value = request
+            .lines()
+            .find_map(|l| {
+                let (name, value) = l.split_once(": ")?;
+                name.eq_ignore_ascii_case(SIGNATURE_HEADER).then_some(value)
+            })
+            .unwrap();
+        let time = value
+            .strip_prefix("t=")
+            .and_then(|v| v.split(',').next())
+            .and_then(|t| t.parse::<i64>().ok())
+            .unwrap();
+        assert_eq!(value, signature("secret", time, body));
+    }
+
+    #[tokio::test]
+    async fn rejected_events_are_not_retried() {
+        let (url, handle) = stand_in(vec![400]).await;
+        let hook = Hook {
+            url,
+            secret: "".to_owned(),
+        };
+        assert!(deliver(&hook, "{}", &[Duration::from_millis(10)])
+            .await
+            .is_err());
+        assert_eq!(handle.await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,287 @@
+//! Bandwidth limiting and scheduling of file transfer read jobs.
+//!
+//! `fs::handle_read_jobs()` reads and sends the next block of the jobs it is given on every
+//! tick. The [`TransferScheduler`] decides which jobs are given a turn: jobs of the highest
+//! priority first, as long as the global and per job token buckets allow it. Buckets may go into
+//! debt by one block, so the sizes of the blocks don't need to be known in advance.
+//!
+//! The limits are read from the options, `PeerConfig` options on the controlling side and
+//! `Config` options on the controlled side, and can be changed while transferring.
+//!
+//! Each side only paces the blocks it sends. The limits of a peer in `PeerConfig` apply to the
+//! uploads to it, the downloads from it are paced by the `Config` limits of the controlled side,
+//! the protocol has no message to forward the controlling side's limits.
+
+use hbb_common::fs::TransferJob;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Global limit in KB/s, 0 or empty for unlimited.
+pub const OPTION_RATE_LIMIT: &str = "file-transfer-rate-limit";
+/// Per job limit in KB/s, 0 or empty for unlimited.
+pub const OPTION_JOB_RATE_LIMIT: &str = "file-transfer-job-rate-limit";
+/// Pause transfers while the video network delay is high.
+pub const OPTION_YIELD_TO_VIDEO: &str = "file-transfer-yield-to-video";
+
+// Same threshold as `VideoQoS` uses to reduce the fps.
+const HIGH_VIDEO_DELAY_MS: u32 = 150;
+// Keep the jobs alive while yielding, the peer times out after 30 seconds.
+const YIELD_KEEPALIVE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferLimits {
+    /// Bytes per second, 0 for unlimited.
+    pub rate: u64,
+    pub job_rate: u64,
+    pub yield_to_video: bool,
+}
+
+impl TransferLimits {
+    pub fn from_options(get_option: impl Fn(&str) -> String) -> Self {
+        let kbps = |name: &str| {
+            get_option(name)
+                .trim()
+                .parse::<u64>()
+                .unwrap_or_default()
+                .saturating_mul(1024)
+        };
+        Self {
+            rate: kbps(OPTION_RATE_LIMIT),
+            job_rate: kbps(OPTION_JOB_RATE_LIMIT),
+            yield_to_video: get_option(OPTION_YIELD_TO_VIDEO) == "Y",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    rate: u64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, now: Instant) -> Self {
+        Self {
+            rate,
+            tokens: rate as f64,
+            last: now,
+        }
+    }
+
+    fn set_rate(&mut self, rate: u64) {
+        if self.rate != rate {
+            self.rate = rate;
+            self.tokens = self.tokens.min(rate as f64);
+        }
+    }
+
+    fn ready(&mut self, now: Instant) -> bool {
+        if self.rate == 0 {
+            return true;
+        }
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = now;
+        // At most one second of burst.
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+        self.tokens > 0.0
+    }
+
+    fn consume(&mut self, bytes: u64) {
+        if self.rate > 0 {
+            self.tokens -= bytes as f64;
+        }
+    }
+}
+
+#[derive(Debug)]
+struct JobState {
+    bucket: TokenBucket,
+    priority: i32,
+    transferred: u64,
+}
+
+#[derive(Debug)]
+pub struct TransferScheduler {
+    limits: TransferLimits,
+    global: TokenBucket,
+    jobs: HashMap<i32, JobState>,
+    last_yield_turn: Option<Instant>,
+}
+
+impl TransferScheduler {
+    pub fn new(limits: TransferLimits) -> Self {
+        Self {
+            limits,
+            global: TokenBucket::new(limits.rate, Instant::now()),
+            jobs: Default::default(),
+            last_yield_turn: None,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: TransferLimits) {
+        if self.limits != limits {
+            self.limits = limits;
+            self.global.set_rate(limits.rate);
+            for job in self.jobs.values_mut() {
+                job.bucket.set_rate(limits.job_rate);
+            }
+        }
+    }
+
+    /// Higher priority jobs are sent first, the default is 0.
+    pub fn set_priority(&mut self, id: i32, priority: i32) {
+        self.job(id, Instant::now()).priority = priority;
+    }
+
+    fn job(&mut self, id: i32, now: Instant) -> &mut JobState {
+        let job_rate = self.limits.job_rate;
+        self.jobs.entry(id).or_insert_with(|| JobState {
+            bucket: TokenBucket::new(job_rate, now),
+            priority: 0,
+            transferred: 0,
+        })
+    }
+
+    /// Ids of the jobs which may send a block now.
+    ///
+    /// `video_delay` is the network delay of the video stream in milliseconds, if any.
+    fn ready(&mut self, ids: &[i32], video_delay: Option<u32>, now: Instant) -> Vec<i32> {
+        self.jobs.retain(|id, _| ids.contains(id));
+        if ids.is_empty() || !self.global.ready(now) {
+            return vec![];
+        }
+        let mut ready: Vec<(i32, i32)> = vec![];
+        for &id in ids {
+            let job = self.job(id, now);
+            if job.bucket.ready(now) {
+                ready.push((id, job.priority));
+            }
+        }
+        let Some(highest) = ready.iter().map(|(_, priority)| *priority).max() else {
+            return vec![];
+        };
+        let mut ready: Vec<i32> = ready
+            .into_iter()
+            .filter(|(_, priority)| *priority == highest)
+            .map(|(id, _)| id)
+            .collect();
+        if self.limits.yield_to_video && video_delay.unwrap_or_default() >= HIGH_VIDEO_DELAY_MS {
+            if self
+                .last_yield_turn
+                .map(|t| now.saturating_duration_since(t) < YIELD_KEEPALIVE)
+                .unwrap_or(false)
+            {
+                return vec![];
+            }
+            self.last_yield_turn = Some(now);
+            ready.truncate(1);
+        } else {
+            self.last_yield_turn = None;
+        }
+        ready
+    }
+
+    /// `transferred` is the total number of bytes the job has sent.
+    fn on_sent(&mut self, id: i32, transferred: u64, now: Instant) {
+        let job = self.job(id, now);
+        let bytes = transferred.saturating_sub(job.transferred);
+        job.transferred = transferred;
+        job.bucket.consume(bytes);
+        self.global.consume(bytes);
+    }
+
+    /// Move the jobs which may send a block now out of `jobs`, to be passed to
+    /// `fs::handle_read_jobs()` and given back with [`Self::give_back`].
+    pub fn take_ready(
+        &mut self,
+        jobs: &mut Vec<TransferJob>,
+        video_delay: Option<u32>,
+    ) -> Vec<TransferJob> {
+        let ids: Vec<i32> = jobs.iter().map(|job| job.id()).collect();
+        let ready = self.ready(&ids, video_delay, Instant::now());
+        let (ready, rest) = std::mem::take(jobs)
+            .into_iter()
+            .partition(|job| ready.contains(&job.id()));
+        *jobs = rest;
+        ready
+    }
+
+    pub fn give_back(&mut self, ready: Vec<TransferJob>, jobs: &mut Vec<TransferJob>) {
+        let now = Instant::now();
+        for job in ready.iter() {
+            self.on_sent(job.id(), job.transferred(), now);
+        }
+        jobs.extend(ready);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(rate: u64, job_rate: u64) -> TransferLimits {
+        TransferLimits {
+            rate,
+            job_rate,
+            yield_to_video: false,
+        }
+    }
+
+    #[test]
+    fn rate_limits_are_enforced() {
+        let now = Instant::now();
+        let mut scheduler = TransferScheduler::new(limits(0, 1000));
+        assert_eq!(scheduler.ready(&[1, 2], None, now), vec![1, 2]);
+        scheduler.on_sent(1, 1500, now);
+        assert_eq!(scheduler.ready(&[1, 2], None, now), vec![2]);
+        let later = now + Duration::from_millis(600);
+        assert_eq!(scheduler.ready(&[1, 2], None, later), vec![1, 2]);
+
+        scheduler.set_limits(limits(1000, 0));
+        scheduler.on_sent(2, 2000, later);
+        assert!(scheduler.ready(&[1, 2], None, later).is_empty());
+        scheduler.set_limits(limits(0, 0));
+        assert_eq!(scheduler.ready(&[1, 2], None, later), vec![1, 2]);
+    }
+
+    #[test]
+    fn higher_priority_jobs_go_first() {
+        let now = Instant::now();
+        let mut scheduler = TransferScheduler::new(limits(0, 1000));
+        scheduler.set_priority(2, 1);
+        assert_eq!(scheduler.ready(&[1, 2, 3], None, now), vec![2]);
+        // Lower priority jobs use the bandwidth left by throttled ones.
+        scheduler.on_sent(2, 2000, now);
+        assert_eq!(scheduler.ready(&[1, 2, 3], None, now), vec![1, 3]);
+    }
+
+    #[test]
+    fn yield_to_video_keeps_one_job_alive() {
+        let now = Instant::now();
+        let mut scheduler = TransferScheduler::new(TransferLimits {
+            yield_to_video: true,
+            ..Default::default()
+        });
+        assert_eq!(scheduler.ready(&[1, 2], Some(50), now), vec![1, 2]);
+        assert_eq!(scheduler.ready(&[1, 2], Some(300), now), vec![1]);
+        assert!(scheduler.ready(&[1, 2], Some(300), now).is_empty());
+        let later = now + YIELD_KEEPALIVE;
+        assert_eq!(scheduler.ready(&[1, 2], Some(300), later), vec![1]);
+    }
+
+    #[test]
+    fn limits_are_read_in_kb() {
+        let limits = TransferLimits::from_options(|name| match name {
+            OPTION_RATE_LIMIT => "512".to_owned(),
+            OPTION_JOB_RATE_LIMIT => "invalid".to_owned(),
+            OPTION_YIELD_TO_VIDEO => "Y".to_owned(),
+            _ => "".to_owned(),
+        });
+        assert_eq!(limits.rate, 512 * 1024);
+        assert_eq!(limits.job_rate, 0);
+        assert!(limits.yield_to_video);
+    }
+}
@@ -0,0 +1,261 @@
+//! UDP port forwarding.
+//!
+//! A UDP forward is requested like a TCP one, with the `udp://` prefix on the remote host of
+//! the `PortForward` login, so peers without UDP support refuse it as an unreachable target.
+//! After login the stream keeps its framing, each message carrying one datagram: the id of the
+//! flow as 4 bytes big endian, then the payload. A flow is a source address on the controlling
+//! side; the controlled side sends each flow from its own socket, so the replies can be told
+//! apart. Flows idle for [`FLOW_IDLE_TIMEOUT`] are dropped on both sides.
+
+use hbb_common::{
+    bail,
+    bytes::{BufMut, Bytes, BytesMut},
+    log,
+    tokio::{
+        self,
+        net::UdpSocket,
+        sync::mpsc::{self, error::TrySendError},
+        task::JoinHandle,
+    },
+    ResultType,
+};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+pub const UDP_PREFIX: &str = "udp://";
+pub const FLOW_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+pub const MAX_DATAGRAM_SIZE: usize = 65536;
+const MAX_FLOWS: usize = 1024;
+// Datagrams of the target waiting for the stream, the next ones are dropped like on a full link.
+const MAX_QUEUED_DATAGRAMS: usize = 256;
+const FLOW_ID_LEN: usize = 4;
+
+/// The remote host without the `udp://` prefix, `None` for a TCP forward.
+pub fn strip_prefix(host: &str) -> Option<&str> {
+    host.strip_prefix(UDP_PREFIX)
+}
+
+pub fn encode(flow: u32, payload: &[u8]) -> Bytes {
+    let mut frame = BytesMut::with_capacity(FLOW_ID_LEN + payload.len());
+    frame.put_u32(flow);
+    frame.put_slice(payload);
+    frame.freeze()
+}
+
+pub fn decode(frame: &[u8]) -> Option<(u32, &[u8])> {
+    if frame.len() < FLOW_ID_LEN {
+        return None;
+    }
+    let (id, payload) = frame.split_at(FLOW_ID_LEN);
+    Some((u32::from_be_bytes(id.try_into().ok()?), payload))
+}
+
+/// Flows of the controlling side, by source address.
+#[derive(Debug, Default)]
+pub struct Flows {
+    ids: HashMap<SocketAddr, (u32, Instant)>,
+    addrs: HashMap<u32, SocketAddr>,
+    next_id: u32,
+}
+
+impl Flows {
+    /// The id of the flow of `addr`, a new one for a new source.
+    pub fn id(&mut self, addr: SocketAddr, now: Instant) -> u32 {
+        if let Some((id, last_active)) = self.ids.get_mut(&addr) {
+            *last_active = now;
+            return *id;
+        }
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.ids.insert(addr, (id, now));
+        self.addrs.insert(id, addr);
+        id
+    }
+
+    /// The source address to send a reply of the flow to.
+    pub fn addr(&mut self, id: u32, now: Instant) -> Option<SocketAddr> {
+        let addr = *self.addrs.get(&id)?;
+        if let Some((_, last_active)) = self.ids.get_mut(&addr) {
+            *last_active = now;
+        }
+        Some(addr)
+    }
+
+    pub fn remove_idle(&mut self, now: Instant) {
+        let addrs = &mut self.addrs;
+        self.ids.retain(|_, (id, last_active)| {
+            let active = now.saturating_duration_since(*last_active) < FLOW_IDLE_TIMEOUT;
+            if !active {
+                addrs.remove(id);
+            }
+            active
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.ids.clear();
+        self.addrs.clear();
+    }
+}
+
+struct Flow {
+    socket: Arc<UdpSocket>,
+    last_active: Instant,
+    reader: JoinHandle<()>,
+}
+
+impl Drop for Flow {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// The controlled side of a UDP forward, one socket connected to the target per flow.
+pub struct UdpForwarder {
+    target: SocketAddr,
+    flows: HashMap<u32, Flow>,
+    tx: mpsc::Sender<(u32, Bytes)>,
+    rx: mpsc::Receiver<(u32, Bytes)>,
+}
+
+impl UdpForwarder {
+    pub async fn new(addr: &str) -> ResultType<Self> {
+        let Some(target) = tokio::net::lookup_host(addr).await?.next() else {
+            bail!("Failed to resolve {}", addr);
+        };
+        let (tx, rx) = mpsc::channel(MAX_QUEUED_DATAGRAMS);
+        Ok(Self {
+            target,
+            flows: Default::default(),
+            tx,
+            rx,
+        })
+    }
+
+    async fn open(&self, id: u32) -> ResultType<Flow> {
+        let local = if self.target.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = Arc::new(UdpSocket::bind(local).await?);
+        socket.connect(self.target).await?;
+        let reader = {
+            let socket = socket.clone();
+            let tx = self.tx.clone();
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+                loop {
+                    match socket.recv(&mut buf).await {
+                        Ok(n) => match tx.try_send((id, encode(id, &buf[..n]))) {
+                            Ok(_) => {}
+                            Err(TrySendError::Full(_)) => {
+                                log::trace!(
+                                    "UDP forward queue full, datagram of flow {} dropped",
+                                    id
+                                );
+                            }
+                            Err(TrySendError::Closed(_)) => break,
+                        },
+                        // ICMP port unreachable of a previous datagram
+                        Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {}
+                        Err(e) => {
+                            log::debug!("UDP forward flow {} closed: {}", id, e);
+                            break;
+                        }
+                    }
+                }
+            })
+        };
+        Ok(Flow {
+            socket,
+            last_active: Instant::now(),
+            reader,
+        })
+    }
+
+    /// Send a datagram of the controlling side to the target.
+    pub async fn send(&mut self, frame: &[u8]) -> ResultType<()> {
+        let Some((id, payload)) = decode(frame) else {
+            bail!("Invalid UDP forward frame");
+        };
+        if !self.flows.contains_key(&id) {
+            if self.flows.len() >= MAX_FLOWS {
+                self.remove_idle();
+                if self.flows.len() >= MAX_FLOWS {
+                    log::warn!("Too many UDP forward flows, datagram dropped");
+                    return Ok(());
+                }
+            }
+            match self.open(id).await {
+                Ok(flow) => {
+                    self.flows.insert(id, flow);
+                }
+                // Only this flow is lost, the next datagram of it tries again.
+                Err(e) => {
+                    log::warn!("Failed to open UDP forward flow {}: {}", id, e);
+                    return Ok(());
+                }
+            }
+        }
+        if let Some(flow) = self.flows.get_mut(&id) {
+            flow.last_active = Instant::now();
+            if let Err(e) = flow.socket.send(payload).await {
+                log::debug!("UDP forward send failed for flow {}: {}", id, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// The next datagram of the target, framed for the controlling side.
+    pub async fn recv(&mut self) -> Option<Bytes> {
+        let (id, frame) = self.rx.recv().await?;
+        if let Some(flow) = self.flows.get_mut(&id) {
+            flow.last_active = Instant::now();
+        }
+        Some(frame)
+    }
+
+    pub fn remove_idle(&mut self) {
+        self.flows
+            .retain(|_, flow| flow.last_active.elapsed() < FLOW_IDLE_TIMEOUT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_carry_the_flow_id() {
+        let frame = encode(0x01020304, b"datagram");
+        assert_eq!(&frame[..FLOW_ID_LEN], &[1, 2, 3, 4]);
+        assert_eq!(decode(&frame), Some((0x01020304, &b"datagram"[..])));
+        assert_eq!(decode(&encode(7, b"")), Some((7, &b""[..])));
+        assert_eq!(decode(&[0, 1]), None);
+    }
+
+    #[test]
+    fn flows_are_tracked_by_source_and_expire() {
+        let now = Instant::now();
+        let a: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let b: SocketAddr = "127.0.0.1:5001".parse().unwrap();
+        let mut flows = Flows::default();
+        let id_a = flows.id(a, now);
+        let id_b = flows.id(b, now);
+        assert_ne!(id_a, id_b);
+        assert_eq!(flows.id(a, now), id_a);
+        assert_eq!(flows.addr(id_b, now), Some(b));
+
+        let later = now + FLOW_IDLE_TIMEOUT / 2;
+        flows.addr(id_a, later);
+        flows.remove_idle(now + FLOW_IDLE_TIMEOUT);
+        assert_eq!(flows.addr(id_a, later), Some(a));
+        assert_eq!(flows.addr(id_b, later), None);
+        assert_ne!(flows.id(b, later), id_b);
+    }
+}
@@ -1,7 +1,9 @@
+use crate::file_delta;
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 use crate::ipc::Connection;
 #[cfg(not(any(target_os = "ios")))]
 use crate::ipc::{self, Data};
+use crate::transfer_limit::{TransferLimits, TransferScheduler};
 #[cfg(target_os = "windows")]
 use crate::{clipboard::ClipboardSide, ipc::ClipboardNonFile};
 #[cfg(target_os = "windows")]
@@ -144,6 +146,8 @@ pub struct Client {
     pub recording: bool,
     pub block_input: bool,
     pub privacy_mode: bool,
+    // False for a read-only observer of a shared terminal
+    pub terminal_input: bool,
     pub from_switch: bool,
     pub in_voice_call: bool,
     pub incoming_voice_call: bool,
@@ -167,6 +171,7 @@ struct IpcTaskRunner<T: InvokeUiCM> {
     file_transfer_enabled_peer: bool,
     /// Read jobs for CM-side file reading (server to client transfers)
     read_jobs: Vec<fs::TransferJob>,
+    transfer_scheduler: TransferScheduler,
 }
 
 lazy_static::lazy_static! {
@@ -254,6 +259,7 @@ impl<T: InvokeUiCM> ConnectionManager<T> {
             recording,
             block_input,
             privacy_mode,
+            terminal_input: true,
             from_switch,
             #[cfg(not(any(target_os = "ios")))]
             tx,
@@ -482,9 +488,11 @@ impl<T: InvokeUiCM> IpcTaskRunner<T> {
 
         // for tmp use, without real conn id
         let mut write_jobs: Vec<fs::TransferJob> = Vec::new();
+        let mut deltas = file_delta::Deltas::default();
         // File timer for processing read_jobs
         let mut file_timer =
             crate::rustdesk_interval(time::interval_at(Instant::now() + SEC30, SEC30));
+        let mut transfer_limits_instant = Instant::now();
 
         #[cfg(target_os = "windows")]
         let is_authorized = self.cm.is_authorized(self.conn_id);
@@ -574,15 +582,19 @@ impl<T: InvokeUiCM> IpcTaskRunner<T> {
                                     self.cm.new_message(self.conn_id, text);
                                 }
                                 Data::SwitchPermission { name, enabled } => {
-                                    // Keep this branch scoped to privacy mode rollback.
                                     // Other CM permission toggles are updated optimistically by the UI itself.
-                                    // The backend currently sends SwitchPermission back to CM only when
-                                    // privacy-mode turn-off fails and the UI state must be restored.
-                                    if name == "privacy_mode" {
+                                    // The backend sends SwitchPermission back to CM only when privacy-mode
+                                    // turn-off fails and the UI state must be restored, or when the
+                                    // connection is attached to a shared terminal.
+                                    if name == "privacy_mode" || name == "terminal_input" {
                                         let client = {
                                             let mut clients = CLIENTS.write().unwrap();
                                             clients.get_mut(&self.conn_id).map(|c| {
-                                                c.privacy_mode = enabled;
+                                                if name == "privacy_mode" {
+                                                    c.privacy_mode = enabled;
+                                                } else {
+                                                    c.terminal_input = enabled;
+                                                }
                                                 c.clone()
                                             })
                                         };
@@ -597,10 +609,18 @@ impl<T: InvokeUiCM> IpcTaskRunner<T> {
                                     if let ipc::FS::WriteBlock { id, file_num, data: _, compressed } = fs {
                                         if let Ok(bytes) = self.stream.next_raw().await {
                                             fs = ipc::FS::WriteBlock{id, file_num, data:bytes.into(), compressed};
-                                            handle_fs(fs, &mut write_jobs, &mut self.read_jobs, &self.tx, Some(&tx_log), self.conn_id).await;
+                                            handle_fs(fs, &mut write_jobs, &mut self.read_jobs, &mut deltas, &self.tx, Some(&tx_log), self.conn_id).await;
+                                        }
+                                    } else if let ipc::FS::ReadDelta { .. } | ipc::FS::WriteDelta { .. } = fs {
+                                        if let Ok(bytes) = self.stream.next_raw().await {
+                                            fs = match fs {
+                                                ipc::FS::ReadDelta { .. } => ipc::FS::ReadDelta { data: bytes.into() },
+                                                _ => ipc::FS::WriteDelta { data: bytes.into() },
+                                            };
+                                            handle_fs(fs, &mut write_jobs, &mut self.read_jobs, &mut deltas, &self.tx, Some(&tx_log), self.conn_id).await;
                                         }
                                     } else {
-                                        handle_fs(fs, &mut write_jobs, &mut self.read_jobs, &self.tx, Some(&tx_log), self.conn_id).await;
+                                        handle_fs(fs, &mut write_jobs, &mut self.read_jobs, &mut deltas, &self.tx, Some(&tx_log), self.conn_id).await;
                                     }
                                     // Activate fast timer immediately when read jobs exist.
                                     // This ensures new jobs start processing without waiting for the slow 30s timer.
@@ -787,8 +807,18 @@ impl<T: InvokeUiCM> IpcTaskRunner<T> {
                 }
                 _ = file_timer.tick() => {
                     if !self.read_jobs.is_empty() {
+                        if transfer_limits_instant.elapsed() >= Duration::from_secs(1) {
+                            transfer_limits_instant = Instant::now();
+                            self.transfer_scheduler.set_limits(TransferLimits::from_options(Config::get_option));
+                        }
                         let conn_id = self.conn_id;
-                        if let Err(e) = handle_read_jobs_tick(&mut self.read_jobs, &self.tx, conn_id).await {
+                        for msg in deltas.next_messages(&mut self.read_jobs).await {
+                            send_raw(msg, &self.tx);
+                        }
+                        let mut ready = self.transfer_scheduler.take_ready(&mut self.read_jobs, None);
+                        let res = handle_read_jobs_tick(&mut ready, &self.tx, conn_id).await;
+                        self.transfer_scheduler.give_back(ready, &mut self.read_jobs);
+                        if let Err(e) = res {
                             log::error!("Error processing read jobs: {}", e);
                         }
                         let log = fs::serialize_transfer_jobs(&self.read_jobs);
@@ -817,6 +847,9 @@ impl<T: InvokeUiCM> IpcTaskRunner<T> {
             #[cfg(target_os = "windows")]
             file_transfer_enabled_peer: false,
             read_jobs: Vec::new(),
+            transfer_scheduler: TransferScheduler::new(TransferLimits::from_options(
+                Config::get_option,
+            )),
         };
 
         while task_runner.running {
@@ -876,6 +909,7 @@ pub async fn start_listen<T: InvokeUiCM>(
 ) {
     let mut current_id = 0;
     let mut write_jobs: Vec<fs::TransferJob> = Vec::new();
+    let mut deltas = file_delta::Deltas::default();
     loop {
         match rx.recv().await {
             Some(Data::Login {
@@ -932,6 +966,7 @@ pub async fn start_listen<T: InvokeUiCM>(
                     fs,
                     &mut write_jobs,
                     &mut read_jobs_placeholder,
+                    &mut deltas,
                     &tx,
                     None,
                     current_id,
@@ -964,6 +999,7 @@ async fn handle_fs(
     fs: ipc::FS,
     write_jobs: &mut Vec<fs::TransferJob>,
     read_jobs: &mut Vec<fs::TransferJob>,
+    deltas: &mut file_delta::Deltas,
     tx: &UnboundedSender<Data>,
     tx_log: Option<&UnboundedSender<String>>,
     _conn_id: i32,
@@ -1035,6 +1071,7 @@ async fn handle_fs(
             write_jobs.push(job);
         }
         ipc::FS::CancelWrite { id } => {
+            deltas.remove_job(id);
             if let Some(job) = fs::remove_job(id, write_jobs) {
                 job.remove_download_file();
                 if let Some(tx) = tx_log {
@@ -1052,6 +1089,7 @@ async fn handle_fs(
             }
         }
         ipc::FS::WriteError { id, file_num, err } => {
+            deltas.remove_job(id);
             if let Some(job) = fs::remove_job(id, write_jobs) {
                 tx_log.map(|tx| tx.send(serialize_transfer_job(&job, false, false, &err)));
                 send_raw(fs::new_error(job.id(), err, file_num), tx);
@@ -1138,7 +1176,44 @@ async fn handle_fs(
         ipc::FS::SendConfirm(bytes) => {
             if let Ok(r) = FileTransferSendConfirmRequest::parse_from_bytes(&bytes) {
                 if let Some(job) = fs::get_job(r.id, write_jobs) {
-                    job.confirm(&r).await;
+                    if r.delta() {
+                        // The uploader asks for the signature of the file to overwrite.
+                        if let Some(msg) = deltas.start_write(job, r.file_num).await {
+                            send_raw(msg, tx);
+                            return;
+                        }
+                        let req = FileTransferSendConfirmRequest {
+                            id: r.id,
+                            file_num: r.file_num,
+                            union: Some(file_transfer_send_confirm_request::Union::OffsetBlk(0)),
+                            ..Default::default()
+                        };
+                        job.confirm(&req).await;
+                        send_raw(new_send_confirm(req), tx);
+                    } else {
+                        job.confirm(&r).await;
+                    }
+                }
+            }
+        }
+        ipc::FS::ReadDelta { data } => {
+            if let Ok(s) = FileTransferSignature::parse_from_bytes(&data) {
+                if let Err(err) = deltas.start_read(read_jobs, &s) {
+                    send_raw(fs::new_error(s.id, err, s.file_num), tx);
+                }
+            }
+        }
+        ipc::FS::WriteDelta { data } => {
+            if let Ok(d) = FileTransferDelta::parse_from_bytes(&data) {
+                let (id, file_num) = (d.id, d.file_num);
+                if let Err(err) = deltas.write(d) {
+                    deltas.remove_job(id);
+                    if let Some(job) = fs::remove_job(id, write_jobs) {
+                        tx_log.map(|tx| {
+                            tx.send(serialize_transfer_job(&job, false, false, &err.to_string()))
+                        });
+                    }
+                    send_raw(fs::new_error(id, err, file_num), tx);
                 }
             }
         }
@@ -1170,6 +1245,7 @@ async fn handle_fs(
         // operations, which are one-shot directory scans that complete quickly and don't
         // have persistent job tracking.
         ipc::FS::CancelRead { id, conn_id: _ } => {
+            deltas.remove_job(id);
             if let Some(job) = fs::remove_job(id, read_jobs) {
                 if let Some(tx) = tx_log {
                     if let Err(e) = tx.send(serialize_transfer_job(&job, false, true, "")) {
@@ -1958,17 +1958,45 @@ pub async fn io_loop<T: InvokeUiSession>(handler: Session<T>, round: u32) {
                 handler.get_option("rdp_password".to_owned()),
             );
             log::info!("Remote rdp port: {}", port);
-            start_one_port_forward(handler, 0, "".to_owned(), port, receiver, &key, &token).await;
+            start_one_port_forward(
+                handler,
+                0,
+                "".to_owned(),
+                port,
+                receiver,
+                &key,
+                &token,
+                None,
+            )
+            .await;
         } else if handler.args.len() == 0 {
             let pfs = handler.lc.read().unwrap().port_forwards.clone();
             let mut queues = HashMap::<i32, mpsc::UnboundedSender<Data>>::new();
+            // The forwards share one connection, it gets the ui messages like them.
+            let (mux_sender, mux_receiver) = mpsc::unbounded_channel::<Data>();
+            queues.insert(0, mux_sender);
+            let mux_targets = pfs
+                .iter()
+                .filter(|(port, ..)| *port > 0)
+                .filter_map(|(_, host, port)| crate::port_forward::mux_target(host, *port))
+                .collect();
+            let mux = crate::port_forward::MuxHandle::start(
+                handler.get_id(),
+                handler.password.clone(),
+                handler.clone(),
+                mux_receiver,
+                key.clone(),
+                token.clone(),
+                handler.lc.clone(),
+                mux_targets,
+            );
             for d in pfs {
                 sender.send(Data::AddPortForward(d)).ok();
             }
             loop {
                 match receiver.recv().await {
                     Some(Data::AddPortForward((port, remote_host, remote_port))) => {
-                        if port <= 0 || remote_port <= 0 {
+                        if !crate::port_forward::is_valid_forward(port, &remote_host, remote_port) {
                             continue;
                         }
                         let (sender, receiver) = mpsc::unbounded_channel::<Data>();
@@ -1976,6 +2004,7 @@ pub async fn io_loop<T: InvokeUiSession>(handler: Session<T>, round: u32) {
                         let handler = handler.clone();
                         let key = key.clone();
                         let token = token.clone();
+                        let mux = mux.clone();
                         tokio::spawn(async move {
                             start_one_port_forward(
                                 handler,
@@ -1985,6 +2014,7 @@ pub async fn io_loop<T: InvokeUiSession>(handler: Session<T>, round: u32) {
                                 receiver,
                                 &key,
                                 &token,
+                                Some(mux),
                             )
                             .await;
                         });
@@ -2006,15 +2036,12 @@ pub async fn io_loop<T: InvokeUiSession>(handler: Session<T>, round: u32) {
                 }
             }
         } else {
-            let port = handler.args[0].parse::<i32>().unwrap_or(0);
-            if handler.args.len() != 3
-                || handler.args[2].parse::<i32>().unwrap_or(0) <= 0
-                || port <= 0
-            {
+            let Some((port, remote_host, remote_port)) =
+                crate::port_forward::parse_forward_args(&handler.args)
+            else {
                 handler.on_error("Invalid arguments, usage:<br><br> rustdesk --port-forward remote-id listen-port remote-host remote-port");
-            }
-            let remote_host = handler.args[1].clone();
-            let remote_port = handler.args[2].parse::<i32>().unwrap_or(0);
+                return;
+            };
             start_one_port_forward(
                 handler,
                 port,
@@ -2023,6 +2050,7 @@ pub async fn io_loop<T: InvokeUiSession>(handler: Session<T>, round: u32) {
                 receiver,
                 &key,
                 &token,
+                None,
             )
             .await;
         }
@@ -2042,6 +2070,7 @@ async fn start_one_port_forward<T: InvokeUiSession>(
     receiver: mpsc::UnboundedReceiver<Data>,
     key: &str,
     token: &str,
+    mux: Option<crate::port_forward::MuxHandle>,
 ) {
     if let Err(err) = crate::port_forward::listen(
         handler.get_id(),
@@ -2054,6 +2083,7 @@ async fn start_one_port_forward<T: InvokeUiSession>(
         handler.lc.clone(),
         remote_host,
         remote_port,
+        mux,
     )
     .await
     {
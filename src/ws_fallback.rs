@@ -0,0 +1,321 @@
+//! WebSocket fallback for networks where only HTTPS egress works.
+//!
+//! When the rendezvous server can't be reached over TCP/UDP and the `websocket-fallback`
+//! option is set, the rendezvous and relay connections are made over WebSocket instead,
+//! using the `/ws/id` and `/ws/relay` paths a reverse proxy on port 443 forwards to hbbs
+//! and hbbr. `connect_tcp()` treats `ws://` and `wss://` targets as WebSocket endpoints
+//! with the same `Stream` framing. A connection through the rendezvous server is then always
+//! relayed, like with the `allow-websocket` option.
+//!
+//! Direct connections work over WebSocket too. With the option set, the controlled side also
+//! accepts them on the port after the direct access port, see [`accept`], for its own proxy
+//! to forward `/ws/direct` to. A client connects to a `ws://` or `wss://` peer address
+//! directly, and retries `wss://<peer host>/ws/direct` when a direct TCP connection fails.
+//!
+//! The option is `Y` to use `wss://` on the host of the server, or a base URL such as
+//! `wss://gateway.example.com` if the proxy is on another host.
+
+use hbb_common::{
+    bytes_codec::BytesCodec,
+    config::Config,
+    futures::{SinkExt, StreamExt},
+    log,
+    tcp::{DynTcpStream, FramedStream},
+    tokio::{self, net::TcpStream},
+    tokio_util::codec::Framed,
+    ResultType, Stream,
+};
+use std::{collections::HashMap, sync::Mutex};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+pub const OPTION_WEBSOCKET_FALLBACK: &str = "websocket-fallback";
+const ID_PATH: &str = "/ws/id";
+const RELAY_PATH: &str = "/ws/relay";
+const DIRECT_PATH: &str = "/ws/direct";
+// Buffered between the WebSocket and the framing of the connection.
+const BRIDGE_BUFFER: usize = 256 * 1024;
+// While the native connection fails, it is retried every so many attempts of the mediator.
+const NATIVE_RETRY_INTERVAL: usize = 4;
+
+lazy_static::lazy_static! {
+    static ref MEDIATOR_FAILURES: Mutex<HashMap<String, usize>> = Default::default();
+}
+
+pub fn is_ws_endpoint(addr: &str) -> bool {
+    addr.starts_with("ws://") || addr.starts_with("wss://")
+}
+
+fn option() -> String {
+    Config::get_option(OPTION_WEBSOCKET_FALLBACK)
+}
+
+pub fn is_enabled() -> bool {
+    is_enabled_by(&option())
+}
+
+fn is_enabled_by(option: &str) -> bool {
+    option == "Y" || is_ws_endpoint(option)
+}
+
+// Host of `host`, `host:port`, `[ipv6]:port` or a URL, without the port.
+fn host(addr: &str) -> &str {
+    let addr = addr
+        .strip_prefix("wss://")
+        .or_else(|| addr.strip_prefix("ws://"))
+        .unwrap_or(addr);
+    let addr = addr.split('/').next().unwrap_or(addr);
+    if addr.starts_with('[') {
+        return addr.split_inclusive(']').next().unwrap_or(addr);
+    }
+    match addr.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') && port.parse::<u16>().is_ok() => host,
+        _ => addr,
+    }
+}
+
+fn endpoint(option: &str, server: &str, path: &str) -> Option<String> {
+    if is_ws_endpoint(option) {
+        Some(format!("{}{}", option.trim_end_matches('/'), path))
+    } else if option == "Y" && !host(server).is_empty() {
+        Some(format!("wss://{}{}", host(server), path))
+    } else {
+        None
+    }
+}
+
+/// WebSocket endpoint of the rendezvous server, `None` if the fallback is disabled.
+pub fn rendezvous_endpoint(rendezvous_server: &str) -> Option<String> {
+    endpoint(&option(), rendezvous_server, ID_PATH)
+}
+
+/// The relay server to use, over WebSocket if the rendezvous connection is.
+pub fn relay_endpoint(rendezvous_server: &str, relay_server: String) -> String {
+    relay_endpoint_by(&option(), rendezvous_server, relay_server)
+}
+
+fn relay_endpoint_by(option: &str, rendezvous_server: &str, relay_server: String) -> String {
+    if !is_ws_endpoint(rendezvous_server) || is_ws_endpoint(&relay_server) {
+        return relay_server;
+    }
+    if let Some(endpoint) = endpoint(option, &relay_server, RELAY_PATH) {
+        return endpoint;
+    }
+    // The option was turned off meanwhile, stay on the proxy of the rendezvous server.
+    match rendezvous_server.strip_suffix(ID_PATH) {
+        Some(base) => format!("{}{}", base, RELAY_PATH),
+        None => relay_server,
+    }
+}
+
+/// The WebSocket endpoint to retry a failed direct connection to `peer` on, only with the
+/// option `Y`, a gateway on another host can't tell which peer is meant.
+pub fn direct_endpoint(peer: &str) -> Option<String> {
+    direct_endpoint_by(&option(), peer)
+}
+
+fn direct_endpoint_by(option: &str, peer: &str) -> Option<String> {
+    if option == "Y" {
+        endpoint(option, peer, DIRECT_PATH)
+    } else {
+        None
+    }
+}
+
+/// Accept a direct connection over WebSocket. Each binary message is a frame of the
+/// connection, they are passed through an in-memory pipe to give the usual `Stream`.
+pub async fn accept(socket: TcpStream) -> ResultType<Stream> {
+    let local_addr = socket.local_addr()?;
+    let mut ws = tokio_tungstenite::accept_async(socket).await?;
+    let (stream, bridge) = tokio::io::duplex(BRIDGE_BUFFER);
+    tokio::spawn(async move {
+        let mut bridge = Framed::new(bridge, BytesCodec::new());
+        loop {
+            tokio::select! {
+                msg = ws.next() => match msg {
+                    Some(Ok(WsMessage::Binary(data))) => {
+                        if bridge.send(data).await.is_err() {
+                            break;
+                        }
+                    }
+                    // Pongs are answered by the WebSocket itself.
+                    Some(Ok(WsMessage::Ping(_))) | Some(Ok(WsMessage::Pong(_))) => {}
+                    Some(Ok(_)) | None => break,
+                    Some(Err(e)) => {
+                        log::debug!("websocket direct connection error: {}", e);
+                        break;
+                    }
+                },
+                res = bridge.next() => match res {
+                    Some(Ok(bytes)) => {
+                        if ws.send(WsMessage::Binary(bytes.freeze())).await.is_err() {
+                            break;
+                        }
+                    }
+                    _ => break,
+                },
+            }
+        }
+        ws.close(None).await.ok();
+    });
+    Ok(Stream::Tcp(FramedStream(
+        Framed::new(DynTcpStream(Box::new(stream)), BytesCodec::new()),
+        local_addr,
+        None,
+        0,
+    )))
+}
+
+/// The WebSocket endpoint the rendezvous mediator of `host` should use, if the native
+/// connection has failed before.
+pub fn mediator_endpoint(host: &str) -> Option<String> {
+    let failures = MEDIATOR_FAILURES
+        .lock()
+        .unwrap()
+        .get(host)
+        .copied()
+        .unwrap_or_default();
+    if should_fall_back(failures) {
+        rendezvous_endpoint(host)
+    } else {
+        None
+    }
+}
+
+fn should_fall_back(failures: usize) -> bool {
+    failures > 0 && failures % NATIVE_RETRY_INTERVAL != 0
+}
+
+pub fn on_mediator_exit(host: &str, ok: bool) {
+    let mut failures = MEDIATOR_FAILURES.lock().unwrap();
+    if ok {
+        failures.remove(host);
+    } else {
+        *failures.entry(host.to_owned()).or_default() += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoints_use_the_host_of_the_server() {
+        assert_eq!(host("rs.example.com:21116"), "rs.example.com");
+        assert_eq!(host("[::1]:21117"), "[::1]");
+        assert_eq!(host("wss://rs.example.com/ws/id"), "rs.example.com");
+        assert_eq!(
+            endpoint("Y", "rs.example.com:21116", ID_PATH),
+            Some("wss://rs.example.com/ws/id".to_owned())
+        );
+        assert_eq!(
+            endpoint("wss://gw.example.com/", "rs.example.com", ID_PATH),
+            Some("wss://gw.example.com/ws/id".to_owned())
+        );
+        assert_eq!(endpoint("", "rs.example.com", ID_PATH), None);
+        assert!(!is_enabled_by("N"));
+    }
+
+    #[test]
+    fn relay_follows_the_rendezvous_connection() {
+        let relay = "rs.example.com:21117".to_owned();
+        assert_eq!(
+            relay_endpoint_by("Y", "rs.example.com:21116", relay.clone()),
+            relay
+        );
+        assert_eq!(
+            relay_endpoint_by("Y", "wss://rs.example.com/ws/id", relay.clone()),
+            "wss://rs.example.com/ws/relay"
+        );
+        assert_eq!(
+            relay_endpoint_by("", "wss://gw.example.com/ws/id", relay),
+            "wss://gw.example.com/ws/relay"
+        );
+    }
+
+    #[test]
+    fn direct_endpoint_needs_the_host_of_the_peer() {
+        assert_eq!(
+            direct_endpoint_by("Y", "192.168.1.2:21118"),
+            Some("wss://192.168.1.2/ws/direct".to_owned())
+        );
+        assert_eq!(direct_endpoint_by("wss://gw.example.com", "192.168.1.2"), None);
+        assert_eq!(direct_endpoint_by("", "192.168.1.2"), None);
+    }
+
+    // A stand-in for hbbr behind the proxy: it gets the relay request of the client, then
+    // passes the frames of the other side, here echoing them.
+    #[tokio::test]
+    async fn relay_handshake_over_websocket() {
+        use hbb_common::{protobuf::Message as _, rendezvous_proto::*};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let relay_server = format!("ws://{}{}", listener.local_addr().unwrap(), RELAY_PATH);
+        let stand_in = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(socket).await.unwrap();
+            let Some(Ok(WsMessage::Binary(request))) = ws.next().await else {
+                panic!("no relay request");
+            };
+            let Some(Ok(WsMessage::Binary(data))) = ws.next().await else {
+                panic!("no data");
+            };
+            ws.send(WsMessage::Binary(data)).await.unwrap();
+            RendezvousMessage::parse_from_bytes(&request).unwrap()
+        });
+
+        let mut conn = crate::client::Client::create_relay(
+            "123456789",
+            "relay-uuid".to_owned(),
+            relay_server,
+            "key",
+            ConnType::DEFAULT_CONN,
+            true,
+        )
+        .await
+        .unwrap();
+        conn.send_bytes(hbb_common::bytes::Bytes::from_static(b"hello"))
+            .await
+            .unwrap();
+        let echoed = hbb_common::timeout(3000, conn.next()).await.unwrap();
+        assert_eq!(&echoed.unwrap().unwrap()[..], b"hello");
+
+        let request = stand_in.await.unwrap();
+        let Some(rendezvous_message::Union::RequestRelay(rr)) = request.union else {
+            panic!("not a relay request");
+        };
+        assert_eq!(rr.id, "123456789");
+        assert_eq!(rr.uuid, "relay-uuid");
+        assert_eq!(rr.licence_key, "key");
+    }
+
+    // The controlled side of a direct connection, with the client connecting like
+    // `connect_tcp()` does to a `ws://` peer address.
+    #[tokio::test]
+    async fn direct_connection_over_websocket() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}{}", listener.local_addr().unwrap(), DIRECT_PATH);
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut stream = accept(socket).await.unwrap();
+            let data = stream.next().await.unwrap().unwrap();
+            stream.send_bytes(data.freeze()).await.unwrap();
+        });
+        let (mut ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        ws.send(WsMessage::Binary(hbb_common::bytes::Bytes::from_static(b"hello")))
+            .await
+            .unwrap();
+        let Some(Ok(WsMessage::Binary(echoed))) = ws.next().await else {
+            panic!("no reply");
+        };
+        assert_eq!(&echoed[..], b"hello");
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn native_connection_is_retried_periodically() {
+        assert!(!should_fall_back(0));
+        assert!(should_fall_back(1));
+        assert!(should_fall_back(3));
+        assert!(!should_fall_back(NATIVE_RETRY_INTERVAL));
+    }
+}